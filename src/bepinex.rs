@@ -1,36 +1,47 @@
-use crate::types::ModIndex;
-use anyhow::Result;
+pub mod cfg;
+
+use crate::cache;
+use crate::game::GameInfo;
+use crate::hash;
+use crate::pe;
+use crate::types::{BepInExInstall, ModIndex, TrackedFile};
+use anyhow::{Result, anyhow};
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use zip::read::ZipArchive;
 
-pub fn index_path(game_dir: &PathBuf) -> PathBuf {
+/// Index location relative to the game dir.
+pub const INDEX_FILE: &str = "BepInEx/mod-manager.index.json";
+/// BepInEx.cfg location relative to the game dir.
+pub const CONFIG_FILE: &str = "BepInEx/config/BepInEx.cfg";
+
+pub fn index_path(game_dir: &Path) -> PathBuf {
     game_dir.join("BepInEx").join("mod-manager.index.json")
 }
 
-pub fn plugins_dir(game_dir: &PathBuf) -> PathBuf {
+pub fn plugins_dir(game_dir: &Path) -> PathBuf {
     game_dir.join("BepInEx").join("plugins")
 }
 
-pub fn bep_config_path(game_dir: &PathBuf) -> PathBuf {
+pub fn bep_config_path(game_dir: &Path) -> PathBuf {
     game_dir.join("BepInEx").join("config").join("BepInEx.cfg")
 }
 
-pub fn load_index(game_dir: &PathBuf) -> ModIndex {
+pub fn load_index(game_dir: &Path) -> ModIndex {
     let path = index_path(game_dir);
     if let Ok(mut f) = File::open(&path) {
         let mut buf = String::new();
-        if f.read_to_string(&mut buf).is_ok() {
-            if let Ok(idx) = serde_json::from_str::<ModIndex>(&buf) {
-                return idx;
-            }
+        if f.read_to_string(&mut buf).is_ok()
+            && let Ok(idx) = serde_json::from_str::<ModIndex>(&buf)
+        {
+            return idx;
         }
     }
     ModIndex::default()
 }
 
-pub fn save_index(game_dir: &PathBuf, mods: &ModIndex) -> Result<()> {
+pub fn save_index(game_dir: &Path, mods: &ModIndex) -> Result<()> {
     let path = index_path(game_dir);
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
@@ -41,21 +52,290 @@ pub fn save_index(game_dir: &PathBuf, mods: &ModIndex) -> Result<()> {
     Ok(())
 }
 
-pub fn is_bep_installed(game_dir: &PathBuf) -> bool {
-    let bep_core_dll = game_dir.join("BepInEx").join("core").join("BepInEx.dll");
-    bep_core_dll.exists()
+/// Whether a BepInEx core assembly is in place: `BepInEx.Core.dll` in 6.x
+/// builds, `BepInEx.dll` in 5.x.
+pub fn is_bep_installed(game_dir: &Path) -> bool {
+    core_assembly_exists(game_dir)
 }
 
-pub fn detect_bep_status(game_dir: &PathBuf) -> String {
-    let bep_core_dll = game_dir.join("BepInEx").join("core").join("BepInEx.dll");
-    if bep_core_dll.exists() {
-        "Installed".into()
+/// Log BepInEx writes on every launch, relative to the game dir.
+pub const LOG_FILE: &str = "BepInEx/LogOutput.log";
+
+/// Where BepInEx stands in the game folder, each state backed by files on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BepState {
+    NotInstalled,
+    /// Files are in place but the game has not been started with them.
+    Extracted,
+    /// The game was started but BepInEx has not finished its first launch
+    /// (IL2CPP interop assemblies not generated, chainloader not started).
+    AwaitingFirstLaunch,
+    Ready,
+    Broken(String),
+}
+
+impl BepState {
+    /// Whether BepInEx files are in place, so mods can be installed.
+    pub fn is_installed(&self) -> bool {
+        matches!(
+            self,
+            BepState::Extracted | BepState::AwaitingFirstLaunch | BepState::Ready
+        )
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            BepState::NotInstalled => "Not installed".into(),
+            BepState::Extracted => "Installed, not launched yet".into(),
+            BepState::AwaitingFirstLaunch => "Waiting for first launch to finish".into(),
+            BepState::Ready => "Ready".into(),
+            BepState::Broken(reason) => format!("Broken: {reason}"),
+        }
+    }
+
+    /// What the user should do next.
+    pub fn next_step(&self) -> &'static str {
+        match self {
+            BepState::NotInstalled => "Install BepInEx with one of the buttons below.",
+            BepState::Extracted => {
+                "Start Restaurats once. The first launch with BepInEx takes a while because it generates interop assemblies."
+            }
+            BepState::AwaitingFirstLaunch => {
+                "Let the game finish loading once. If it closed early, start it again."
+            }
+            BepState::Ready => "Drop mods on the Mods tab and start the game.",
+            BepState::Broken(_) => "Use Repair BepInEx, or reinstall it.",
+        }
+    }
+}
+
+fn core_assembly_exists(game_dir: &Path) -> bool {
+    let core = game_dir.join("BepInEx").join("core");
+    core.join("BepInEx.Core.dll").exists() || core.join("BepInEx.dll").exists()
+}
+
+fn dir_has_files(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|mut d| d.next().is_some())
+}
+
+pub fn detect_state(game_dir: &Path) -> BepState {
+    let core = core_assembly_exists(game_dir);
+    let proxy = game_dir.join("winhttp.dll").exists();
+    match (core, proxy) {
+        (false, false) => return BepState::NotInstalled,
+        (true, false) => return BepState::Broken("winhttp.dll is missing".into()),
+        (false, true) => return BepState::Broken("BepInEx core files are missing".into()),
+        (true, true) => {}
+    }
+    if let Some(install) = load_index(game_dir).bepinex {
+        let damaged = quick_check(game_dir, &install);
+        if !damaged.is_empty() {
+            return BepState::Broken(format!(
+                "{} BepInEx files missing or changed",
+                damaged.len()
+            ));
+        }
+    }
+    let Ok(log) = fs::read(game_dir.join(LOG_FILE)) else {
+        return BepState::Extracted;
+    };
+    let il2cpp = game_dir
+        .join("BepInEx/core/BepInEx.Unity.IL2CPP.dll")
+        .exists()
+        || game_dir.join("dotnet").is_dir();
+    let interop_ready = !il2cpp
+        || (dir_has_files(&game_dir.join("BepInEx/interop"))
+            && dir_has_files(&game_dir.join("BepInEx/unity-libs")));
+    let chainloader = String::from_utf8_lossy(&log).contains("Chainloader started");
+    if interop_ready && chainloader {
+        BepState::Ready
     } else {
-        "Not installed".into()
+        BepState::AwaitingFirstLaunch
     }
 }
 
-pub fn validate_bepinex_installation(game_dir: &PathBuf) -> Result<()> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Il2Cpp,
+    Mono,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X64,
+    X86,
+}
+
+/// Version and flavor of a BepInEx build, either installed or in an archive.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BepInExInfo {
+    /// e.g. `6.0.0-be.752` or `5.4.23.2`, without the commit hash.
+    pub version: Option<String>,
+    /// Bleeding Edge build number.
+    pub build: Option<u32>,
+    pub runtime: Option<Runtime>,
+    pub arch: Option<Arch>,
+    /// Contents of `.doorstop_version`.
+    pub doorstop: Option<String>,
+}
+
+impl BepInExInfo {
+    /// Whether this build can be installed into `game`, as far as it is known.
+    pub fn fits(&self, game: &GameInfo) -> bool {
+        fn matches<T: PartialEq>(want: Option<T>, have: Option<T>) -> bool {
+            want.is_none() || have.is_none() || want == have
+        }
+        matches(game.runtime, self.runtime) && matches(game.arch, self.arch)
+    }
+
+    fn with_version(mut self, version: &str) -> Self {
+        let version = version.split('+').next().unwrap_or(version).trim();
+        self.build = version.split_once("-be.").and_then(|(_, b)| b.parse().ok());
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Read a release archive name such as
+    /// `BepInEx-Unity.IL2CPP-win-x64-6.0.0-be.752+dd0655f.zip` or
+    /// `BepInEx_win_x86_5.4.23.2.zip`. URL-encoded names are accepted.
+    pub fn from_archive_name(name: &str) -> Self {
+        let name = name.rsplit('/').next().unwrap_or(name).replace("%2B", "+");
+        let stem = name.strip_suffix(".zip").unwrap_or(&name);
+        let lower = stem.to_ascii_lowercase();
+        let version_at = stem.char_indices().find_map(|(i, c)| {
+            let rest = &stem[i + c.len_utf8()..];
+            ((c == '-' || c == '_')
+                && rest.starts_with(|d: char| d.is_ascii_digit())
+                && rest.split(['-', '+']).next().unwrap_or("").contains('.'))
+            .then_some(i + 1)
+        });
+        let mut info = match version_at {
+            Some(at) => Self::default().with_version(&stem[at..]),
+            None => Self::default(),
+        };
+        info.runtime = if lower.contains("il2cpp") {
+            Some(Runtime::Il2Cpp)
+        } else if lower.contains("mono")
+            || info.version.as_deref().is_some_and(|v| v.starts_with('5'))
+        {
+            Some(Runtime::Mono)
+        } else {
+            None
+        };
+        info.arch = if lower.contains("x64") {
+            Some(Arch::X64)
+        } else if lower.contains("x86") {
+            Some(Arch::X86)
+        } else {
+            None
+        };
+        info
+    }
+
+    /// Whether `other` is the same build, as far as both are known.
+    pub fn same_build(&self, other: &BepInExInfo) -> bool {
+        match (self.build, other.build) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.version == other.version,
+        }
+    }
+}
+
+impl std::fmt::Display for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Runtime::Il2Cpp => "IL2CPP",
+            Runtime::Mono => "Mono",
+        })
+    }
+}
+
+impl std::fmt::Display for Arch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Arch::X64 => "x64",
+            Arch::X86 => "x86",
+        })
+    }
+}
+
+impl std::fmt::Display for BepInExInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "BepInEx {}",
+            self.version.as_deref().unwrap_or("(unknown version)")
+        )?;
+        let mut flavor = Vec::new();
+        if let Some(runtime) = self.runtime {
+            flavor.push(runtime.to_string());
+        }
+        if let Some(arch) = self.arch {
+            flavor.push(arch.to_string());
+        }
+        if let Some(build) = self.build {
+            flavor.push(format!("build {build}"));
+        }
+        if !flavor.is_empty() {
+            write!(f, " ({})", flavor.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn assembly_version(dll: &[u8]) -> Option<String> {
+    pe::version_string(dll, "ProductVersion")
+        .or_else(|| pe::version_string(dll, "FileVersion"))
+        .or_else(|| pe::file_version(dll).map(|v| format!("{}.{}.{}.{}", v[0], v[1], v[2], v[3])))
+}
+
+fn arch_of(dll: &[u8]) -> Option<Arch> {
+    match pe::machine(dll)? {
+        pe::MACHINE_AMD64 => Some(Arch::X64),
+        pe::MACHINE_I386 => Some(Arch::X86),
+        _ => None,
+    }
+}
+
+/// Version and flavor of the BepInEx installed in `game_dir`, from the core
+/// assembly's version info, `changelog.txt` and `.doorstop_version`.
+pub fn detect_bepinex(game_dir: &Path) -> Option<BepInExInfo> {
+    let core = game_dir.join("BepInEx").join("core");
+    let assembly = ["BepInEx.Core.dll", "BepInEx.dll"]
+        .iter()
+        .map(|n| core.join(n))
+        .find(|p| p.exists())?;
+    let bytes = fs::read(&assembly).unwrap_or_default();
+    let version = assembly_version(&bytes).or_else(|| {
+        // First line reads e.g. `v6.0.0-pre.2 Changelog`.
+        let changelog = fs::read_to_string(game_dir.join("changelog.txt")).ok()?;
+        let first = changelog.lines().next()?.split_whitespace().next()?;
+        Some(first.trim_start_matches('v').to_string())
+    });
+    let mut info = match version {
+        Some(v) => BepInExInfo::default().with_version(&v),
+        None => BepInExInfo::default(),
+    };
+    info.runtime = if core.join("BepInEx.Unity.IL2CPP.dll").exists()
+        || game_dir.join("dotnet").is_dir()
+    {
+        Some(Runtime::Il2Cpp)
+    } else if core.join("BepInEx.Unity.Mono.dll").exists() || core.join("BepInEx.dll").exists() {
+        Some(Runtime::Mono)
+    } else {
+        None
+    };
+    info.arch = fs::read(game_dir.join("winhttp.dll"))
+        .ok()
+        .and_then(|b| arch_of(&b));
+    info.doorstop = fs::read_to_string(game_dir.join(".doorstop_version"))
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    Some(info)
+}
+
+pub fn validate_bepinex_installation(game_dir: &Path) -> Result<()> {
     let bep_core_dll = game_dir.join("BepInEx").join("core").join("BepInEx.dll");
     let bep_core_xml = game_dir
         .join("BepInEx")
@@ -78,49 +358,170 @@ pub fn validate_bepinex_installation(game_dir: &PathBuf) -> Result<()> {
     Ok(())
 }
 
-pub fn ensure_dirs(game_dir: &PathBuf) -> Result<()> {
+pub fn ensure_dirs(game_dir: &Path) -> Result<()> {
     fs::create_dir_all(plugins_dir(game_dir))?;
     Ok(())
 }
 
-pub fn set_unity_log_listening_false(game_dir: &PathBuf) -> Result<()> {
-    let cfg_path = bep_config_path(game_dir);
-    if !cfg_path.exists() {
-        if let Some(parent) = cfg_path.parent() {
-            fs::create_dir_all(parent)?;
+/// Where the entry `f` extracts to inside `game_dir`, refusing names such
+/// as `../x` or `/x` that would land outside it.
+fn entry_path<R: io::Read>(game_dir: &Path, f: &zip::read::ZipFile<'_, R>) -> Result<PathBuf> {
+    let rel = f
+        .enclosed_name()
+        .ok_or_else(|| anyhow!("The BepInEx archive has an unsafe path: {}", f.name()))?;
+    Ok(game_dir.join(rel))
+}
+
+/// Relative paths an archive will write into the game directory, split into
+/// files and directory entries. Archives with entries outside it are refused.
+pub fn archive_paths(bytes: &[u8]) -> Result<(Vec<String>, Vec<String>)> {
+    let mut zip = ZipArchive::new(io::Cursor::new(bytes))?;
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for i in 0..zip.len() {
+        let f = zip.by_index(i)?;
+        let name = f.name();
+        if name.is_empty() {
+            continue;
+        }
+        entry_path(Path::new(""), &f)?;
+        if name.ends_with('/') {
+            dirs.push(name.trim_end_matches('/').to_string());
+        } else {
+            files.push(name.to_string());
         }
-        let mut f = File::create(&cfg_path)?;
-        f.write_all(b"[Logging]\nUnityLogListening = false\n")?;
-        return Ok(());
-    }
-    let mut content = String::new();
-    File::open(&cfg_path)?.read_to_string(&mut content)?;
-    let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-    let mut in_logging = false;
-    let mut updated = false;
-    for i in 0..lines.len() {
-        let l = lines[i].trim();
-        if l.starts_with('[') && l.ends_with(']') {
-            in_logging = l.eq_ignore_ascii_case("[logging]");
-        } else if in_logging && l.to_lowercase().starts_with("unityloglistening") {
-            lines[i] = "UnityLogListening = false".to_string();
-            updated = true;
-            break;
+    }
+    Ok((files, dirs))
+}
+
+/// Version and flavor of a BepInEx release archive, read without extracting it.
+pub fn inspect_archive(bytes: &[u8]) -> Result<BepInExInfo> {
+    let mut zip = ZipArchive::new(io::Cursor::new(bytes))?;
+    let names: Vec<String> = zip.file_names().map(|n| n.replace('\\', "/")).collect();
+    let find = |want: &str| names.iter().position(|n| n.eq_ignore_ascii_case(want));
+    let mut read = |want: &str| -> Option<Vec<u8>> {
+        let mut f = zip.by_name(&names[find(want)?]).ok()?;
+        let mut buf = Vec::new();
+        f.read_to_end(&mut buf).ok()?;
+        Some(buf)
+    };
+    let Some(core) = ["BepInEx/core/BepInEx.Core.dll", "BepInEx/core/BepInEx.dll"]
+        .into_iter()
+        .find(|c| find(c).is_some())
+    else {
+        if names
+            .iter()
+            .any(|n| n.to_ascii_lowercase().starts_with("melonloader"))
+        {
+            return Err(anyhow!(
+                "This is a MelonLoader archive, not BepInEx; the manager only installs BepInEx"
+            ));
         }
+        return Err(anyhow!(
+            "This is not a BepInEx release: it has no BepInEx/core/BepInEx.Core.dll (a mod archive belongs on the Mods tab)"
+        ));
+    };
+    let version = read(core)
+        .and_then(|dll| assembly_version(&dll))
+        .or_else(|| {
+            let changelog = String::from_utf8(read("changelog.txt")?).ok()?;
+            let first = changelog.lines().next()?.split_whitespace().next()?;
+            Some(first.trim_start_matches('v').to_string())
+        });
+    let mut info = match version {
+        Some(v) => BepInExInfo::default().with_version(&v),
+        None => BepInExInfo::default(),
+    };
+    info.runtime = if find("BepInEx/core/BepInEx.Unity.IL2CPP.dll").is_some()
+        || names.iter().any(|n| n.starts_with("dotnet/"))
+    {
+        Some(Runtime::Il2Cpp)
+    } else {
+        Some(Runtime::Mono)
+    };
+    info.arch = read("winhttp.dll").and_then(|dll| arch_of(&dll));
+    info.doorstop = read(".doorstop_version")
+        .and_then(|b| String::from_utf8(b).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    Ok(info)
+}
+
+/// Check that `bytes` is a complete BepInEx build matching the game's
+/// scripting backend and architecture, before anything is written.
+pub fn verify_archive(game_dir: &Path, bytes: &[u8]) -> Result<BepInExInfo> {
+    let info = inspect_archive(bytes)?;
+    let (files, _) = archive_paths(bytes)?;
+    if !files.iter().any(|f| f.eq_ignore_ascii_case("winhttp.dll")) {
+        return Err(anyhow!(
+            "{info} has no winhttp.dll in its root, so the game would never load it"
+        ));
+    }
+    if info.runtime == Some(Runtime::Il2Cpp) && !files.iter().any(|f| f.starts_with("dotnet/")) {
+        return Err(anyhow!(
+            "{info} is missing the dotnet/ runtime IL2CPP builds need"
+        ));
+    }
+    let game = GameInfo::detect(game_dir);
+    if let (Some(game), Some(build)) = (game.runtime, info.runtime)
+        && game != build
+    {
+        return Err(anyhow!(
+            "{info} is a {build} build, but the game uses {game}; use a BepInEx-Unity.{game} build"
+        ));
     }
-    if !updated {
-        if !content.to_lowercase().contains("[logging]") {
-            lines.push("".into());
-            lines.push("[Logging]".into());
+    if let (Some(game), Some(build)) = (game.arch, info.arch)
+        && game != build
+    {
+        return Err(anyhow!(
+            "{info} is a {build} build, but the game is {game}; its winhttp.dll would not load"
+        ));
+    }
+    Ok(info)
+}
+
+/// Folders an upgrade replaces wholesale, so files dropped by the new build
+/// do not linger.
+pub const UPGRADE_REPLACED_DIRS: [&str; 2] = ["BepInEx/core", "dotnet"];
+/// Folders whose existing files an upgrade never overwrites.
+const USER_DIRS: [&str; 3] = ["BepInEx/config/", "BepInEx/plugins/", "BepInEx/patchers/"];
+
+pub fn install_bepinex_from_zip_bytes(game_dir: &Path, bytes: &[u8]) -> Result<()> {
+    verify_archive(game_dir, bytes)?;
+    extract_bepinex(game_dir, bytes, false)
+}
+
+/// Files an upgrade will delete before extracting, relative to the game dir.
+pub fn upgrade_replaced_files(game_dir: &Path) -> Vec<String> {
+    UPGRADE_REPLACED_DIRS
+        .iter()
+        .flat_map(|d| walkdir::WalkDir::new(game_dir.join(d)))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(game_dir)
+                .ok()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        })
+        .collect()
+}
+
+/// Install a different BepInEx build over an existing one, keeping plugins,
+/// configs and the mod index.
+pub fn upgrade_bepinex_from_zip_bytes(game_dir: &Path, bytes: &[u8]) -> Result<()> {
+    // Make sure the archive is usable before removing anything.
+    verify_archive(game_dir, bytes)?;
+    for dir in UPGRADE_REPLACED_DIRS {
+        let path = game_dir.join(dir);
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
         }
-        lines.push("UnityLogListening = false".into());
     }
-    let mut f = File::create(&cfg_path)?;
-    f.write_all(lines.join("\n").as_bytes())?;
-    Ok(())
+    extract_bepinex(game_dir, bytes, true)
 }
 
-pub fn install_bepinex_from_zip_bytes(game_dir: &PathBuf, bytes: &[u8]) -> Result<()> {
+fn extract_bepinex(game_dir: &Path, bytes: &[u8], keep_user_files: bool) -> Result<()> {
     let reader = io::Cursor::new(bytes);
     let mut zip = ZipArchive::new(reader)?;
 
@@ -133,12 +534,18 @@ pub fn install_bepinex_from_zip_bytes(game_dir: &PathBuf, bytes: &[u8]) -> Resul
             continue;
         }
 
-        let outpath = game_dir.join(file_path);
+        let outpath = entry_path(game_dir, &f)?;
 
         if file_path.ends_with('/') {
             // Directory entry
             fs::create_dir_all(&outpath)?;
         } else {
+            if keep_user_files
+                && outpath.exists()
+                && USER_DIRS.iter().any(|d| file_path.starts_with(d))
+            {
+                continue;
+            }
             // File entry - create parent directories if needed
             if let Some(parent) = outpath.parent() {
                 fs::create_dir_all(parent)?;
@@ -148,7 +555,173 @@ pub fn install_bepinex_from_zip_bytes(game_dir: &PathBuf, bytes: &[u8]) -> Resul
         }
     }
 
-    let _ = set_unity_log_listening_false(game_dir);
+    Ok(())
+}
+
+/// Record the files `bytes` just installed in the mod index and keep the
+/// archive in the download cache at `cache_root`.
+pub fn record_install(game_dir: &Path, bytes: &[u8], cache_root: &Path) -> Result<()> {
+    let (files, dirs) = archive_paths(bytes)?;
+    let version = detect_bepinex(game_dir).and_then(|i| i.version);
+    let origin = cache::Origin::new(cache::Kind::BepInEx, "BepInEx", version.clone());
+    let sha256 = cache::put(cache_root, bytes, origin)?;
+    let mut tracked = Vec::new();
+    for rel in files {
+        let path = game_dir.join(&rel);
+        if path.is_file() {
+            tracked.push(TrackedFile {
+                sha256: hash::sha256_file(&path)?,
+                size: Some(fs::metadata(&path)?.len()),
+                path: rel,
+            });
+        }
+    }
+    let mut index = load_index(game_dir);
+    index.bepinex = Some(BepInExInstall {
+        version,
+        archive_sha256: sha256,
+        files: tracked,
+        dirs,
+    });
+    save_index(game_dir, &index)
+}
+
+/// BepInEx files users are expected to edit.
+const USER_FILES: [&str; 1] = ["doorstop_config.ini"];
+
+fn in_user_dir(rel: &str) -> bool {
+    USER_DIRS.iter().any(|d| rel.starts_with(d))
+}
+
+/// Whether a tracked file is the user's business rather than BepInEx's: the
+/// plugin and config folders, and settings files like the doorstop config.
+fn user_owned(rel: &str) -> bool {
+    in_user_dir(rel) || USER_FILES.contains(&rel)
+}
+
+/// Tracked BepInEx files that are missing or have another size than when
+/// they were installed. Cheap enough for every state refresh; only
+/// [`repair_plan`] reads the files.
+fn quick_check(game_dir: &Path, install: &BepInExInstall) -> Vec<String> {
+    install
+        .files
+        .iter()
+        .filter(|f| !user_owned(&f.path))
+        .filter(|f| match fs::metadata(game_dir.join(&f.path)) {
+            Ok(meta) => f.size.is_some_and(|size| size != meta.len()),
+            Err(_) => true,
+        })
+        .map(|f| f.path.clone())
+        .collect()
+}
+
+/// Tracked BepInEx files that are missing or differ from what was installed.
+pub fn repair_plan(game_dir: &Path, install: &BepInExInstall) -> Vec<String> {
+    install
+        .files
+        .iter()
+        .filter(|f| !user_owned(&f.path))
+        .filter(|f| {
+            hash::sha256_file(&game_dir.join(&f.path))
+                .map(|h| h != f.sha256)
+                .unwrap_or(true)
+        })
+        .map(|f| f.path.clone())
+        .collect()
+}
+
+/// Re-extract `files` from the cached archive of `install`.
+pub fn repair(
+    game_dir: &Path,
+    cache_root: &Path,
+    install: &BepInExInstall,
+    files: &[String],
+) -> Result<()> {
+    let cached = cache::get(cache_root, &install.archive_sha256).ok_or_else(|| {
+        anyhow!("The BepInEx archive is no longer cached; reinstall BepInEx instead")
+    })?;
+    let bytes = fs::read(&cached)?;
+    if hash::sha256_bytes(&bytes) != install.archive_sha256 {
+        return Err(anyhow!(
+            "Cached BepInEx archive {} is corrupt",
+            cached.display()
+        ));
+    }
+    let mut zip = ZipArchive::new(io::Cursor::new(bytes))?;
+    for rel in files {
+        let mut f = zip.by_name(rel)?;
+        let out = entry_path(game_dir, &f)?;
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut f, &mut File::create(&out)?)?;
+    }
+    Ok(())
+}
+
+/// Files and folders uninstalling BepInEx removes, deepest folders last.
+/// Unless `keep_user_files`, everything in the plugin, patcher and config
+/// folders goes too.
+pub fn uninstall_plan(
+    game_dir: &Path,
+    install: &BepInExInstall,
+    keep_user_files: bool,
+) -> (Vec<String>, Vec<String>) {
+    let mut files: Vec<String> = install
+        .files
+        .iter()
+        .filter(|f| !(keep_user_files && in_user_dir(&f.path)))
+        .filter(|f| game_dir.join(&f.path).is_file())
+        .map(|f| f.path.clone())
+        .collect();
+    let mut dirs = install.dirs.clone();
+    if !keep_user_files {
+        for dir in USER_DIRS {
+            let dir = dir.trim_end_matches('/');
+            for e in walkdir::WalkDir::new(game_dir.join(dir))
+                .into_iter()
+                .flatten()
+            {
+                let Ok(rel) = e.path().strip_prefix(game_dir) else {
+                    continue;
+                };
+                let rel = rel.to_string_lossy().replace('\\', "/");
+                if e.file_type().is_file() {
+                    if !files.contains(&rel) {
+                        files.push(rel);
+                    }
+                } else if !dirs.contains(&rel) {
+                    dirs.push(rel);
+                }
+            }
+        }
+        files.push(INDEX_FILE.to_string());
+        if game_dir.join(crate::configs::LINKS_FILE).exists() {
+            files.push(crate::configs::LINKS_FILE.to_string());
+        }
+    }
+    dirs.sort_by_key(|d| std::cmp::Reverse(d.matches('/').count()));
+    (files, dirs)
+}
+
+/// Remove what [`uninstall_plan`] listed. Folders are only removed once empty.
+/// The index forgets BepInEx but keeps its mods when they stay.
+pub fn uninstall(game_dir: &Path, files: &[String], dirs: &[String]) -> Result<()> {
+    let keep_index = !files.iter().any(|f| f == INDEX_FILE);
+    if keep_index {
+        let mut index = load_index(game_dir);
+        index.bepinex = None;
+        save_index(game_dir, &index)?;
+    }
+    for rel in files {
+        let path = game_dir.join(rel);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    for rel in dirs {
+        let _ = fs::remove_dir(game_dir.join(rel));
+    }
     Ok(())
 }
 
@@ -242,7 +815,19 @@ mod tests {
 
         // Check BepInEx installation detection
         assert!(is_bep_installed(&game_dir));
-        assert_eq!(detect_bep_status(&game_dir), "Installed");
+        assert_eq!(detect_state(&game_dir), BepState::Extracted);
+
+        // Entries that would land outside the game folder are refused.
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let options: zip::write::FileOptions<()> = zip::write::FileOptions::default();
+        zw.start_file("winhttp.dll", options).unwrap();
+        zw.start_file("../escape.dll", options).unwrap();
+        let evil = zw.finish().unwrap().into_inner();
+        let nested = game_dir.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        assert!(archive_paths(&evil).is_err());
+        assert!(extract_bepinex(&nested, &evil, false).is_err());
+        assert!(!game_dir.join("escape.dll").exists());
     }
 
     #[test]
@@ -251,7 +836,7 @@ mod tests {
         let game_dir = PathBuf::from(temp_dir.path());
 
         assert!(!is_bep_installed(&game_dir));
-        assert_eq!(detect_bep_status(&game_dir), "Not installed");
+        assert_eq!(detect_state(&game_dir), BepState::NotInstalled);
     }
 
     #[test]
@@ -266,6 +851,9 @@ mod tests {
             version: Some("1.0.0".to_string()),
             source_zip: None,
             installed_files: vec!["BepInEx/plugins/test.dll".to_string()],
+            source: None,
+            archive_sha256: None,
+            disabled: false,
         });
 
         save_index(&game_dir, &index).expect("Failed to save index");
@@ -275,4 +863,178 @@ mod tests {
         assert_eq!(loaded.mods[0].id, "test_mod");
         assert_eq!(loaded.mods[0].name, "Test Mod");
     }
+
+    #[test]
+    fn test_archive_name_and_upgrade() {
+        let info = BepInExInfo::from_archive_name(
+            "https://builds.bepinex.dev/projects/bepinex_be/752/BepInEx-Unity.IL2CPP-win-x64-6.0.0-be.752%2Bdd0655f.zip",
+        );
+        assert_eq!(info.version.as_deref(), Some("6.0.0-be.752"));
+        assert_eq!(info.build, Some(752));
+        assert_eq!(info.runtime, Some(Runtime::Il2Cpp));
+        assert_eq!(info.arch, Some(Arch::X64));
+        let v5 = BepInExInfo::from_archive_name("BepInEx_win_x86_5.4.23.2.zip");
+        assert_eq!(v5.version.as_deref(), Some("5.4.23.2"));
+        assert_eq!(
+            (v5.runtime, v5.arch),
+            (Some(Runtime::Mono), Some(Arch::X86))
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = temp_dir.path();
+        install_bepinex_from_zip_bytes(game_dir, &create_test_zip()).unwrap();
+        let installed = detect_bepinex(game_dir).unwrap();
+        assert_eq!(installed.version.as_deref(), Some("6.0.0-pre.2"));
+        assert_eq!(installed.doorstop.as_deref(), Some("1.0.0"));
+
+        fs::write(game_dir.join("BepInEx/core/Old.dll"), b"old").unwrap();
+        fs::write(game_dir.join("BepInEx/plugins/Mine.dll"), b"mod").unwrap();
+        save_index(game_dir, &ModIndex::default()).unwrap();
+        upgrade_bepinex_from_zip_bytes(game_dir, &create_test_zip()).unwrap();
+        assert!(!game_dir.join("BepInEx/core/Old.dll").exists());
+        assert!(game_dir.join("BepInEx/core/BepInEx.dll").exists());
+        assert!(game_dir.join("BepInEx/plugins/Mine.dll").exists());
+        assert!(index_path(game_dir).exists());
+    }
+
+    #[test]
+    fn test_tracked_install_repair_and_uninstall() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let game_dir = temp_dir.path();
+        let bytes = create_test_zip();
+        install_bepinex_from_zip_bytes(game_dir, &bytes).unwrap();
+        record_install(game_dir, &bytes, cache.path()).unwrap();
+        let install = load_index(game_dir).bepinex.unwrap();
+        assert_eq!(install.files.len(), 7);
+        assert!(repair_plan(game_dir, &install).is_empty());
+
+        fs::remove_file(game_dir.join("winhttp.dll")).unwrap();
+        fs::write(game_dir.join("BepInEx/core/BepInEx.dll"), b"broken").unwrap();
+        let broken = repair_plan(game_dir, &install);
+        assert_eq!(broken, ["winhttp.dll", "BepInEx/core/BepInEx.dll"]);
+        assert_eq!(quick_check(game_dir, &install), broken);
+        repair(game_dir, cache.path(), &install, &broken).unwrap();
+        assert!(repair_plan(game_dir, &install).is_empty());
+        // Users tune the doorstop config; that is not damage.
+        fs::write(
+            game_dir.join("doorstop_config.ini"),
+            b"[General]\nenabled=false\n",
+        )
+        .unwrap();
+        assert!(repair_plan(game_dir, &install).is_empty());
+        assert!(quick_check(game_dir, &install).is_empty());
+
+        fs::write(game_dir.join("BepInEx/plugins/Mine.dll"), b"mod").unwrap();
+        fs::write(game_dir.join("Restaurats.exe"), b"game").unwrap();
+        let (files, dirs) = uninstall_plan(game_dir, &install, true);
+        uninstall(game_dir, &files, &dirs).unwrap();
+        assert!(!game_dir.join("winhttp.dll").exists());
+        assert!(!game_dir.join("dotnet").exists());
+        assert!(game_dir.join("BepInEx/plugins/Mine.dll").exists());
+        assert!(load_index(game_dir).bepinex.is_none());
+
+        let (files, dirs) = uninstall_plan(game_dir, &install, false);
+        uninstall(game_dir, &files, &dirs).unwrap();
+        assert!(!game_dir.join("BepInEx").exists());
+        assert!(game_dir.join("Restaurats.exe").exists());
+    }
+
+    #[test]
+    fn test_state_follows_first_launch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = temp_dir.path();
+        install_bepinex_from_zip_bytes(game_dir, &create_test_zip()).unwrap();
+        assert_eq!(detect_state(game_dir), BepState::Extracted);
+
+        fs::write(
+            game_dir.join(LOG_FILE),
+            "[Message:   BepInEx] BepInEx 6.0.0\n",
+        )
+        .unwrap();
+        assert_eq!(detect_state(game_dir), BepState::AwaitingFirstLaunch);
+
+        for dir in ["BepInEx/interop", "BepInEx/unity-libs"] {
+            fs::create_dir_all(game_dir.join(dir)).unwrap();
+            fs::write(game_dir.join(dir).join("UnityEngine.dll"), b"x").unwrap();
+        }
+        fs::write(
+            game_dir.join(LOG_FILE),
+            "[Message:   BepInEx] Chainloader started\n",
+        )
+        .unwrap();
+        assert_eq!(detect_state(game_dir), BepState::Ready);
+
+        fs::remove_file(game_dir.join("winhttp.dll")).unwrap();
+        assert!(matches!(detect_state(game_dir), BepState::Broken(_)));
+    }
+
+    fn zip_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zw.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zw.write_all(data).unwrap();
+        }
+        zw.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_verify_archive_against_game() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = temp_dir.path();
+        fs::create_dir_all(game_dir.join("Restaurats_Data")).unwrap();
+        fs::write(
+            game_dir.join("Restaurats.exe"),
+            pe::test_image(pe::MACHINE_AMD64),
+        )
+        .unwrap();
+        fs::write(game_dir.join("GameAssembly.dll"), b"il2cpp").unwrap();
+        let x64 = pe::test_image(pe::MACHINE_AMD64);
+        let x86 = pe::test_image(pe::MACHINE_I386);
+
+        let il2cpp = zip_of(&[
+            ("winhttp.dll", &x64),
+            ("BepInEx/core/BepInEx.Core.dll", b"core"),
+            ("BepInEx/core/BepInEx.Unity.IL2CPP.dll", b"il2cpp"),
+            ("dotnet/coreclr.dll", b"clr"),
+        ]);
+        let info = verify_archive(game_dir, &il2cpp).unwrap();
+        assert_eq!(info.runtime, Some(Runtime::Il2Cpp));
+        assert_eq!(info.arch, Some(Arch::X64));
+        // IL2CPP builds ship BepInEx.Core.dll only; that still counts as
+        // installed, so upgrades and imports snapshot first.
+        let other = tempfile::tempdir().unwrap();
+        install_bepinex_from_zip_bytes(other.path(), &il2cpp).unwrap();
+        assert!(!other.path().join("BepInEx/core/BepInEx.dll").exists());
+        assert!(is_bep_installed(other.path()));
+
+        let mono = zip_of(&[("winhttp.dll", &x64), ("BepInEx/core/BepInEx.dll", b"core")]);
+        let err = install_bepinex_from_zip_bytes(game_dir, &mono).unwrap_err();
+        assert!(err.to_string().contains("Mono build"), "{err}");
+        let wrong_arch = zip_of(&[
+            ("winhttp.dll", &x86),
+            ("BepInEx/core/BepInEx.Unity.IL2CPP.dll", b"il2cpp"),
+            ("BepInEx/core/BepInEx.Core.dll", b"core"),
+            ("dotnet/coreclr.dll", b"clr"),
+        ]);
+        assert!(verify_archive(game_dir, &wrong_arch).is_err());
+        let melon = zip_of(&[
+            ("MelonLoader/MelonLoader.dll", b"ml"),
+            ("version.dll", b"v"),
+        ]);
+        assert!(
+            inspect_archive(&melon)
+                .unwrap_err()
+                .to_string()
+                .contains("MelonLoader")
+        );
+        let no_dotnet = zip_of(&[
+            ("winhttp.dll", &x64),
+            ("BepInEx/core/BepInEx.Unity.IL2CPP.dll", b"il2cpp"),
+            ("BepInEx/core/BepInEx.Core.dll", b"core"),
+        ]);
+        assert!(verify_archive(game_dir, &no_dotnet).is_err());
+        assert!(!game_dir.join("winhttp.dll").exists());
+    }
 }
@@ -0,0 +1,388 @@
+//! Reader/writer for the BepInEx `.cfg` format.
+//!
+//! A document keeps every line of the original file. Only the value span of an
+//! edited setting is rewritten, so saving an unchanged document reproduces the
+//! file byte-for-byte and comments, spacing and line endings survive edits.
+
+use anyhow::Result;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// One `key = value` setting with the metadata comments BepInEx writes above it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+    /// `## ` lines, joined with newlines.
+    pub description: String,
+    /// `# Setting type:`
+    pub setting_type: Option<String>,
+    /// `# Default value:`
+    pub default: Option<String>,
+    /// `# Acceptable values:`
+    pub acceptable: Vec<String>,
+    /// `# Acceptable value range: From <min> to <max>`
+    pub range: Option<(String, String)>,
+    /// Set for flag enums, which take several comma-separated values.
+    pub multiple: bool,
+    line: usize,
+}
+
+/// How a setting's value should be edited, derived from its comments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueKind {
+    Bool,
+    Enum(Vec<String>),
+    Flags(Vec<String>),
+    Integer { min: Option<i64>, max: Option<i64> },
+    Float { min: Option<f64>, max: Option<f64> },
+    Text,
+}
+
+impl Entry {
+    pub fn kind(&self) -> ValueKind {
+        let min = self.range.as_ref().map(|(lo, _)| lo.as_str());
+        let max = self.range.as_ref().map(|(_, hi)| hi.as_str());
+        match self.setting_type.as_deref().unwrap_or("") {
+            "Boolean" => ValueKind::Bool,
+            "Byte" | "SByte" | "Int16" | "UInt16" | "Int32" | "UInt32" | "Int64" | "UInt64" => {
+                ValueKind::Integer {
+                    min: min.and_then(|v| v.parse().ok()),
+                    max: max.and_then(|v| v.parse().ok()),
+                }
+            }
+            "Single" | "Double" | "Decimal" => ValueKind::Float {
+                min: min.and_then(|v| v.parse().ok()),
+                max: max.and_then(|v| v.parse().ok()),
+            },
+            _ if self.acceptable.is_empty() => ValueKind::Text,
+            _ if self.multiple => ValueKind::Flags(self.acceptable.clone()),
+            _ => ValueKind::Enum(self.acceptable.clone()),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.default.as_deref().is_none_or(|d| d == self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    text: String,
+    eol: &'static str,
+    /// Byte range of the value inside `text` for setting lines.
+    value: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CfgDocument {
+    lines: Vec<Line>,
+    entries: Vec<Entry>,
+    /// From the `## Settings file was created by plugin <name> v<version>` header.
+    plugin_name: Option<String>,
+    /// From the `## Plugin GUID: <guid>` header.
+    plugin_guid: Option<String>,
+}
+
+impl CfgDocument {
+    pub fn parse(content: &str) -> Self {
+        let mut doc = CfgDocument::default();
+        let mut section = String::new();
+        let mut pending = Entry {
+            section: String::new(),
+            key: String::new(),
+            value: String::new(),
+            description: String::new(),
+            setting_type: None,
+            default: None,
+            acceptable: Vec::new(),
+            range: None,
+            multiple: false,
+            line: 0,
+        };
+        let blank = pending.clone();
+
+        for raw in content.split_inclusive('\n') {
+            let (text, eol) = if let Some(t) = raw.strip_suffix("\r\n") {
+                (t, "\r\n")
+            } else if let Some(t) = raw.strip_suffix('\n') {
+                (t, "\n")
+            } else {
+                (raw, "")
+            };
+            let mut line = Line {
+                text: text.to_string(),
+                eol,
+                value: None,
+            };
+            let trimmed = text.trim();
+            if trimmed.is_empty() {
+                pending = blank.clone();
+            } else if let Some(desc) = trimmed.strip_prefix("##")
+                && section.is_empty()
+                && doc.entries.is_empty()
+            {
+                let desc = desc.trim();
+                if let Some(rest) = desc.strip_prefix("Settings file was created by plugin ") {
+                    let name = rest.rsplit_once(" v").map_or(rest, |(name, _)| name);
+                    doc.plugin_name = Some(name.trim().to_string());
+                } else if let Some(guid) = desc.strip_prefix("Plugin GUID:") {
+                    doc.plugin_guid = Some(guid.trim().to_string());
+                }
+            } else if let Some(desc) = trimmed.strip_prefix("##") {
+                if !pending.description.is_empty() {
+                    pending.description.push('\n');
+                }
+                pending.description.push_str(desc.trim());
+            } else if let Some(comment) = trimmed.strip_prefix('#') {
+                let comment = comment.trim();
+                if let Some(v) = comment.strip_prefix("Setting type:") {
+                    pending.setting_type = Some(v.trim().to_string());
+                } else if let Some(v) = comment.strip_prefix("Default value:") {
+                    pending.default = Some(v.trim().to_string());
+                } else if let Some(v) = comment.strip_prefix("Acceptable values:") {
+                    pending.acceptable = v.split(',').map(|s| s.trim().to_string()).collect();
+                } else if let Some(v) = comment.strip_prefix("Acceptable value range:") {
+                    pending.range = v
+                        .trim()
+                        .strip_prefix("From ")
+                        .and_then(|r| r.split_once(" to "))
+                        .map(|(lo, hi)| (lo.trim().to_string(), hi.trim().to_string()));
+                } else if comment.starts_with("Multiple values can be set") {
+                    pending.multiple = true;
+                }
+            } else if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                section = trimmed[1..trimmed.len() - 1].trim().to_string();
+                pending = blank.clone();
+            } else if let Some(eq) = text.find('=') {
+                let key = text[..eq].trim().to_string();
+                let after = &text[eq + 1..];
+                let start = eq + 1 + (after.len() - after.trim_start().len());
+                let end = eq + 1 + after.trim_end().len();
+                let end = end.max(start);
+                line.value = Some((start, end));
+                doc.entries.push(Entry {
+                    section: section.clone(),
+                    key,
+                    value: text[start..end].to_string(),
+                    line: doc.lines.len(),
+                    ..std::mem::replace(&mut pending, blank.clone())
+                });
+            }
+            doc.lines.push(line);
+        }
+        doc
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse `path`, or start an empty document if it does not exist yet.
+    pub fn load_or_default(path: &Path) -> Result<Self> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn plugin_name(&self) -> Option<&str> {
+        self.plugin_name.as_deref()
+    }
+
+    pub fn plugin_guid(&self) -> Option<&str> {
+        self.plugin_guid.as_deref()
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|e| e.section == section && e.key == key)
+    }
+
+    /// Change an existing setting. Returns false if it is not in the document.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) -> bool {
+        let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|e| e.section == section && e.key == key)
+        else {
+            return false;
+        };
+        let line = &mut self.lines[entry.line];
+        let (start, end) = line.value.expect("setting line has a value span");
+        line.text.replace_range(start..end, value);
+        line.value = Some((start, start + value.len()));
+        entry.value = value.to_string();
+        true
+    }
+
+    /// Change a setting, adding it (and its section) when missing.
+    pub fn set_or_insert(&mut self, section: &str, key: &str, value: &str) {
+        if self.set(section, key, value) {
+            return;
+        }
+        let eol = self
+            .lines
+            .iter()
+            .map(|l| l.eol)
+            .find(|e| !e.is_empty())
+            .unwrap_or("\n");
+        if let Some(last) = self.lines.last_mut()
+            && last.eol.is_empty()
+        {
+            last.eol = eol;
+        }
+
+        let section_start = self.lines.iter().position(|l| {
+            let t = l.text.trim();
+            t.starts_with('[') && t.ends_with(']') && t[1..t.len() - 1].trim() == section
+        });
+        let at = match section_start {
+            Some(start) => {
+                let mut at = start + 1;
+                for (i, l) in self.lines.iter().enumerate().skip(start + 1) {
+                    let t = l.text.trim();
+                    if t.starts_with('[') && t.ends_with(']') {
+                        break;
+                    }
+                    if !t.is_empty() {
+                        at = i + 1;
+                    }
+                }
+                at
+            }
+            None => {
+                if !self.lines.is_empty() {
+                    self.lines.push(Line {
+                        text: String::new(),
+                        eol,
+                        value: None,
+                    });
+                }
+                self.lines.push(Line {
+                    text: format!("[{section}]"),
+                    eol,
+                    value: None,
+                });
+                self.lines.len()
+            }
+        };
+
+        let text = format!("{key} = {value}");
+        let start = key.len() + 3;
+        self.lines.insert(
+            at,
+            Line {
+                value: Some((start, text.len())),
+                text,
+                eol,
+            },
+        );
+        for e in &mut self.entries {
+            if e.line >= at {
+                e.line += 1;
+            }
+        }
+        self.entries.push(Entry {
+            section: section.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            description: String::new(),
+            setting_type: None,
+            default: None,
+            acceptable: Vec::new(),
+            range: None,
+            multiple: false,
+            line: at,
+        });
+    }
+}
+
+impl fmt::Display for CfgDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}{}", line.text, line.eol)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "## Settings file was created by plugin BepInEx v5.4.23.2\r\n## Plugin GUID: BepInEx\r\n\r\n[Logging]\r\n\r\n## Enables showing unity log messages in the BepInEx logging system.\r\n# Setting type: Boolean\r\n# Default value: true\r\nUnityLogListening = true\r\n\r\n[Logging.Console]\r\n\r\n## Which log levels to show in the console output.\r\n# Setting type: LogLevel\r\n# Default value: Fatal, Error, Warning, Message, Info\r\n# Acceptable values: None, Fatal, Error, Warning, Message, Info, Debug, All\r\n# Multiple values can be set at the same time by separating them with , (e.g. Debug, Warning)\r\nLogLevels = Fatal, Error, Warning, Message, Info\r\n\r\n## Scale\r\n# Setting type: Single\r\n# Default value: 1\r\n# Acceptable value range: From 0.5 to 2\r\nScale =   1   \r\n";
+
+    #[test]
+    fn test_parse_and_roundtrip() {
+        let mut doc = CfgDocument::parse(SAMPLE);
+        assert_eq!(doc.to_string(), SAMPLE);
+
+        assert_eq!(doc.entries().len(), 3);
+        assert_eq!(doc.plugin_name(), Some("BepInEx"));
+        assert_eq!(doc.plugin_guid(), Some("BepInEx"));
+
+        let levels = doc.get("Logging.Console", "LogLevels").unwrap();
+        assert!(matches!(levels.kind(), ValueKind::Flags(v) if v.len() == 8));
+        assert!(levels.is_default());
+        assert_eq!(levels.acceptable.len(), 8);
+        assert!(levels.multiple);
+        assert_eq!(
+            levels.default.as_deref(),
+            Some("Fatal, Error, Warning, Message, Info")
+        );
+        let scale = doc.get("Logging.Console", "Scale").unwrap();
+        assert_eq!(scale.value, "1");
+        assert_eq!(scale.setting_type.as_deref(), Some("Single"));
+        assert_eq!(
+            scale.kind(),
+            ValueKind::Float {
+                min: Some(0.5),
+                max: Some(2.0)
+            }
+        );
+
+        assert!(doc.set("Logging", "UnityLogListening", "false"));
+        assert!(doc.set("Logging.Console", "Scale", "1.5"));
+        assert_eq!(
+            doc.to_string(),
+            SAMPLE
+                .replace("UnityLogListening = true", "UnityLogListening = false")
+                .replace("Scale =   1   ", "Scale =   1.5   ")
+        );
+    }
+
+    #[test]
+    fn test_set_or_insert() {
+        let mut doc = CfgDocument::default();
+        doc.set_or_insert("Logging", "UnityLogListening", "false");
+        assert_eq!(doc.to_string(), "[Logging]\nUnityLogListening = false\n");
+
+        let mut doc = CfgDocument::parse("[Logging]\nA = 1\n\n[Other]\nB = 2");
+        doc.set_or_insert("Logging", "C", "3");
+        doc.set_or_insert("New", "D", "4");
+        assert_eq!(
+            doc.to_string(),
+            "[Logging]\nA = 1\nC = 3\n\n[Other]\nB = 2\n\n[New]\nD = 4\n"
+        );
+        assert_eq!(doc.get("Other", "B").unwrap().value, "2");
+        assert!(doc.set("Logging", "C", "30"));
+        assert!(doc.to_string().contains("C = 30\n"));
+    }
+}
@@ -0,0 +1,338 @@
+//! Every archive the manager downloaded or installed, stored by SHA-256 in the
+//! user data dir so reinstalls, repairs and imports work without the network
+//! or the file the user picked.
+
+use crate::hash;
+use crate::net::{self, HttpConfig, Transfer};
+use crate::types::ModIndex;
+use crate::verify::{self, Expected};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Cache size kept unless the user picks another limit.
+pub const DEFAULT_LIMIT_MB: u64 = 2048;
+
+/// Jobs and the UI both update the index.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    BepInEx,
+    Mod,
+}
+
+/// Where an archive came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Origin {
+    pub kind: Kind,
+    pub name: String,
+    pub version: Option<String>,
+    /// Download URL, for archives fetched by the manager.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Local file the user installed it from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl Origin {
+    pub fn new(kind: Kind, name: impl Into<String>, version: Option<String>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            version,
+            url: None,
+            source: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub sha256: String,
+    pub size: u64,
+    pub added: u64,
+    /// Stored or read last; the least recently used archives go first.
+    pub last_used: u64,
+    #[serde(flatten)]
+    pub origin: Origin,
+}
+
+pub fn default_root() -> PathBuf {
+    crate::config::data_dir().join("cache")
+}
+
+fn index_path(root: &Path) -> PathBuf {
+    root.join("index.json")
+}
+
+fn object_path(root: &Path, sha256: &str) -> PathBuf {
+    root.join("objects").join(format!("{sha256}.zip"))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Cached archives, least recently used first.
+pub fn list(root: &Path) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = fs::read_to_string(index_path(root))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    entries.retain(|e| object_path(root, &e.sha256).is_file());
+    entries.sort_by_key(|e| e.last_used);
+    entries
+}
+
+fn save(root: &Path, entries: &[Entry]) -> Result<()> {
+    fs::create_dir_all(root)?;
+    let part = root.join("index.json.part");
+    fs::write(&part, serde_json::to_string_pretty(entries)?)?;
+    fs::rename(part, index_path(root))?;
+    Ok(())
+}
+
+/// Keep `bytes` in the cache and return their hash. Storing an archive that
+/// is already cached only refreshes its metadata.
+pub fn put(root: &Path, bytes: &[u8], origin: Origin) -> Result<String> {
+    let sha256 = hash::sha256_bytes(bytes);
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let path = object_path(root, &sha256);
+    if !path.is_file() {
+        fs::create_dir_all(root.join("objects"))?;
+        let part = path.with_extension("zip.part");
+        fs::write(&part, bytes)?;
+        fs::rename(&part, &path)?;
+    }
+    let mut entries = list(root);
+    let now = now_secs();
+    match entries.iter_mut().find(|e| e.sha256 == sha256) {
+        Some(e) => {
+            e.last_used = now;
+            e.origin = Origin {
+                url: origin.url.or(e.origin.url.take()),
+                source: origin.source.or(e.origin.source.take()),
+                ..origin
+            };
+        }
+        None => entries.push(Entry {
+            sha256: sha256.clone(),
+            size: bytes.len() as u64,
+            added: now,
+            last_used: now,
+            origin,
+        }),
+    }
+    save(root, &entries)?;
+    Ok(sha256)
+}
+
+/// [`put`] for a local archive, remembering where it was picked from.
+pub fn put_file(root: &Path, path: &Path, origin: Origin) -> Result<String> {
+    let origin = Origin {
+        source: Some(path.display().to_string()),
+        ..origin
+    };
+    put(root, &fs::read(path)?, origin)
+}
+
+fn touch(root: &Path, found: impl Fn(&Entry) -> bool) -> Option<PathBuf> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut entries = list(root);
+    let entry = entries.iter_mut().rev().find(|e| found(e))?;
+    entry.last_used = now_secs();
+    let path = object_path(root, &entry.sha256);
+    let _ = save(root, &entries);
+    Some(path)
+}
+
+/// Path of the cached archive with this hash.
+pub fn get(root: &Path, sha256: &str) -> Option<PathBuf> {
+    touch(root, |e| e.sha256 == sha256)
+}
+
+/// Path of the archive last downloaded from `url`.
+pub fn find_url(root: &Path, url: &str) -> Option<PathBuf> {
+    touch(root, |e| e.origin.url.as_deref() == Some(url))
+}
+
+/// The cached download of `url` when `expected` pins its content, else a
+/// fresh download that is cached for next time; an unpinned URL such as
+/// `.../latest.zip` may serve something new each time. Offline, the last
+/// download of `url` is used either way.
+pub fn fetch(
+    root: &Path,
+    url: &str,
+    http: &HttpConfig,
+    expected: &Expected,
+    transfer: &Transfer,
+    origin: Origin,
+) -> Result<PathBuf> {
+    let pinned = expected.sha256.is_some() || expected.signature.is_some();
+    if (pinned || http.offline)
+        && let Some(path) = find_url(root, url)
+        && fs::read(&path).is_ok_and(|bytes| verify::check(&bytes, expected).is_ok())
+    {
+        return Ok(path);
+    }
+    if http.offline {
+        let version = origin.version.as_deref().unwrap_or("");
+        return Err(anyhow!(
+            "{} {version} is not in the download cache. Turn off Offline mode to download it",
+            origin.name
+        ));
+    }
+    let bytes = net::download_verified(url, http, expected, transfer)?;
+    let origin = Origin {
+        url: Some(url.to_string()),
+        ..origin
+    };
+    let sha256 = put(root, &bytes, origin)?;
+    Ok(object_path(root, &sha256))
+}
+
+/// Hashes of the archives `index` was installed from.
+pub fn in_use(index: &ModIndex) -> HashSet<String> {
+    index
+        .mods
+        .iter()
+        .filter_map(|m| m.archive_sha256.clone())
+        .chain(index.bepinex.iter().map(|b| b.archive_sha256.clone()))
+        .collect()
+}
+
+/// Remove the least recently used archives until the cache fits in
+/// `limit_bytes`, sparing those in `keep`. Returns how many archives and
+/// bytes were removed.
+pub fn prune(root: &Path, limit_bytes: u64, keep: &HashSet<String>) -> Result<(usize, u64)> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut entries = list(root);
+    let mut total: u64 = entries.iter().map(|e| e.size).sum();
+    let mut removed = (0, 0);
+    let mut kept = Vec::new();
+    for e in entries.drain(..) {
+        if total > limit_bytes && !keep.contains(&e.sha256) {
+            fs::remove_file(object_path(root, &e.sha256))?;
+            total -= e.size;
+            removed.0 += 1;
+            removed.1 += e.size;
+        } else {
+            kept.push(e);
+        }
+    }
+    if removed.0 > 0 {
+        save(root, &kept)?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ModEntry;
+
+    #[test]
+    fn test_put_dedupes_and_prune_spares_archives_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let old = put(root, b"PK\x03\x04old", Origin::new(Kind::Mod, "Old", None)).unwrap();
+        let used = put(
+            root,
+            b"PK\x03\x04used",
+            Origin::new(Kind::Mod, "Used", None),
+        )
+        .unwrap();
+        let src = root.join("new.zip");
+        fs::write(&src, b"PK\x03\x04new").unwrap();
+        let origin = Origin::new(Kind::Mod, "New", Some("1.0.0".into()));
+        let new = put_file(root, &src, origin.clone()).unwrap();
+        // Storing the same bytes again keeps one copy and the source path.
+        assert_eq!(put(root, b"PK\x03\x04new", origin).unwrap(), new);
+        let entries = list(root);
+        assert_eq!(entries.len(), 3);
+        let new_entry = entries.iter().find(|e| e.sha256 == new).unwrap();
+        assert_eq!(
+            new_entry.origin.source.as_deref(),
+            Some(src.display().to_string().as_str())
+        );
+        assert_eq!(
+            fs::read(get(root, &new).unwrap()).unwrap(),
+            b"PK\x03\x04new"
+        );
+
+        let index = ModIndex {
+            mods: vec![ModEntry {
+                archive_sha256: Some(used.clone()),
+                ..Default::default()
+            }],
+            bepinex: None,
+        };
+        let (count, bytes) = prune(root, 0, &in_use(&index)).unwrap();
+        assert_eq!((count, bytes), (2, 14));
+        let left: Vec<_> = list(root).into_iter().map(|e| e.sha256).collect();
+        assert_eq!(left, [used]);
+        assert!(get(root, &old).is_none());
+        assert!(!object_path(root, &old).exists());
+
+        // Offline, downloads are served from the cache or refused.
+        let offline = HttpConfig {
+            offline: true,
+            ..HttpConfig::default()
+        };
+        let url = "https://example.invalid/Used.zip";
+        let origin = Origin {
+            url: Some(url.into()),
+            ..Origin::new(Kind::Mod, "Used", None)
+        };
+        put(root, b"PK\x03\x04used", origin.clone()).unwrap();
+        let transfer = Transfer::default();
+        let expected = Expected::default();
+        let path = fetch(root, url, &offline, &expected, &transfer, origin.clone()).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"PK\x03\x04used");
+        let other = "https://example.invalid/Other.zip";
+        let err = fetch(root, other, &offline, &expected, &transfer, origin).unwrap_err();
+        assert!(
+            err.to_string().contains("not in the download cache"),
+            "{err}"
+        );
+
+        // Online, a URL is only served from the cache when its content is
+        // pinned; otherwise the server may have something newer.
+        let mut server = mockito::Server::new();
+        let latest = server
+            .mock("GET", "/latest.zip")
+            .with_body(b"PK\x03\x04newer")
+            .expect(1)
+            .create();
+        let url = format!("{}/latest.zip", server.url());
+        let origin = Origin {
+            url: Some(url.clone()),
+            ..Origin::new(Kind::Mod, "Latest", None)
+        };
+        let old = put(root, b"PK\x03\x04older", origin.clone()).unwrap();
+        let online = HttpConfig::default();
+        let path = fetch(
+            root,
+            &url,
+            &online,
+            &Expected::sha256(&old),
+            &transfer,
+            origin.clone(),
+        )
+        .unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"PK\x03\x04older");
+        let path = fetch(root, &url, &online, &expected, &transfer, origin).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"PK\x03\x04newer");
+        latest.assert();
+    }
+}
@@ -0,0 +1,232 @@
+//! Available BepInEx builds: releases from GitHub and Bleeding Edge artifacts
+//! from builds.bepinex.dev.
+
+use crate::bepinex::BepInExInfo;
+use crate::game::GameInfo;
+use crate::net::{self, HttpConfig};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+pub const BE_BUILDS_URL: &str = "https://builds.bepinex.dev/projects/bepinex_be";
+pub const GITHUB_RELEASES_URL: &str = "https://api.github.com/repos/BepInEx/BepInEx/releases";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    /// GitHub releases (5.x).
+    Stable,
+    /// GitHub pre-releases (6.0.0-pre.N).
+    Prerelease,
+    /// builds.bepinex.dev artifacts.
+    #[default]
+    BleedingEdge,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 3] = [Channel::Stable, Channel::Prerelease, Channel::BleedingEdge];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Channel::Stable => "Stable",
+            Channel::Prerelease => "Pre-release",
+            Channel::BleedingEdge => "Bleeding Edge",
+        }
+    }
+}
+
+/// One downloadable archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Build {
+    pub channel: Channel,
+    pub info: BepInExInfo,
+    pub url: String,
+    /// Digest published with the build, e.g. `sha256:...` for GitHub assets.
+    pub sha256: Option<String>,
+}
+
+impl Build {
+    fn from_url(channel: Channel, url: String) -> Option<Self> {
+        let lower = url.to_ascii_lowercase();
+        if !lower.ends_with(".zip")
+            || ["linux", "macos", "unix"]
+                .iter()
+                .any(|os| lower.contains(os))
+        {
+            return None;
+        }
+        let info = BepInExInfo::from_archive_name(&url);
+        // Only builds for Unity games, with a version we can compare.
+        (info.runtime.is_some() && info.version.is_some()).then_some(Self {
+            channel,
+            info,
+            url,
+            sha256: None,
+        })
+    }
+
+    pub fn fits(&self, game: &GameInfo) -> bool {
+        self.info.fits(game)
+    }
+}
+
+#[derive(Deserialize)]
+struct Release {
+    prerelease: bool,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    browser_download_url: String,
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+/// Builds listed by the GitHub releases API.
+pub fn parse_github_releases(json: &str) -> Result<Vec<Build>> {
+    let releases: Vec<Release> = serde_json::from_str(json)?;
+    Ok(releases
+        .into_iter()
+        .flat_map(|r| {
+            let channel = if r.prerelease {
+                Channel::Prerelease
+            } else {
+                Channel::Stable
+            };
+            r.assets.into_iter().filter_map(move |a| {
+                let build = Build::from_url(channel, a.browser_download_url)?;
+                Some(Build {
+                    sha256: a.digest.filter(|d| d.starts_with("sha256:")),
+                    ..build
+                })
+            })
+        })
+        .collect())
+}
+
+/// Builds linked from the Bleeding Edge project page at `page_url`.
+pub fn parse_be_page(html: &str, page_url: &str) -> Vec<Build> {
+    let origin = page_url
+        .find("://")
+        .and_then(|s| page_url[s + 3..].find('/').map(|p| &page_url[..s + 3 + p]))
+        .unwrap_or(page_url);
+    html.split("href=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .filter_map(|href| {
+            let url = if href.starts_with('/') {
+                format!("{origin}{href}")
+            } else {
+                href.to_string()
+            };
+            Build::from_url(Channel::BleedingEdge, url)
+        })
+        .collect()
+}
+
+/// Every build from both sources.
+pub fn fetch(be_url: &str, releases_url: &str, http: &HttpConfig) -> Result<Vec<Build>> {
+    let html = net::download_bytes_blocking(be_url, http)?;
+    let mut builds = parse_be_page(&String::from_utf8_lossy(&html), be_url);
+    let json = net::download_bytes_blocking(releases_url, http)?;
+    builds.extend(parse_github_releases(&String::from_utf8_lossy(&json))?);
+    Ok(builds)
+}
+
+/// Sort key for a version such as `6.0.0-be.752` or `5.4.23.2`: release
+/// numbers, then releases above pre-releases, then the pre-release numbers.
+fn version_key(info: &BepInExInfo) -> (Vec<u64>, bool, Vec<u64>) {
+    let numbers = |s: &str| -> Vec<u64> {
+        s.split(|c: char| !c.is_ascii_digit())
+            .filter_map(|n| n.parse().ok())
+            .collect()
+    };
+    let version = info.version.as_deref().unwrap_or("");
+    match version.split_once('-') {
+        Some((release, pre)) => (numbers(release), false, numbers(pre)),
+        None => (numbers(version), true, Vec::new()),
+    }
+}
+
+/// Whether `a` is a later build than `b`.
+pub fn is_newer(a: &BepInExInfo, b: &BepInExInfo) -> bool {
+    version_key(a) > version_key(b)
+}
+
+/// Builds of `channel` that fit `game`, newest first.
+pub fn builds_for<'a>(builds: &'a [Build], channel: Channel, game: &GameInfo) -> Vec<&'a Build> {
+    let mut found: Vec<&Build> = builds
+        .iter()
+        .filter(|b| b.channel == channel && b.fits(game))
+        .collect();
+    found.sort_by_key(|b| std::cmp::Reverse(version_key(&b.info)));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bepinex::{Arch, Runtime};
+
+    const BE_PAGE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/bepinex_be.html"
+    ));
+    const RELEASES: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/github_releases.json"
+    ));
+
+    #[test]
+    fn test_fetch_and_filter_for_game() {
+        let mut server = mockito::Server::new();
+        let be = server
+            .mock("GET", "/projects/bepinex_be")
+            .with_body(BE_PAGE)
+            .create();
+        let gh = server
+            .mock("GET", "/repos/BepInEx/BepInEx/releases")
+            .with_header("content-type", "application/json")
+            .with_body(RELEASES)
+            .create();
+        let builds = fetch(
+            &format!("{}/projects/bepinex_be", server.url()),
+            &format!("{}/repos/BepInEx/BepInEx/releases", server.url()),
+            &HttpConfig::default(),
+        )
+        .unwrap();
+        be.assert();
+        gh.assert();
+        // CoreCLR and Linux builds are left out.
+        assert_eq!(builds.len(), 5 + 5);
+
+        let il2cpp_x64 = GameInfo {
+            runtime: Some(Runtime::Il2Cpp),
+            arch: Some(Arch::X64),
+            ..Default::default()
+        };
+        let be_builds = builds_for(&builds, Channel::BleedingEdge, &il2cpp_x64);
+        assert_eq!(be_builds.len(), 2);
+        assert_eq!(be_builds[0].info.build, Some(753));
+        assert_eq!(
+            be_builds[0].url,
+            format!(
+                "{}/projects/bepinex_be/753/BepInEx-Unity.IL2CPP-win-x64-6.0.0-be.753%2B9c4b2a1.zip",
+                server.url()
+            )
+        );
+        assert!(is_newer(&be_builds[0].info, &be_builds[1].info));
+        assert!(builds_for(&builds, Channel::Stable, &il2cpp_x64).is_empty());
+        let pre = builds_for(&builds, Channel::Prerelease, &il2cpp_x64);
+        assert!(pre[0].sha256.as_deref().unwrap().starts_with("sha256:"));
+
+        let mono_x86 = GameInfo {
+            runtime: Some(Runtime::Mono),
+            arch: Some(Arch::X86),
+            ..Default::default()
+        };
+        let stable = builds_for(&builds, Channel::Stable, &mono_x86);
+        let versions: Vec<_> = stable.iter().map(|b| b.info.version.as_deref()).collect();
+        assert_eq!(versions, [Some("5.4.23.2"), Some("5.4.22.0")]);
+    }
+}
@@ -1,25 +1,53 @@
+use crate::fixups::Fixup;
+use crate::net::HttpConfig;
+use crate::verify::Expected;
 use serde::Deserialize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Constants {
     pub bepinex_url: String,
-    pub user_agent: String,
     pub default_game_dir: String,
     pub app_title: String,
     pub log_max_height: f32,
     pub mods_max_height: f32,
+    /// Where older Config.toml files set the user agent; `[http]` now.
+    #[serde(default)]
+    pub user_agent: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub constants: Constants,
+    /// `BepInEx.cfg` presets and known fixes, see `[[fixups]]` in Config.toml.
+    #[serde(default)]
+    pub fixups: Vec<Fixup>,
+    /// Known digests or signatures of BepInEx archives, see `[[checksums]]`.
+    #[serde(default)]
+    pub checksums: Vec<Checksum>,
+    /// Proxy, certificates, timeouts and user agent for every request.
+    #[serde(default)]
+    pub http: HttpConfig,
+}
+
+/// What the archive called `file` must match.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Checksum {
+    pub file: String,
+    #[serde(flatten)]
+    pub expected: Expected,
 }
 
 impl Config {
     pub fn load(path: &Path) -> anyhow::Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
+        let mut config: Config = toml::from_str(&content)?;
+        if let Some(user_agent) = config.constants.user_agent.take() {
+            eprintln!("Warning: [constants] user_agent is deprecated; set it under [http].");
+            if config.http.user_agent == HttpConfig::default().user_agent {
+                config.http.user_agent = user_agent;
+            }
+        }
         Ok(config)
     }
 
@@ -36,9 +64,25 @@ impl Config {
         }
     }
 
+    /// Checks configured for the archive `url` points to.
+    pub fn expected_for(&self, url: &str) -> Expected {
+        let file = url.rsplit('/').next().unwrap_or(url).replace("%2B", "+");
+        self.checksums
+            .iter()
+            .find(|c| c.file.replace("%2B", "+") == file)
+            .map(|c| c.expected.clone())
+            .unwrap_or_default()
+    }
+
     pub fn load_or_default(path: &Path) -> Self {
         match Self::load(path) {
-            Ok(cfg) => cfg,
+            Ok(mut cfg) => {
+                // Older Config.toml files predate the fixups; keep the built-in ones.
+                if cfg.fixups.is_empty() {
+                    cfg.fixups = Self::load_embedded().fixups;
+                }
+                cfg
+            }
             Err(_e) => {
                 eprintln!("Config.toml not found; using embedded defaults.");
                 Self::load_embedded()
@@ -52,12 +96,23 @@ impl Default for Config {
         Self {
             constants: Constants {
                 bepinex_url: "https://builds.bepinex.dev/projects/bepinex_be/752/BepInEx-Unity.IL2CPP-win-x64-6.0.0-be.752%2Bdd0655f.zip".to_string(),
-                user_agent: "restaurats-mod-manager".to_string(),
                 default_game_dir: r"C:\Program Files (x86)\Steam\steamapps\common\Restaurats".to_string(),
                 app_title: "Restaurats Mod Manager".to_string(),
                 log_max_height: 160.0,
                 mods_max_height: 220.0,
+                user_agent: None,
             },
+            fixups: Vec::new(),
+            checksums: Vec::new(),
+            http: HttpConfig::default(),
         }
     }
 }
+
+/// Per-user data directory for state that must outlive the game folder
+/// (journal, snapshots, caches).
+pub fn data_dir() -> PathBuf {
+    directories::ProjectDirs::from("com", "mbround18", "restaurats-mod-manager")
+        .map(|d| d.data_dir().to_path_buf())
+        .unwrap_or_else(|| std::env::temp_dir().join("restaurats-mod-manager"))
+}
@@ -0,0 +1,502 @@
+use crate::bepinex::cfg::CfgDocument;
+use crate::journal::{self, OpKind};
+use crate::mods;
+use crate::types::{ModEntry, ModIndex};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// Config folder relative to the game dir.
+pub const CONFIG_DIR: &str = "BepInEx/config";
+/// Which mod created which config file, relative to the game dir. Kept apart
+/// from the mod index so linking never interferes with undo.
+pub const LINKS_FILE: &str = "BepInEx/mod-manager.config-links.json";
+
+/// Config files plugins generated, linked to the mod that owns the plugin.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigLinks {
+    /// Every config file already considered for linking.
+    #[serde(default)]
+    pub seen: Vec<String>,
+    /// Config path to mod id.
+    #[serde(default)]
+    pub owners: BTreeMap<String, String>,
+}
+
+impl ConfigLinks {
+    pub fn load(game_dir: &Path) -> Self {
+        fs::read_to_string(game_dir.join(LINKS_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, game_dir: &Path) -> Result<()> {
+        let path = game_dir.join(LINKS_FILE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Generated config files linked to `mod_id` that still exist.
+    pub fn files_of(&self, game_dir: &Path, mod_id: &str) -> Vec<String> {
+        self.owners
+            .iter()
+            .filter(|(rel, id)| *id == mod_id && game_dir.join(rel).is_file())
+            .map(|(rel, _)| rel.clone())
+            .collect()
+    }
+
+    /// Forget `rels`, so they are linked afresh if a plugin creates them again.
+    pub fn forget(&mut self, rels: &[String]) {
+        self.seen.retain(|s| !rels.contains(s));
+        self.owners.retain(|rel, _| !rels.contains(rel));
+    }
+}
+
+/// A `.cfg` file under `BepInEx/config` and the mod it belongs to, if known.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    /// Path relative to the game dir.
+    pub rel: String,
+    pub plugin_name: Option<String>,
+    pub plugin_guid: Option<String>,
+    pub owner: Option<String>,
+}
+
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Which installed mod `rel` most likely belongs to: one that shipped the file
+/// or is linked to it, then one whose name matches the plugin that wrote it,
+/// then one whose name appears in the file name.
+pub fn owner_of<'a>(
+    index: &'a ModIndex,
+    links: &ConfigLinks,
+    rel: &str,
+    plugin_name: Option<&str>,
+) -> Option<&'a ModEntry> {
+    if let Some(m) = index.mods.iter().find(|m| {
+        m.installed_files
+            .iter()
+            .any(|f| f.eq_ignore_ascii_case(rel))
+            || links.owners.get(rel) == Some(&m.id)
+    }) {
+        return Some(m);
+    }
+    let names = |m: &ModEntry| {
+        let mut names = vec![normalize(&m.name)];
+        if let Some((_, name)) = m.id.split_once('-') {
+            names.push(normalize(name));
+        }
+        names.retain(|n| n.len() >= 3);
+        names
+    };
+    if let Some(plugin) = plugin_name.map(normalize)
+        && let Some(m) = index.mods.iter().find(|m| names(m).contains(&plugin))
+    {
+        return Some(m);
+    }
+    let stem = Path::new(rel)
+        .file_stem()
+        .map(|s| normalize(&s.to_string_lossy()))
+        .unwrap_or_default();
+    index
+        .mods
+        .iter()
+        .find(|m| names(m).iter().any(|n| stem.contains(n.as_str())))
+}
+
+/// Every `.cfg` under `BepInEx/config`, sorted by path. `BepInEx.cfg` itself
+/// is owned by BepInEx.
+pub fn list(game_dir: &Path, index: &ModIndex) -> Vec<ConfigFile> {
+    let links = ConfigLinks::load(game_dir);
+    cfg_paths(game_dir)
+        .into_iter()
+        .map(|rel| {
+            let doc = CfgDocument::load(&game_dir.join(&rel)).ok();
+            let plugin_name = doc
+                .as_ref()
+                .and_then(|d| d.plugin_name().map(str::to_string));
+            let plugin_guid = doc
+                .as_ref()
+                .and_then(|d| d.plugin_guid().map(str::to_string));
+            let owner = if rel.eq_ignore_ascii_case(crate::bepinex::CONFIG_FILE) {
+                Some("BepInEx".to_string())
+            } else {
+                owner_of(index, &links, &rel, plugin_name.as_deref()).map(|m| m.name.clone())
+            };
+            ConfigFile {
+                rel,
+                plugin_name,
+                plugin_guid,
+                owner,
+            }
+        })
+        .collect()
+}
+
+/// Relative paths of the `.cfg` files under `BepInEx/config`, sorted.
+fn cfg_paths(game_dir: &Path) -> Vec<String> {
+    WalkDir::new(game_dir.join(CONFIG_DIR))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_type().is_file()
+                && e.path()
+                    .extension()
+                    .is_some_and(|x| x.eq_ignore_ascii_case("cfg"))
+        })
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(game_dir)
+                .ok()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        })
+        .collect()
+}
+
+/// Whether a DLL installed by `entry` declares `guid`. The `[BepInPlugin]`
+/// attribute stores it as UTF-8 in the assembly's metadata.
+fn declares_guid(game_dir: &Path, entry: &ModEntry, guid: &str) -> bool {
+    let needle = guid.as_bytes();
+    entry
+        .installed_files
+        .iter()
+        .filter(|f| f.to_ascii_lowercase().ends_with(".dll"))
+        .filter_map(|f| fs::read(game_dir.join(f)).ok())
+        .any(|bytes| bytes.windows(needle.len()).any(|w| w == needle))
+}
+
+/// Link config files that appeared since the last call to the mod whose
+/// plugin created them: by the GUID in the file header, falling back to the
+/// name heuristics of [`owner_of`]. Each file is only considered the first
+/// time it is seen.
+pub fn link_new(game_dir: &Path, index: &ModIndex) -> Result<ConfigLinks> {
+    let mut links = ConfigLinks::load(game_dir);
+    let present = cfg_paths(game_dir);
+    let before = (links.seen.len(), links.owners.len());
+    links.seen.retain(|s| present.contains(s));
+    for rel in present {
+        if links.seen.contains(&rel) {
+            continue;
+        }
+        links.seen.push(rel.clone());
+        if rel.eq_ignore_ascii_case(crate::bepinex::CONFIG_FILE)
+            || index.mods.iter().any(|m| m.installed_files.contains(&rel))
+        {
+            continue;
+        }
+        let doc = CfgDocument::load(&game_dir.join(&rel)).ok();
+        let by_guid = doc
+            .as_ref()
+            .and_then(|d| d.plugin_guid())
+            .filter(|g| g.len() >= 3)
+            .and_then(|guid| index.mods.iter().find(|m| declares_guid(game_dir, m, guid)));
+        let owner = by_guid.or_else(|| {
+            owner_of(
+                index,
+                &links,
+                &rel,
+                doc.as_ref().and_then(|d| d.plugin_name()),
+            )
+        });
+        if let Some(m) = owner {
+            links.owners.insert(rel, m.id.clone());
+        }
+    }
+    if links.seen.len() != before.0 || links.owners.len() != before.1 {
+        links.save(game_dir)?;
+    }
+    Ok(links)
+}
+
+pub fn default_backup_root() -> PathBuf {
+    crate::config::data_dir().join("config-backups")
+}
+
+/// Copy `rels` into a new timestamped folder under `root`, keeping their
+/// paths relative to the game dir. Returns the folder.
+pub fn backup(root: &Path, game_dir: &Path, label: &str, rels: &[String]) -> Result<PathBuf> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let safe: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let dir = root.join(format!("{now}-{safe}"));
+    for rel in rels {
+        let out = dir.join(rel);
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(game_dir.join(rel), out)?;
+    }
+    Ok(dir)
+}
+
+/// Write an edited config back, recorded in the journal so it can be undone.
+pub fn save(game_dir: &Path, journal_root: &Path, rel: &str, doc: &CfgDocument) -> Result<()> {
+    if !mods::is_config_path(rel) {
+        return Err(anyhow!(
+            "Refusing config path outside BepInEx/config: {rel}"
+        ));
+    }
+    let mut rec = journal::Recorder::new(
+        journal_root,
+        game_dir,
+        OpKind::EditConfig,
+        format!("Edit {rel}"),
+    );
+    rec.track_file(rel)?;
+    let res = doc.save(&game_dir.join(rel));
+    rec.commit()?;
+    res
+}
+
+/// Config files of a mod as they were before an upgrade.
+pub struct ConfigStash {
+    files: Vec<(String, String)>,
+}
+
+impl ConfigStash {
+    /// Read the configs that belong to `entry` before it is replaced.
+    pub fn take(game_dir: &Path, index: &ModIndex, entry: &ModEntry) -> Self {
+        let files = list(game_dir, index)
+            .into_iter()
+            .filter(|f| f.owner.as_deref() == Some(entry.name.as_str()))
+            .filter_map(|f| {
+                let text = fs::read_to_string(game_dir.join(&f.rel)).ok()?;
+                Some((f.rel, text))
+            })
+            .collect();
+        Self { files }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(|(rel, _)| rel.as_str())
+    }
+
+    /// Reconcile each stashed file with what the upgrade left on disk.
+    /// Files the upgrade did not touch are skipped; removed ones are put back.
+    pub fn merge_into(&self, game_dir: &Path) -> Result<Vec<MergeReport>> {
+        let mut reports = Vec::new();
+        for (rel, before) in &self.files {
+            let path = game_dir.join(rel);
+            let after = fs::read_to_string(&path).ok();
+            if after.as_deref() == Some(before.as_str()) {
+                continue;
+            }
+            let user = CfgDocument::parse(before);
+            let (doc, report) = match after {
+                Some(after) => merge(rel, &user, &CfgDocument::parse(&after)),
+                None => (
+                    user,
+                    MergeReport {
+                        file: rel.clone(),
+                        ..Default::default()
+                    },
+                ),
+            };
+            doc.save(&path)?;
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+}
+
+/// What happened to the settings of one config file during an upgrade, as
+/// `Section.Key` names.
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    pub file: String,
+    /// User changes carried over to the new file.
+    pub kept: Vec<String>,
+    /// Settings that only exist in the new version.
+    pub added: Vec<String>,
+    /// Settings the new version no longer has.
+    pub removed: Vec<String>,
+}
+
+/// Three-way merge of a config across an upgrade. `user` is the file before
+/// the upgrade; its `# Default value` comments are the old defaults. `new` is
+/// the file the new version shipped. The result keeps `new`'s layout and
+/// comments with every value the user had changed from the old default.
+pub fn merge(rel: &str, user: &CfgDocument, new: &CfgDocument) -> (CfgDocument, MergeReport) {
+    let mut out = new.clone();
+    let mut report = MergeReport {
+        file: rel.to_string(),
+        ..Default::default()
+    };
+    for e in user.entries() {
+        let name = format!("{}.{}", e.section, e.key);
+        if new.get(&e.section, &e.key).is_none() {
+            report.removed.push(name);
+        } else if !e.is_default() || e.default.is_none() {
+            out.set(&e.section, &e.key, &e.value);
+            report.kept.push(name);
+        }
+    }
+    for e in new.entries() {
+        if user.get(&e.section, &e.key).is_none() {
+            report.added.push(format!("{}.{}", e.section, e.key));
+        }
+    }
+    (out, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_maps_owners() {
+        let game = tempfile::tempdir().unwrap();
+        let dir = game.path().join(CONFIG_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("BepInEx.cfg"), "[Logging]\n").unwrap();
+        fs::write(
+            dir.join("com.chef.knives.cfg"),
+            "## Settings file was created by plugin Faster Knives v1.0.4\n## Plugin GUID: com.chef.knives\n",
+        )
+        .unwrap();
+        fs::write(dir.join("com.someone.rattrap.cfg"), "").unwrap();
+        fs::write(dir.join("unrelated.cfg"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut index = ModIndex::default();
+        for (id, name) in [
+            ("Chef-FasterKnives", "FasterKnives"),
+            ("Someone-RatTrap", "Rat Trap"),
+        ] {
+            index.mods.push(ModEntry {
+                id: id.into(),
+                name: name.into(),
+                ..Default::default()
+            });
+        }
+
+        let files = list(game.path(), &index);
+        let owners: Vec<(&str, Option<&str>)> = files
+            .iter()
+            .map(|f| (f.rel.as_str(), f.owner.as_deref()))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![
+                ("BepInEx/config/BepInEx.cfg", Some("BepInEx")),
+                ("BepInEx/config/com.chef.knives.cfg", Some("FasterKnives")),
+                ("BepInEx/config/com.someone.rattrap.cfg", Some("Rat Trap")),
+                ("BepInEx/config/unrelated.cfg", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_refuses_config_paths_outside_config_dir() {
+        let game = tempfile::tempdir().unwrap();
+        let journal = tempfile::tempdir().unwrap();
+        let doc = CfgDocument::parse("[General]\nSpeed = 8\n");
+        for rel in [
+            "BepInEx/config/..\\..\\..\\winhttp.dll",
+            "BepInEx/config/../../winhttp.dll",
+            "BepInEx/plugins/x.cfg",
+        ] {
+            assert!(
+                save(game.path(), journal.path(), rel, &doc).is_err(),
+                "{rel}"
+            );
+            let configs = [(rel.to_string(), b"x".to_vec())];
+            let res = mods::deploy(
+                game.path(),
+                journal.path(),
+                OpKind::ImportModpack,
+                "Import",
+                &[],
+                &configs,
+            );
+            assert!(res.is_err(), "{rel}");
+        }
+        assert_eq!(fs::read_dir(game.path()).unwrap().count(), 0);
+        save(game.path(), journal.path(), "BepInEx/config/x.cfg", &doc).unwrap();
+    }
+
+    #[test]
+    fn test_merge_keeps_user_changes() {
+        let user = CfgDocument::parse(
+            "[General]\n# Default value: 5\nSpeed = 8\n\n# Default value: true\nSounds = true\n\n# Default value: a\nOld = a\n",
+        );
+        let new = CfgDocument::parse(
+            "## Faster now\n[General]\n# Default value: 10\nSpeed = 10\n\n# Default value: false\nSounds = false\n\n# Default value: 1\nFresh = 1\n",
+        );
+        let (doc, report) = merge("BepInEx/config/x.cfg", &user, &new);
+        assert_eq!(
+            doc.to_string(),
+            "## Faster now\n[General]\n# Default value: 10\nSpeed = 8\n\n# Default value: false\nSounds = false\n\n# Default value: 1\nFresh = 1\n"
+        );
+        assert_eq!(report.kept, ["General.Speed"]);
+        assert_eq!(report.added, ["General.Fresh"]);
+        assert_eq!(report.removed, ["General.Old"]);
+    }
+
+    #[test]
+    fn test_link_new_by_guid() {
+        let game = tempfile::tempdir().unwrap();
+        let g = game.path();
+        fs::create_dir_all(g.join("BepInEx/plugins/Knives")).unwrap();
+        fs::write(
+            g.join("BepInEx/plugins/Knives/Blades.dll"),
+            b"MZ\0\0\x0fcom.chef.blades\0",
+        )
+        .unwrap();
+        fs::create_dir_all(g.join(CONFIG_DIR)).unwrap();
+        fs::write(
+            g.join("BepInEx/config/com.chef.blades.cfg"),
+            "## Settings file was created by plugin Sharp v1.0.0\n## Plugin GUID: com.chef.blades\n",
+        )
+        .unwrap();
+
+        let mut index = ModIndex::default();
+        index.mods.push(ModEntry {
+            id: "Chef-Knives".into(),
+            name: "Knives".into(),
+            installed_files: vec!["BepInEx/plugins/Knives/Blades.dll".into()],
+            ..Default::default()
+        });
+        let links = link_new(g, &index).unwrap();
+        assert_eq!(
+            links.files_of(g, "Chef-Knives"),
+            ["BepInEx/config/com.chef.blades.cfg"]
+        );
+        assert_eq!(list(g, &index)[0].owner.as_deref(), Some("Knives"));
+
+        // First-seen: a later mod cannot claim the file.
+        index.mods.insert(
+            0,
+            ModEntry {
+                id: "Other-Blades".into(),
+                name: "Blades".into(),
+                ..Default::default()
+            },
+        );
+        let links = link_new(g, &index).unwrap();
+        assert_eq!(links.owners.len(), 1);
+        assert!(links.files_of(g, "Other-Blades").is_empty());
+    }
+}
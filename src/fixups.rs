@@ -0,0 +1,156 @@
+use crate::bepinex::{self, cfg::CfgDocument};
+use crate::game::GameInfo;
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::Path;
+
+/// A named set of `BepInEx.cfg` changes from `Config.toml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fixup {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Applied after every BepInEx install when `when` matches.
+    #[serde(default)]
+    pub auto: bool,
+    #[serde(default)]
+    pub when: Condition,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Condition {
+    /// Prefix of the game's Unity version, e.g. `6000.` for Unity 6.
+    pub unity_version: Option<String>,
+    /// Prefix of the installed BepInEx version, e.g. `6.` or `5.4`.
+    pub bepinex_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Change {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+}
+
+/// What the conditions are checked against. Unknown values match anything.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    pub unity_version: Option<String>,
+    pub bepinex_version: Option<String>,
+}
+
+impl Environment {
+    pub fn detect(game_dir: &Path) -> Self {
+        Self {
+            unity_version: GameInfo::detect(game_dir).unity_version,
+            bepinex_version: bepinex::detect_bepinex(game_dir).and_then(|i| i.version),
+        }
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, env: &Environment) -> bool {
+        let check = |want: &Option<String>, have: &Option<String>| match (want, have) {
+            (Some(want), Some(have)) => have.starts_with(want.as_str()),
+            _ => true,
+        };
+        check(&self.unity_version, &env.unity_version)
+            && check(&self.bepinex_version, &env.bepinex_version)
+    }
+}
+
+impl Fixup {
+    /// Whether every change of the fixup is already in `doc`.
+    pub fn is_applied(&self, doc: &CfgDocument) -> bool {
+        self.changes.iter().all(|c| {
+            doc.get(&c.section, &c.key)
+                .is_some_and(|e| e.value.eq_ignore_ascii_case(&c.value))
+        })
+    }
+
+    pub fn apply(&self, doc: &mut CfgDocument) {
+        for c in &self.changes {
+            doc.set_or_insert(&c.section, &c.key, &c.value);
+        }
+    }
+
+    /// Put the touched settings back to their `# Default value`. Settings
+    /// without a documented default are left alone.
+    pub fn revert(&self, doc: &mut CfgDocument) {
+        for c in &self.changes {
+            if let Some(default) = doc.get(&c.section, &c.key).and_then(|e| e.default.clone()) {
+                doc.set(&c.section, &c.key, &default);
+            }
+        }
+    }
+}
+
+/// Apply the automatic fixups that match this game to `BepInEx.cfg`.
+/// Returns the names of the ones that changed something.
+pub fn apply_auto(game_dir: &Path, fixups: &[Fixup]) -> Result<Vec<String>> {
+    let env = Environment::detect(game_dir);
+    let path = bepinex::bep_config_path(game_dir);
+    let mut doc = CfgDocument::load_or_default(&path)?;
+    let mut applied = Vec::new();
+    for f in fixups {
+        if f.auto && f.when.matches(&env) && !f.is_applied(&doc) {
+            f.apply(&mut doc);
+            applied.push(f.name.clone());
+        }
+    }
+    if !applied.is_empty() {
+        doc.save(&path)?;
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn unity6_fixup() -> Fixup {
+        toml::from_str(
+            r#"
+            id = "unity6"
+            name = "Unity 6 workaround"
+            auto = true
+            when = { unity_version = "6000." }
+            changes = [{ section = "Logging", key = "UnityLogListening", value = "false" }]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_conditions_and_apply_auto() {
+        let game = tempfile::tempdir().unwrap();
+        let game_dir = game.path();
+        let fixups = vec![unity6_fixup()];
+
+        fs::create_dir_all(game_dir.join("Restaurats_Data")).unwrap();
+        fs::write(
+            game_dir.join("Restaurats_Data/globalgamemanagers"),
+            b"\0\0\0\0\0\0\x01\x2c\0\0\0\x16\0\0\0\x006000.0.23f1\0\x13\0\0\0",
+        )
+        .unwrap();
+        let env = Environment::detect(game_dir);
+        assert_eq!(env.unity_version.as_deref(), Some("6000.0.23f1"));
+        assert!(fixups[0].when.matches(&env));
+        let older = Environment {
+            unity_version: Some("2022.3.10f1".into()),
+            bepinex_version: None,
+        };
+        assert!(!fixups[0].when.matches(&older));
+
+        assert_eq!(
+            apply_auto(game_dir, &fixups).unwrap(),
+            ["Unity 6 workaround"]
+        );
+        assert!(apply_auto(game_dir, &fixups).unwrap().is_empty());
+        let doc = CfgDocument::load(&bepinex::bep_config_path(game_dir)).unwrap();
+        assert!(fixups[0].is_applied(&doc));
+    }
+}
@@ -0,0 +1,173 @@
+//! What the game folder says about the game: scripting backend, architecture
+//! and Unity version.
+
+use crate::bepinex::{Arch, Runtime};
+use crate::pe;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GameInfo {
+    /// Executable next to the `<Game>_Data` folder, e.g. `Restaurats.exe`.
+    pub exe: Option<String>,
+    /// IL2CPP when `GameAssembly.dll` exists, Mono when
+    /// `<Game>_Data/Managed/Assembly-CSharp.dll` does.
+    pub runtime: Option<Runtime>,
+    /// Machine type of the executable.
+    pub arch: Option<Arch>,
+    /// e.g. `6000.0.23f1`.
+    pub unity_version: Option<String>,
+}
+
+impl GameInfo {
+    pub fn detect(game_dir: &Path) -> Self {
+        let data_dir = fs::read_dir(game_dir).ok().and_then(|d| {
+            d.flatten()
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .find(|n| n.ends_with("_Data") && game_dir.join(n).is_dir())
+        });
+        let exe = data_dir
+            .as_ref()
+            .map(|d| format!("{}.exe", d.trim_end_matches("_Data")))
+            .filter(|exe| game_dir.join(exe).is_file());
+        let runtime = if game_dir.join("GameAssembly.dll").exists() {
+            Some(Runtime::Il2Cpp)
+        } else if data_dir.as_ref().is_some_and(|d| {
+            game_dir
+                .join(d)
+                .join("Managed/Assembly-CSharp.dll")
+                .exists()
+        }) {
+            Some(Runtime::Mono)
+        } else {
+            None
+        };
+        let arch = exe
+            .as_ref()
+            .and_then(|exe| fs::read(game_dir.join(exe)).ok())
+            .and_then(|bytes| match pe::machine(&bytes)? {
+                pe::MACHINE_AMD64 => Some(Arch::X64),
+                pe::MACHINE_I386 => Some(Arch::X86),
+                _ => None,
+            });
+        let unity_version = data_dir
+            .and_then(|d| unity_version_from_data(&game_dir.join(d)))
+            .or_else(|| unity_version_from_player(game_dir));
+        Self {
+            exe,
+            runtime,
+            arch,
+            unity_version,
+        }
+    }
+
+    /// `configured`, a BepInEx release URL, switched to the flavor this game
+    /// needs, e.g. `...-Unity.IL2CPP-win-x64-...` to `...-Unity.Mono-win-x86-...`.
+    /// Parts that could not be detected are left as configured.
+    pub fn bepinex_url(&self, configured: &str) -> String {
+        let mut url = configured.to_string();
+        if let Some(runtime) = self.runtime {
+            let want = format!("Unity.{runtime}-");
+            for have in ["Unity.IL2CPP-", "Unity.Mono-"] {
+                url = url.replace(have, &want);
+            }
+        }
+        if let Some(arch) = self.arch {
+            let want = format!("win-{arch}");
+            for have in ["win-x64", "win-x86", "win_x64", "win_x86"] {
+                url = url.replace(have, &want);
+            }
+        }
+        url
+    }
+}
+
+impl std::fmt::Display for GameInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(v) = &self.unity_version {
+            parts.push(format!("Unity {v}"));
+        }
+        if let Some(runtime) = self.runtime {
+            parts.push(runtime.to_string());
+        }
+        if let Some(arch) = self.arch {
+            parts.push(arch.to_string());
+        }
+        if parts.is_empty() {
+            parts.push("unknown game build".to_string());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Unity version from the header of `globalgamemanagers`, which starts with
+/// the editor version string.
+fn unity_version_from_data(data_dir: &Path) -> Option<String> {
+    let mut header = Vec::new();
+    File::open(data_dir.join("globalgamemanagers"))
+        .ok()?
+        .take(256)
+        .read_to_end(&mut header)
+        .ok()?;
+    header
+        .split(|b| !(b.is_ascii_alphanumeric() || *b == b'.'))
+        .filter_map(|run| std::str::from_utf8(run).ok())
+        .find(|s| is_unity_version(s))
+        .map(str::to_string)
+}
+
+/// Unity version from `UnityPlayer.dll`, whose version info reads e.g.
+/// `6000.0.23f1 (1c4764c07fb4)`.
+fn unity_version_from_player(game_dir: &Path) -> Option<String> {
+    let bytes = fs::read(game_dir.join("UnityPlayer.dll")).ok()?;
+    ["ProductVersion", "FileVersion"]
+        .iter()
+        .filter_map(|key| pe::version_string(&bytes, key))
+        .find_map(|v| {
+            let first = v.split_whitespace().next()?.to_string();
+            is_unity_version(&first).then_some(first)
+        })
+}
+
+fn is_unity_version(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('.').collect();
+    parts.len() == 3
+        && !parts[0].is_empty()
+        && parts[0].chars().all(|c| c.is_ascii_digit())
+        && parts[2].starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_mono_x86_game() {
+        let game = tempfile::tempdir().unwrap();
+        let g = game.path();
+        fs::create_dir_all(g.join("Restaurats_Data/Managed")).unwrap();
+        fs::write(g.join("Restaurats_Data/Managed/Assembly-CSharp.dll"), b"x").unwrap();
+        fs::write(g.join("Restaurats.exe"), pe::test_image(pe::MACHINE_I386)).unwrap();
+        let mut player = pe::test_image(pe::MACHINE_I386);
+        player.extend(
+            "FileVersion\0"
+                .encode_utf16()
+                .chain("2022.3.10f1 (ff3792e53c62)\0".encode_utf16())
+                .flat_map(u16::to_le_bytes),
+        );
+        fs::write(g.join("UnityPlayer.dll"), player).unwrap();
+
+        let info = GameInfo::detect(g);
+        assert_eq!(info.exe.as_deref(), Some("Restaurats.exe"));
+        assert_eq!(info.runtime, Some(Runtime::Mono));
+        assert_eq!(info.arch, Some(Arch::X86));
+        assert_eq!(info.unity_version.as_deref(), Some("2022.3.10f1"));
+        assert_eq!(
+            info.bepinex_url("https://x/752/BepInEx-Unity.IL2CPP-win-x64-6.0.0-be.752.zip"),
+            "https://x/752/BepInEx-Unity.Mono-win-x86-6.0.0-be.752.zip"
+        );
+        assert_eq!(GameInfo::default().bepinex_url("a-win-x64"), "a-win-x64");
+    }
+}
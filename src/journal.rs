@@ -0,0 +1,364 @@
+use crate::hash;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Oldest operations beyond this are dropped together with their backups.
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OpKind {
+    InstallMod,
+    UninstallMod,
+    InstallDll,
+    InstallBepInEx,
+    RepairBepInEx,
+    UninstallBepInEx,
+    ConfigFixup,
+    EditConfig,
+    ImportModpack,
+    ImportProfile,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum FileState {
+    Absent,
+    Present { sha256: String, size: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: String,
+    pub before: FileState,
+    pub after: FileState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirChange {
+    pub path: String,
+    pub existed_before: bool,
+    pub exists_after: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: u64,
+    pub kind: OpKind,
+    pub label: String,
+    pub game_dir: String,
+    pub timestamp: u64,
+    pub files: Vec<FileChange>,
+    pub dirs: Vec<DirChange>,
+    pub undone: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Journal {
+    pub next_id: u64,
+    pub entries: Vec<Operation>,
+}
+
+pub fn default_root() -> PathBuf {
+    crate::config::data_dir().join("journal")
+}
+
+fn journal_path(root: &Path) -> PathBuf {
+    root.join("journal.json")
+}
+
+fn blob_path(root: &Path, sha256: &str) -> PathBuf {
+    root.join("blobs").join(sha256)
+}
+
+fn game_key(game_dir: &Path) -> String {
+    game_dir.display().to_string()
+}
+
+fn state_of(path: &Path) -> Result<FileState> {
+    if !path.is_file() {
+        return Ok(FileState::Absent);
+    }
+    Ok(FileState::Present {
+        sha256: hash::sha256_file(path)?,
+        size: fs::metadata(path)?.len(),
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Journal {
+    pub fn load(root: &Path) -> Self {
+        fs::read_to_string(journal_path(root))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, root: &Path) -> Result<()> {
+        fs::create_dir_all(root)?;
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(journal_path(root), data)?;
+        Ok(())
+    }
+
+    pub fn last_undoable(&self, game_dir: &Path) -> Option<&Operation> {
+        let key = game_key(game_dir);
+        self.entries
+            .iter()
+            .rev()
+            .find(|op| !op.undone && op.game_dir == key)
+    }
+
+    /// Drop old entries and delete backups nothing can restore any more.
+    fn prune(&mut self, root: &Path) {
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+        let live: HashSet<&str> = self
+            .entries
+            .iter()
+            .filter(|op| !op.undone)
+            .flat_map(|op| op.files.iter())
+            .filter_map(|ch| match &ch.before {
+                FileState::Present { sha256, .. } => Some(sha256.as_str()),
+                FileState::Absent => None,
+            })
+            .collect();
+        if let Ok(rd) = fs::read_dir(root.join("blobs")) {
+            for e in rd.flatten() {
+                let name = e.file_name().to_string_lossy().to_string();
+                if !live.contains(name.as_str()) {
+                    let _ = fs::remove_file(e.path());
+                }
+            }
+        }
+    }
+}
+
+/// Captures the before-state of every path an operation is about to touch,
+/// then records the after-state on commit.
+pub struct Recorder {
+    root: PathBuf,
+    game_dir: PathBuf,
+    kind: OpKind,
+    label: String,
+    files: Vec<(String, FileState)>,
+    dirs: Vec<(String, bool)>,
+}
+
+impl Recorder {
+    pub fn new(root: &Path, game_dir: &Path, kind: OpKind, label: impl Into<String>) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            game_dir: game_dir.to_path_buf(),
+            kind,
+            label: label.into(),
+            files: Vec::new(),
+            dirs: Vec::new(),
+        }
+    }
+
+    /// Back up `rel` (relative to the game dir) before it is written or removed.
+    pub fn track_file(&mut self, rel: &str) -> Result<()> {
+        let rel = rel.replace('\\', "/");
+        if self.files.iter().any(|(p, _)| *p == rel) {
+            return Ok(());
+        }
+        if let Some((parent, _)) = rel.rsplit_once('/') {
+            self.track_dir(parent);
+        }
+        let before = state_of(&self.game_dir.join(&rel))?;
+        if let FileState::Present { sha256, .. } = &before {
+            let blob = blob_path(&self.root, sha256);
+            if !blob.exists() {
+                fs::create_dir_all(self.root.join("blobs"))?;
+                fs::copy(self.game_dir.join(&rel), &blob)?;
+            }
+        }
+        self.files.push((rel, before));
+        Ok(())
+    }
+
+    /// Remember whether `rel` and its ancestors existed, so undo can recreate
+    /// or remove them.
+    pub fn track_dir(&mut self, rel: &str) {
+        let rel = rel.replace('\\', "/");
+        let rel = rel.trim_end_matches('/');
+        if rel.is_empty() || self.dirs.iter().any(|(p, _)| p == rel) {
+            return;
+        }
+        if let Some((parent, _)) = rel.rsplit_once('/') {
+            self.track_dir(parent);
+        }
+        let existed = self.game_dir.join(rel).is_dir();
+        self.dirs.push((rel.to_string(), existed));
+    }
+
+    /// Append the operation to the journal. Returns `None` when nothing on
+    /// disk actually changed.
+    pub fn commit(self) -> Result<Option<u64>> {
+        let mut files = Vec::new();
+        for (path, before) in self.files {
+            let after = state_of(&self.game_dir.join(&path))?;
+            if after != before {
+                files.push(FileChange {
+                    path,
+                    before,
+                    after,
+                });
+            }
+        }
+        let dirs: Vec<DirChange> = self
+            .dirs
+            .into_iter()
+            .map(|(path, existed_before)| {
+                let exists_after = self.game_dir.join(&path).is_dir();
+                DirChange {
+                    path,
+                    existed_before,
+                    exists_after,
+                }
+            })
+            .filter(|d| d.existed_before != d.exists_after)
+            .collect();
+        if files.is_empty() && dirs.is_empty() {
+            return Ok(None);
+        }
+
+        let mut journal = Journal::load(&self.root);
+        let id = journal.next_id;
+        journal.next_id += 1;
+        journal.entries.push(Operation {
+            id,
+            kind: self.kind,
+            label: self.label,
+            game_dir: game_key(&self.game_dir),
+            timestamp: now_secs(),
+            files,
+            dirs,
+            undone: false,
+        });
+        journal.prune(&self.root);
+        journal.save(&self.root)?;
+        Ok(Some(id))
+    }
+}
+
+/// Reverse the most recent operation on `game_dir`. Refuses if any file it
+/// touched has changed since, because the result would no longer be exact.
+pub fn undo_last(root: &Path, game_dir: &Path) -> Result<Option<Operation>> {
+    let mut journal = Journal::load(root);
+    let key = game_key(game_dir);
+    let Some(pos) = journal
+        .entries
+        .iter()
+        .rposition(|op| !op.undone && op.game_dir == key)
+    else {
+        return Ok(None);
+    };
+    let op = journal.entries[pos].clone();
+
+    for ch in &op.files {
+        if state_of(&game_dir.join(&ch.path))? != ch.after {
+            return Err(anyhow!(
+                "{} was modified after \"{}\"; refusing to undo",
+                ch.path,
+                op.label
+            ));
+        }
+        if let FileState::Present { sha256, .. } = &ch.before
+            && !blob_path(root, sha256).exists()
+        {
+            return Err(anyhow!("Backup of {} is missing", ch.path));
+        }
+    }
+
+    for d in op.dirs.iter().filter(|d| d.existed_before) {
+        fs::create_dir_all(game_dir.join(&d.path))?;
+    }
+    for ch in &op.files {
+        let target = game_dir.join(&ch.path);
+        match &ch.before {
+            FileState::Absent => {
+                if target.exists() {
+                    fs::remove_file(&target)?;
+                }
+            }
+            FileState::Present { sha256, .. } => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(blob_path(root, sha256), &target)?;
+            }
+        }
+    }
+    let mut created: Vec<&DirChange> = op.dirs.iter().filter(|d| !d.existed_before).collect();
+    created.sort_by_key(|d| std::cmp::Reverse(d.path.matches('/').count()));
+    for d in created {
+        let _ = fs::remove_dir(game_dir.join(&d.path));
+    }
+
+    journal.entries[pos].undone = true;
+    journal.prune(root);
+    journal.save(root)?;
+    Ok(Some(op))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_restores_overwritten_and_removes_created() {
+        let root = tempfile::tempdir().unwrap();
+        let game = tempfile::tempdir().unwrap();
+        let game_dir = game.path();
+        fs::create_dir_all(game_dir.join("BepInEx/plugins")).unwrap();
+        fs::write(game_dir.join("BepInEx/plugins/a.dll"), b"old").unwrap();
+
+        let mut rec = Recorder::new(root.path(), game_dir, OpKind::InstallMod, "Install test");
+        rec.track_file("BepInEx/plugins/a.dll").unwrap();
+        rec.track_file("BepInEx/plugins/Sub/b.dll").unwrap();
+        fs::write(game_dir.join("BepInEx/plugins/a.dll"), b"new").unwrap();
+        fs::create_dir_all(game_dir.join("BepInEx/plugins/Sub")).unwrap();
+        fs::write(game_dir.join("BepInEx/plugins/Sub/b.dll"), b"b").unwrap();
+        assert!(rec.commit().unwrap().is_some());
+
+        let op = undo_last(root.path(), game_dir)
+            .unwrap()
+            .expect("an operation");
+        assert_eq!(op.label, "Install test");
+        assert_eq!(
+            fs::read(game_dir.join("BepInEx/plugins/a.dll")).unwrap(),
+            b"old"
+        );
+        assert!(!game_dir.join("BepInEx/plugins/Sub").exists());
+        assert!(undo_last(root.path(), game_dir).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_undo_refuses_after_external_change() {
+        let root = tempfile::tempdir().unwrap();
+        let game = tempfile::tempdir().unwrap();
+        let game_dir = game.path();
+
+        let mut rec = Recorder::new(root.path(), game_dir, OpKind::InstallDll, "Install dll");
+        rec.track_file("x.dll").unwrap();
+        fs::write(game_dir.join("x.dll"), b"1").unwrap();
+        rec.commit().unwrap();
+        fs::write(game_dir.join("x.dll"), b"2").unwrap();
+
+        assert!(undo_last(root.path(), game_dir).is_err());
+        assert!(game_dir.join("x.dll").exists());
+    }
+}
@@ -1,26 +1,51 @@
 #![windows_subsystem = "windows"]
 
 mod bepinex;
+mod cache;
+mod catalog;
 mod config;
-mod poller;
+mod configs;
+mod fixups;
+mod game;
+mod hash;
+mod journal;
+mod modpack;
+mod mods;
+mod net;
+mod packages;
+mod pe;
+mod r2modman;
+mod reset;
+mod settings;
+mod snapshot;
+mod thunderstore;
 mod types;
+mod updates;
+mod verify;
+mod watcher;
 
-use std::fs::File;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, mpsc};
 
 use config::Config;
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Result, anyhow};
 use eframe::{NativeOptions, Renderer, egui};
 use egui::{Align2, Color32, TextureHandle};
-use serde::Deserialize;
-use walkdir::WalkDir;
-use zip::read::ZipArchive;
 
-use types::{AppState, ModEntry, Tab};
+use bepinex::cfg::{CfgDocument, ValueKind};
+use journal::OpKind;
+use packages::{Listing, SearchIndex};
+use thunderstore::{Package, PackageRef};
+use types::{
+    AppState, BrowseSort, CatalogTask, InstallTask, Job, MigrateWizard, ModDownload,
+    ModDownloadTask, ModEntry, ModSource, OpenConfig, PackagesTask, PendingImport,
+    PendingUninstall, Tab, UpdateCheckTask,
+};
 
 impl AppState {
     fn log(&mut self, msg: &str) {
@@ -33,137 +58,685 @@ impl AppState {
         }
         const BYTES: &[u8] =
             include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/logo.png"));
-        if let Some(tex) = load_texture_from_png_bytes(ctx, BYTES) {
+        if let Some(tex) = load_texture_from_png_bytes(ctx, "logo.png", BYTES) {
             self.logo_texture = Some(Box::new(tex));
         }
     }
 
-    fn install_mod_from_zip_path(&mut self, zip_path: &Path) -> Result<()> {
-        bepinex::ensure_dirs(&self.game_dir)?;
-        let file =
-            File::open(zip_path).with_context(|| format!("Open zip {}", zip_path.display()))?;
-        let mut zip = ZipArchive::new(file)?;
-
-        let mut installed_files: Vec<String> = Vec::new();
-        let mut mod_name: Option<String> = None;
-        let mut mod_version: Option<String> = None;
-
-        for i in 0..zip.len() {
-            let mut f = zip.by_index(i)?;
-            let name = f.name().to_string();
-            if name.to_lowercase().ends_with("manifest.json") {
-                let mut s = String::new();
-                f.read_to_string(&mut s)?;
-                #[derive(Deserialize)]
-                struct Manifest {
-                    name: Option<String>,
-                    version_number: Option<String>,
-                    version: Option<String>,
-                }
-                if let Ok(mani) = serde_json::from_str::<Manifest>(&s) {
-                    mod_name = mani.name.or(mod_name);
-                    mod_version = mani.version_number.or(mani.version).or(mod_version);
+    /// Install the mod in `zip_path`. A Thunderstore `package` and version
+    /// name the mod when the file name cannot, as for cached archives.
+    fn install_mod_from_zip_path(
+        &mut self,
+        zip_path: &Path,
+        known: Option<&ModDownload>,
+    ) -> Result<()> {
+        let plan = mods::plan_zip_install(zip_path)?;
+        let label = plan.name.clone().unwrap_or_else(|| {
+            zip_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "mod".into())
+        });
+        let mut rec = journal::Recorder::new(
+            &self.journal_root,
+            &self.game_dir,
+            OpKind::InstallMod,
+            format!("Install {label}"),
+        );
+        // Installing over an existing mod is an upgrade: its settings are
+        // merged into whatever configs the new version ships.
+        let (id, source) = match known {
+            Some(d) => (d.id.clone(), Some(d.source.clone())),
+            None => mods::zip_identity(zip_path, &plan),
+        };
+        let prev = self.mods.mods.iter().find(|m| m.id == id);
+        let stash = prev.map(|prev| configs::ConfigStash::take(&self.game_dir, &self.mods, prev));
+        // Files the old version had and the new one doesn't would otherwise
+        // linger untracked, e.g. plugin DLLs loading next to the new ones.
+        let dropped = ModEntry {
+            installed_files: prev
+                .map(|prev| mods::dropped_files(prev, &plan))
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        rec.track_file(bepinex::INDEX_FILE)?;
+        for rel in plan
+            .files
+            .iter()
+            .map(|(_, rel)| rel)
+            .chain(&dropped.installed_files)
+        {
+            rec.track_file(rel)?;
+        }
+        for rel in stash.iter().flat_map(|s| s.paths()) {
+            rec.track_file(rel)?;
+        }
+        for dir in &plan.dirs {
+            rec.track_dir(dir);
+        }
+        let res = mods::apply_zip_install(&self.game_dir, zip_path, &plan);
+        let mut merged = Ok(Vec::new());
+        if let Ok(entry) = &res {
+            mods::uninstall_files(&self.game_dir, &dropped);
+            let version = known
+                .and_then(|d| d.version.clone())
+                .or(entry.version.clone());
+            let origin = cache::Origin::new(cache::Kind::Mod, entry.name.clone(), version.clone());
+            // Downloaded mods are already in the cache.
+            let cached = match known {
+                Some(_) => hash::sha256_file(zip_path),
+                None => cache::put_file(&self.cache_root, zip_path, origin),
+            };
+            let archive_sha256 = match cached {
+                Ok(sha256) => Some(sha256),
+                Err(e) => {
+                    self.log(&format!(
+                        "Could not keep {label} in the download cache: {e}"
+                    ));
+                    None
                 }
+            };
+            self.add_mod_entry(ModEntry {
+                id,
+                version,
+                source,
+                archive_sha256,
+                ..entry.clone()
+            });
+            if let Some(stash) = &stash {
+                merged = stash.merge_into(&self.game_dir);
             }
         }
-
-        for i in 0..zip.len() {
-            let mut f = zip.by_index(i)?;
-            let raw_name = f.name().to_string();
-            let dest_rel = map_mod_zip_entry_to_game_rel(&raw_name);
-            if let Some(rel) = dest_rel {
-                let outpath = self.game_dir.join(&rel);
-                if raw_name.ends_with('/') {
-                    std::fs::create_dir_all(&outpath)?;
-                } else {
-                    if let Some(p) = outpath.parent() {
-                        std::fs::create_dir_all(p)?;
-                    }
-                    let mut out = File::create(&outpath)?;
-                    std::io::copy(&mut f, &mut out)?;
-                    installed_files.push(rel.to_string_lossy().to_string());
-                }
-            }
-        }
-
-        if installed_files.is_empty() {
-            let mut second_pass = ZipArchive::new(File::open(zip_path)?)?;
-            for i in 0..second_pass.len() {
-                let mut f = second_pass.by_index(i)?;
-                let name = f.name().to_string();
-                if !name.ends_with('/') && name.to_lowercase().ends_with(".dll") {
-                    let outpath = bepinex::plugins_dir(&self.game_dir)
-                        .join(Path::new(&name).file_name().unwrap());
-                    let mut out = File::create(&outpath)?;
-                    std::io::copy(&mut f, &mut out)?;
-                    installed_files.push(format!(
-                        "BepInEx/plugins/{}",
-                        outpath.file_name().unwrap().to_string_lossy()
+        self.commit_journal(rec);
+        res?;
+        self.log("Mod installed.");
+        match merged {
+            Ok(reports) if !reports.is_empty() => {
+                for r in &reports {
+                    self.log(&format!(
+                        "Merged {}: {} kept, {} new, {} removed settings.",
+                        r.file,
+                        r.kept.len(),
+                        r.added.len(),
+                        r.removed.len()
                     ));
                 }
+                self.merge_reports = reports;
             }
+            Ok(_) => {}
+            Err(e) => self.log(&format!("Could not merge config files: {e}")),
         }
+        Ok(())
+    }
 
-        if installed_files.is_empty() {
-            return Err(anyhow!("No installable files found in zip"));
+    fn install_mod_from_dll_path(&mut self, dll_path: &Path) -> Result<()> {
+        let rel = mods::dll_dest_rel(dll_path)?;
+        let mut rec = journal::Recorder::new(
+            &self.journal_root,
+            &self.game_dir,
+            OpKind::InstallDll,
+            format!("Install {}", dll_path.display()),
+        );
+        rec.track_file(bepinex::INDEX_FILE)?;
+        rec.track_file(&rel)?;
+        let res = mods::install_dll(&self.game_dir, dll_path);
+        if let Ok(entry) = &res {
+            self.add_mod_entry(entry.clone());
         }
+        self.commit_journal(rec);
+        res?;
+        self.log("Mod installed.");
+        Ok(())
+    }
 
-        let id = zip_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("mod")
-            .to_string();
-        let entry = ModEntry {
-            id: id.clone(),
-            name: mod_name.clone().unwrap_or_else(|| id.clone()),
-            version: mod_version.clone(),
-            source_zip: Some(zip_path.display().to_string()),
-            installed_files,
-        };
+    fn add_mod_entry(&mut self, entry: ModEntry) {
         self.mods.mods.retain(|m| m.id != entry.id);
         self.mods.mods.push(entry);
         let _ = bepinex::save_index(&self.game_dir, &self.mods);
-        self.log("Mod installed.");
-        Ok(())
     }
 
-    fn uninstall_mod(&mut self, idx: usize) {
+    /// Remove a mod. With `settings`, the config files its plugins generated
+    /// are deleted too, after being copied to a backup folder.
+    fn uninstall_mod(&mut self, idx: usize, settings: &[String]) {
         if idx >= self.mods.mods.len() {
             return;
         }
         let m = self.mods.mods[idx].clone();
-        let mut removed_any = false;
-        for rel in &m.installed_files {
-            let p = self.game_dir.join(rel);
-            if p.exists() {
-                let _ = std::fs::remove_file(&p);
-                removed_any = true;
+        let mut backup = None;
+        if !settings.is_empty() {
+            match configs::backup(
+                &configs::default_backup_root(),
+                &self.game_dir,
+                &m.id,
+                settings,
+            ) {
+                Ok(dir) => backup = Some(dir),
+                Err(e) => {
+                    self.log(&format!(
+                        "Uninstall failed, could not back up settings: {e}"
+                    ));
+                    return;
+                }
             }
         }
-        for entry in WalkDir::new(self.game_dir.join("BepInEx"))
-            .min_depth(1)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .collect::<Vec<_>>()
-        {
-            if entry.file_type().is_dir() {
-                let _ = std::fs::remove_dir(entry.path());
+        let mut rec = journal::Recorder::new(
+            &self.journal_root,
+            &self.game_dir,
+            OpKind::UninstallMod,
+            format!("Uninstall {}", m.name),
+        );
+        let tracked = std::iter::once(bepinex::INDEX_FILE)
+            .chain(m.installed_files.iter().map(String::as_str))
+            .chain(settings.iter().map(String::as_str))
+            .try_for_each(|rel| rec.track_file(rel));
+        if let Err(e) = tracked {
+            self.log(&format!("Uninstall failed: {e}"));
+            return;
+        }
+        for dir in mods::prunable_dirs(&m) {
+            rec.track_dir(&dir);
+        }
+        let removed_any = mods::uninstall_files(&self.game_dir, &m);
+        for rel in settings {
+            if let Err(e) = fs::remove_file(self.game_dir.join(rel)) {
+                self.log(&format!("Could not delete {rel}: {e}"));
             }
         }
         self.mods.mods.remove(idx);
         let _ = bepinex::save_index(&self.game_dir, &self.mods);
+        self.commit_journal(rec);
+        if !settings.is_empty() {
+            let mut links = configs::ConfigLinks::load(&self.game_dir);
+            links.forget(settings);
+            let _ = links.save(&self.game_dir);
+        }
         if removed_any {
             self.log(&format!("Uninstalled {}", m.name));
         }
+        if let Some(dir) = backup {
+            self.log(&format!(
+                "Deleted {} settings files; backup in {}",
+                settings.len(),
+                dir.display()
+            ));
+        }
+    }
+
+    fn commit_journal(&mut self, rec: journal::Recorder) {
+        if let Err(e) = rec.commit() {
+            self.log(&format!("Could not record operation for undo: {e}"));
+        }
+        self.refresh_last_operation();
+    }
+
+    fn refresh_last_operation(&mut self) {
+        self.last_operation = journal::Journal::load(&self.journal_root)
+            .last_undoable(&self.game_dir)
+            .map(|op| op.label.clone());
+    }
+
+    fn undo_last_operation(&mut self) {
+        match journal::undo_last(&self.journal_root, &self.game_dir) {
+            Ok(Some(op)) => self.log(&format!("Undid: {}", op.label)),
+            Ok(None) => self.log("Nothing to undo."),
+            Err(e) => self.log(&format!("Undo failed: {e}")),
+        }
+        self.reload_game_state();
+    }
+
+    fn set_game_dir(&mut self, dir: PathBuf) {
+        self.game_dir = dir;
+        self.open_config = None;
+        // Dropping the old watcher stops it.
+        self.watcher = None;
+        let ctx = self.egui_ctx.clone();
+        match watcher::GameWatcher::start(&self.game_dir, move || ctx.request_repaint()) {
+            Ok(w) => self.watcher = Some(w),
+            Err(e) => self.log(&format!("Not watching the game folder for changes: {e}")),
+        }
+        self.reload_game_state();
+    }
+
+    /// Refresh what files changed outside the manager may have affected.
+    fn apply_watch_changes(&mut self, changes: BTreeSet<watcher::Change>) {
+        use watcher::Change;
+        let was_ready = self.bep_state == bepinex::BepState::Ready;
+        if changes.contains(&Change::Plugins) {
+            self.mods = bepinex::load_index(&self.game_dir);
+            self.plugin_scan = mods::scan_plugins(&self.game_dir, &self.mods);
+        }
+        let first_launch = matches!(
+            self.bep_state,
+            bepinex::BepState::Extracted | bepinex::BepState::AwaitingFirstLaunch
+        );
+        if changes.contains(&Change::Setup)
+            || (changes.contains(&Change::FirstLaunch) && first_launch)
+        {
+            self.bep_state = bepinex::detect_state(&self.game_dir);
+            self.bep_info = bepinex::detect_bepinex(&self.game_dir);
+            if !was_ready && self.bep_state == bepinex::BepState::Ready {
+                self.log("BepInEx finished its first launch and is ready.");
+            }
+        }
+    }
+
+    /// Re-read everything derived from the game folder after it may have
+    /// changed underneath us.
+    fn reload_game_state(&mut self) {
+        self.mods = bepinex::load_index(&self.game_dir);
+        self.plugin_scan = mods::scan_plugins(&self.game_dir, &self.mods);
+        self.bep_state = bepinex::detect_state(&self.game_dir);
+        self.bep_info = bepinex::detect_bepinex(&self.game_dir);
+        self.game_info = game::GameInfo::detect(&self.game_dir);
+        self.snapshots = snapshot::list(&self.snapshot_root, &self.game_dir);
+        self.cache_entries = cache::list(&self.cache_root);
+        if let Err(e) = configs::link_new(&self.game_dir, &self.mods) {
+            self.log(&format!("Could not link config files to mods: {e}"));
+        }
+        self.config_files = configs::list(&self.game_dir, &self.mods);
+        self.fixup_env = fixups::Environment::detect(&self.game_dir);
+        self.bep_cfg = CfgDocument::load_or_default(&bepinex::bep_config_path(&self.game_dir))
+            .unwrap_or_default();
+        if let Some(open) = &self.open_config
+            && !open.dirty
+        {
+            let rel = open.rel.clone();
+            self.open_config_file(&rel);
+        }
+        self.refresh_last_operation();
+    }
+
+    /// Trim the download cache to `limit_mb`, keeping the archives the
+    /// current setup was installed from. Returns what was removed. Only run
+    /// when the limit changes, on request, or after a download added to the
+    /// cache, since undo and repairs may still want older archives.
+    fn prune_cache(&mut self, limit_mb: u64) -> Option<(usize, u64)> {
+        let keep = cache::in_use(&self.mods);
+        let res = cache::prune(&self.cache_root, limit_mb * 1024 * 1024, &keep);
+        self.cache_entries = cache::list(&self.cache_root);
+        res.inspect_err(|e| self.log(&format!("Could not prune the download cache: {e}")))
+            .ok()
+    }
+
+    fn open_config_file(&mut self, rel: &str) {
+        match CfgDocument::load(&self.game_dir.join(rel)) {
+            Ok(doc) => {
+                self.open_config = Some(OpenConfig {
+                    rel: rel.to_string(),
+                    doc,
+                    dirty: false,
+                })
+            }
+            Err(e) => {
+                self.open_config = None;
+                self.log(&format!("Could not read {rel}: {e}"));
+            }
+        }
+    }
+
+    fn set_fixup(&mut self, idx: usize, on: bool) {
+        let Some(fixup) = self.config.fixups.get(idx).cloned() else {
+            return;
+        };
+        let path = bepinex::bep_config_path(&self.game_dir);
+        let verb = if on { "Apply" } else { "Revert" };
+        let mut rec = journal::Recorder::new(
+            &self.journal_root,
+            &self.game_dir,
+            OpKind::ConfigFixup,
+            format!("{verb} {}", fixup.name),
+        );
+        let res = rec.track_file(bepinex::CONFIG_FILE).and_then(|_| {
+            let mut doc = CfgDocument::load_or_default(&path)?;
+            if on {
+                fixup.apply(&mut doc);
+            } else {
+                fixup.revert(&mut doc);
+            }
+            doc.save(&path)
+        });
+        self.commit_journal(rec);
+        match res {
+            Ok(()) => self.log(&format!("{verb}: {}", fixup.name)),
+            Err(e) => self.log(&format!("{verb} {} failed: {e}", fixup.name)),
+        }
+        self.reload_game_state();
+    }
+
+    fn save_open_config(&mut self) {
+        let Some(open) = &mut self.open_config else {
+            return;
+        };
+        let res = configs::save(&self.game_dir, &self.journal_root, &open.rel, &open.doc);
+        let rel = open.rel.clone();
+        match res {
+            Ok(()) => {
+                open.dirty = false;
+                self.log(&format!("Saved {rel}"));
+            }
+            Err(e) => self.log(&format!("Saving {rel} failed: {e}")),
+        }
+        self.refresh_last_operation();
+    }
+
+    /// Run `work` on a worker thread; its message is logged when it finishes.
+    fn spawn_job<F>(&mut self, name: &str, work: F)
+    where
+        F: FnOnce() -> Result<String> + Send + 'static,
+    {
+        if self.is_busy {
+            return;
+        }
+        self.is_busy = true;
+        let result = Arc::new(Mutex::new(None));
+        self.job = Some(Job {
+            name: name.to_string(),
+            result: Arc::clone(&result),
+        });
+        std::thread::spawn(move || {
+            let res = work().map_err(|e| e.to_string());
+            *result.lock().unwrap() = Some(res);
+        });
+    }
+
+    fn start_create_snapshot(&mut self) {
+        let label = match self.snapshot_label.trim() {
+            "" => "Manual snapshot".to_string(),
+            l => l.to_string(),
+        };
+        self.snapshot_label.clear();
+        let root = self.snapshot_root.clone();
+        let game_dir = self.game_dir.clone();
+        self.spawn_job("Snapshot", move || {
+            let meta = snapshot::create(&root, &game_dir, &label)?;
+            Ok(format!(
+                "Snapshot \"{}\" created ({}).",
+                meta.label,
+                format_size(meta.size)
+            ))
+        });
+    }
+
+    fn start_export_modpack(&mut self, dest: PathBuf) {
+        let game_dir = self.game_dir.clone();
+        let index = self.mods.clone();
+        let cache_root = self.cache_root.clone();
+        let options = modpack::ExportOptions {
+            include_archives: self.pack_include_archives,
+            include_configs: self.pack_include_configs,
+        };
+        self.spawn_job("Modpack export", move || {
+            let pack = modpack::export(&game_dir, &index, &dest, &cache_root, options)?;
+            Ok(format!(
+                "Exported {} mods to {}.",
+                pack.mods.len(),
+                dest.display()
+            ))
+        });
+    }
+
+    fn start_import_modpack(&mut self, pack_path: PathBuf) {
+        let game_dir = self.game_dir.clone();
+        let journal_root = self.journal_root.clone();
+        let snapshot_root = self.snapshot_root.clone();
+        let cache_root = self.cache_root.clone();
+        let http = self.http();
+        let transfer = self.new_transfer();
+        self.spawn_job("Modpack import", move || {
+            let prepared = modpack::prepare_import(&pack_path, &cache_root, |m, url| {
+                let origin =
+                    cache::Origin::new(cache::Kind::Mod, m.name.clone(), m.version.clone());
+                cache::fetch(&cache_root, url, &http, &m.expected(), &transfer, origin)
+            })?;
+            // Only a pack that passed its checks is worth a snapshot.
+            if bepinex::is_bep_installed(&game_dir) {
+                let label = format!("Before modpack import {}", prepared.name());
+                snapshot::create(&snapshot_root, &game_dir, &label)?;
+            }
+            let report = modpack::import(&game_dir, &journal_root, &prepared)?;
+            Ok(format!(
+                "Modpack imported: {} mods installed, {} removed, {} config files; all hashes verified.",
+                report.installed, report.removed, report.configs
+            ))
+        });
+    }
+
+    fn start_import_r2z(&mut self, r2z_path: PathBuf) {
+        let game_dir = self.game_dir.clone();
+        let journal_root = self.journal_root.clone();
+        let snapshot_root = self.snapshot_root.clone();
+        let cache_root = self.cache_root.clone();
+        let http = self.http();
+        let transfer = self.new_transfer();
+        self.spawn_job("r2modman import", move || {
+            let contents = r2modman::read_r2z(&r2z_path)?;
+            if bepinex::is_bep_installed(&game_dir) {
+                let label = format!("Before r2modman import {}", contents.profile.profile_name);
+                snapshot::create(&snapshot_root, &game_dir, &label)?;
+            }
+            let report =
+                r2modman::import_r2z(&game_dir, &contents, &journal_root, |pkg, version| {
+                    thunderstore::fetch_package(
+                        &cache_root,
+                        thunderstore::BASE_URL,
+                        pkg,
+                        version,
+                        &http,
+                        &transfer,
+                    )
+                })?;
+            let mut msg = format!(
+                "Imported r2modman profile \"{}\": {} mods installed, {} removed, {} config files.",
+                contents.profile.profile_name, report.installed, report.removed, report.configs
+            );
+            if report.disabled > 0 {
+                msg.push_str(&format!(" {} of them are disabled.", report.disabled));
+            }
+            if !report.skipped.is_empty() {
+                msg.push_str(&format!(" Skipped: {}.", report.skipped.join(", ")));
+            }
+            Ok(msg)
+        });
+    }
+
+    fn start_export_r2z(&mut self, dest: PathBuf) {
+        let game_dir = self.game_dir.clone();
+        let index = self.mods.clone();
+        self.spawn_job("r2modman export", move || {
+            let profile_name = dest
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "Restaurats".into());
+            let report = r2modman::export_r2z(&game_dir, &index, &profile_name, &dest)?;
+            let mut msg = format!(
+                "Exported {} mods and {} config files to {}.",
+                report.exported,
+                report.configs,
+                dest.display()
+            );
+            if !report.skipped.is_empty() {
+                msg.push_str(&format!(
+                    " Not on Thunderstore, left out: {}.",
+                    report.skipped.join(", ")
+                ));
+            }
+            Ok(msg)
+        });
+    }
+
+    fn start_migrate_profile(&mut self, scan: r2modman::ProfileScan, include_configs: bool) {
+        let game_dir = self.game_dir.clone();
+        let journal_root = self.journal_root.clone();
+        let snapshot_root = self.snapshot_root.clone();
+        self.spawn_job("r2modman migration", move || {
+            if bepinex::is_bep_installed(&game_dir) {
+                snapshot::create(&snapshot_root, &game_dir, "Before r2modman migration")?;
+            }
+            let report =
+                r2modman::deploy_profile_dir(&game_dir, &scan, &journal_root, include_configs)?;
+            let mut msg = format!(
+                "Migrated r2modman profile {}: {} mods installed, {} removed, {} config files.",
+                scan.root.display(),
+                report.installed,
+                report.removed,
+                report.configs
+            );
+            if !report.skipped.is_empty() {
+                msg.push_str(&format!(" Skipped: {}.", report.skipped.join(", ")));
+            }
+            Ok(msg)
+        });
+    }
+
+    fn start_repair_bepinex(&mut self) {
+        let game_dir = self.game_dir.clone();
+        let journal_root = self.journal_root.clone();
+        let cache_root = self.cache_root.clone();
+        self.spawn_job("Repair BepInEx", move || {
+            let install = bepinex::load_index(&game_dir)
+                .bepinex
+                .ok_or_else(|| anyhow!("BepInEx was not installed by this manager"))?;
+            let broken = bepinex::repair_plan(&game_dir, &install);
+            if broken.is_empty() {
+                return Ok("BepInEx files are intact; nothing to repair.".into());
+            }
+            let mut rec = journal::Recorder::new(
+                &journal_root,
+                &game_dir,
+                OpKind::RepairBepInEx,
+                "Repair BepInEx",
+            );
+            for rel in &broken {
+                rec.track_file(rel)?;
+            }
+            let res = bepinex::repair(&game_dir, &cache_root, &install, &broken);
+            rec.commit()?;
+            res?;
+            Ok(format!("Repaired {} BepInEx files.", broken.len()))
+        });
+    }
+
+    fn start_uninstall_bepinex(&mut self, keep_user_files: bool) {
+        let game_dir = self.game_dir.clone();
+        let journal_root = self.journal_root.clone();
+        self.spawn_job("Uninstall BepInEx", move || {
+            let install = bepinex::load_index(&game_dir)
+                .bepinex
+                .ok_or_else(|| anyhow!("BepInEx was not installed by this manager"))?;
+            let (files, dirs) = bepinex::uninstall_plan(&game_dir, &install, keep_user_files);
+            let mut rec = journal::Recorder::new(
+                &journal_root,
+                &game_dir,
+                OpKind::UninstallBepInEx,
+                "Uninstall BepInEx",
+            );
+            rec.track_file(bepinex::INDEX_FILE)?;
+            for rel in &files {
+                rec.track_file(rel)?;
+            }
+            for dir in &dirs {
+                rec.track_dir(dir);
+            }
+            let res = bepinex::uninstall(&game_dir, &files, &dirs);
+            rec.commit()?;
+            res?;
+            Ok(format!(
+                "Uninstalled BepInEx ({} files removed).",
+                files.len()
+            ))
+        });
+    }
+
+    fn start_reset_to_vanilla(&mut self, entries: Vec<reset::ResetEntry>) {
+        let game_dir = self.game_dir.clone();
+        let snapshot_root = self.snapshot_root.clone();
+        self.spawn_job("Reset to vanilla", move || {
+            let tops: Vec<&str> = entries.iter().map(|e| e.rel.as_str()).collect();
+            let backup =
+                snapshot::create_with(&snapshot_root, &game_dir, "Before reset to vanilla", &tops)?;
+            reset::apply(&game_dir, &entries)?;
+            Ok(format!(
+                "Reset the game folder to vanilla; removed {} entries. Backup: snapshot \"{}\".",
+                entries.len(),
+                backup.label
+            ))
+        });
+    }
+
+    fn start_restore_snapshot(&mut self, meta: snapshot::SnapshotMeta) {
+        let root = self.snapshot_root.clone();
+        let game_dir = self.game_dir.clone();
+        self.spawn_job("Restore", move || {
+            snapshot::restore(&root, &game_dir, &meta.id)?;
+            Ok(format!("Restored snapshot \"{}\".", meta.label))
+        });
+    }
+}
+
+/// Progress bar of a running download with its Cancel button.
+fn ui_transfer(transfer: &net::Transfer, ui: &mut egui::Ui) {
+    let p = transfer.progress();
+    let name = p
+        .url
+        .rsplit('/')
+        .next()
+        .unwrap_or(&p.url)
+        .replace("%2B", "+");
+    let size = match p.total {
+        Some(total) => format!("{} / {}", format_size(p.downloaded), format_size(total)),
+        None => format_size(p.downloaded),
+    };
+    ui.horizontal(|ui| {
+        let bar = egui::ProgressBar::new(match p.total {
+            Some(total) if total > 0 => p.downloaded as f32 / total as f32,
+            _ => 0.0,
+        })
+        .desired_width(240.0)
+        .text(format!(
+            "{size} ({}/s)",
+            format_size(p.bytes_per_sec as u64)
+        ));
+        ui.add(bar).on_hover_text(&p.url);
+        ui.label(name);
+        if ui.button("Cancel").clicked() {
+            transfer.cancel();
+        }
+    });
+}
+
+fn format_size(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= MB {
+        format!("{:.1} MB", bytes as f64 / MB)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+fn format_age(timestamp: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(timestamp);
+    let secs = now.saturating_sub(timestamp);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
     }
 }
 
-fn load_texture_from_png_bytes(ctx: &egui::Context, bytes: &[u8]) -> Option<TextureHandle> {
+fn load_texture_from_png_bytes(
+    ctx: &egui::Context,
+    name: &str,
+    bytes: &[u8],
+) -> Option<TextureHandle> {
     let dyn_img = image::load_from_memory(bytes).ok()?;
     let rgba = dyn_img.to_rgba8();
     let size = [rgba.width() as usize, rgba.height() as usize];
     let color_image = egui::ColorImage::from_rgba_unmultiplied(size, &rgba);
-    Some(ctx.load_texture("logo.png", color_image, egui::TextureOptions::LINEAR))
+    Some(ctx.load_texture(name, color_image, egui::TextureOptions::LINEAR))
 }
 
 fn draw_play_button(ui: &mut egui::Ui, tex: &TextureHandle) -> egui::Response {
@@ -226,141 +799,1334 @@ fn launch_game(app: &mut AppState) {
     }
 }
 
-fn open_directory_in_explorer(path: &Path) {
-    let _ = open::that(path);
+fn open_directory_in_explorer(path: &Path) {
+    let _ = open::that(path);
+}
+
+/// Channel and build pickers, and the switch to the chosen build when it is
+/// not the installed one.
+fn ui_bepinex_build(app: &mut AppState, ui: &mut egui::Ui) {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Channel:");
+        egui::ComboBox::from_id_salt("bep_channel")
+            .selected_text(app.settings.bepinex_channel.label())
+            .show_ui(ui, |ui| {
+                for c in catalog::Channel::ALL {
+                    changed |= ui
+                        .selectable_value(&mut app.settings.bepinex_channel, c, c.label())
+                        .changed();
+                }
+            });
+        let builds =
+            catalog::builds_for(&app.catalog, app.settings.bepinex_channel, &app.game_info);
+        let selected = match &app.settings.pinned_bepinex {
+            Some(url) => format!("{} (pinned)", bepinex::BepInExInfo::from_archive_name(url)),
+            None => "Newest".to_string(),
+        };
+        ui.label("Build:");
+        egui::ComboBox::from_id_salt("bep_build")
+            .selected_text(selected)
+            .width(320.0)
+            .show_ui(ui, |ui| {
+                changed |= ui
+                    .selectable_value(&mut app.settings.pinned_bepinex, None, "Newest")
+                    .changed();
+                for b in builds {
+                    changed |= ui
+                        .selectable_value(
+                            &mut app.settings.pinned_bepinex,
+                            Some(b.url.clone()),
+                            b.info.to_string(),
+                        )
+                        .changed();
+                }
+            });
+        let fetching = app.catalog_task.is_some();
+        if ui
+            .add_enabled(
+                !fetching && !app.settings.offline,
+                egui::Button::new("Refresh"),
+            )
+            .on_hover_text("Fetch the list of builds from builds.bepinex.dev and GitHub")
+            .clicked()
+        {
+            app.start_fetch_catalog();
+        }
+        if fetching {
+            ui.spinner();
+        }
+    });
+    if changed {
+        app.save_settings();
+    }
+    if app.settings.pinned_bepinex.is_none()
+        && !app.catalog.is_empty()
+        && catalog::builds_for(&app.catalog, app.settings.bepinex_channel, &app.game_info)
+            .is_empty()
+    {
+        ui.label(
+            egui::RichText::new(format!(
+                "No {} builds fit this game; the configured build is used.",
+                app.settings.bepinex_channel.label()
+            ))
+            .weak(),
+        );
+    }
+    let target = bepinex::BepInExInfo::from_archive_name(&app.bepinex_url());
+    if let Some(installed) = &app.bep_info
+        && target.version.is_some()
+        && !installed.same_build(&target)
+    {
+        let newer = catalog::is_newer(&target, installed);
+        ui.horizontal(|ui| {
+            if newer {
+                ui.label(format!("Newer build available: {target}"));
+            } else {
+                ui.label(format!("Selected build: {target}"));
+            }
+            if ui
+                .add_enabled(
+                    !app.is_busy,
+                    egui::Button::new(if newer { "Upgrade in place" } else { "Switch in place" }),
+                )
+                .on_hover_text("Replaces BepInEx core files; plugins, configs and the mod list are kept. A snapshot is taken first.")
+                .clicked()
+            {
+                app.start_upgrade_bepinex();
+            }
+        });
+    }
+}
+
+fn ui_getting_started(app: &mut AppState, ui: &mut egui::Ui) {
+    ui.label(format!(
+        "Game: {} ({})",
+        app.game_info.exe.as_deref().unwrap_or("not found"),
+        app.game_info
+    ));
+    ui.heading("BepInEx");
+    ui.horizontal(|ui| {
+        ui.label(format!("Status: {}", app.bep_state.label()));
+        if let Some(info) = &app.bep_info {
+            let doorstop = info
+                .doorstop
+                .as_ref()
+                .map(|d| format!("Doorstop {d}"))
+                .unwrap_or_default();
+            ui.label(info.to_string()).on_hover_text(doorstop);
+        }
+        let auto_btn = ui
+            .add_enabled(!app.is_busy, egui::Button::new("Install BepInEx (auto)"))
+            .on_hover_text(format!(
+                "Downloads {}",
+                bepinex::BepInExInfo::from_archive_name(&app.bepinex_url())
+            ));
+        if auto_btn.clicked() {
+            app.start_install_bepinex_stable_v5_async();
+        }
+    });
+    ui.label(egui::RichText::new(app.bep_state.next_step()).weak());
+    if app.bep_info.is_some() {
+        ui.horizontal(|ui| {
+            let tracked = app.mods.bepinex.is_some();
+            let hint = "BepInEx was installed before the manager tracked its files; reinstall it to enable this";
+            let repair = ui
+                .add_enabled(tracked && !app.is_busy, egui::Button::new("Repair BepInEx"))
+                .on_hover_text("Re-extract missing or changed BepInEx files")
+                .on_disabled_hover_text(hint);
+            if repair.clicked() {
+                app.start_repair_bepinex();
+            }
+            let uninstall = ui
+                .add_enabled(tracked && !app.is_busy, egui::Button::new("Uninstall BepInEx..."))
+                .on_disabled_hover_text(hint);
+            if uninstall.clicked() {
+                app.pending_bep_uninstall = Some(true);
+            }
+        });
+    }
+    if ui
+        .add_enabled(!app.is_busy, egui::Button::new("Reset to vanilla..."))
+        .on_hover_text("Remove BepInEx, doorstop, dotnet/ and every mod from the game folder")
+        .clicked()
+    {
+        app.reset_preview = Some(reset::plan(&app.game_dir));
+    }
+    ui_bepinex_build(app, ui);
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut app.custom_bep_url)
+            .on_hover_text("Custom BepInEx zip URL (e.g., BE IL2CPP build)");
+        let from_url_btn = ui.add_enabled(!app.is_busy, egui::Button::new("Install from URL"));
+        if from_url_btn.clicked() && !app.custom_bep_url.trim().is_empty() {
+            let url = app.custom_bep_url.trim().to_string();
+            app.start_install_bepinex_from_url_async(url);
+        }
+        let from_zip_btn = ui.add_enabled(!app.is_busy, egui::Button::new("Install from ZIP..."));
+        if from_zip_btn.clicked()
+            && let Some(zip) = rfd::FileDialog::new()
+                .add_filter("zip", &["zip"])
+                .pick_file()
+        {
+            app.spawn_bepinex_install(
+                move || {
+                    let mut buf = Vec::new();
+                    File::open(&zip)?.read_to_end(&mut buf)?;
+                    Ok(buf)
+                },
+                false,
+            );
+        }
+    });
+}
+
+fn ui_mods(app: &mut AppState, ui: &mut egui::Ui, _ctx: &egui::Context) {
+    let _ = draw_drop_zone(ui, "Drag a mod zip or dll here");
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut app.mod_url)
+                .hint_text("https://example.com/SomeMod.zip")
+                .desired_width(300.0),
+        );
+        let url = app.mod_url.trim().to_string();
+        if ui
+            .add_enabled(
+                !app.is_busy && !url.is_empty(),
+                egui::Button::new("Install from URL"),
+            )
+            .on_hover_text("Download the zip and install it; the mod is updated from the same URL")
+            .clicked()
+        {
+            app.mod_url.clear();
+            app.start_install_from_url(url);
+        }
+    });
+    ui.add_space(8.0);
+    ui.heading("Installed Mods");
+    if app.mods.mods.is_empty() {
+        ui.label("Drag a mod zip or dll into the box above to install.");
+    } else {
+        ui_updates_bar(app, ui);
+    }
+    egui::ScrollArea::vertical()
+        .id_salt("mods_scroll")
+        .max_height(app.config.constants.mods_max_height)
+        .show(ui, |ui| {
+            egui::Grid::new("mods_grid").striped(true).show(ui, |ui| {
+                ui.label("Name");
+                ui.label("Version");
+                ui.label("Update");
+                ui.label("");
+                ui.end_row();
+                for i in 0..app.mods.mods.len() {
+                    let m = &app.mods.mods[i];
+                    let id = m.id.clone();
+                    let updatable = m.source.is_some();
+                    if app.plugin_scan.missing.contains(&m.id) {
+                        ui.label(format!("{} (files missing)", m.name))
+                            .on_hover_text("Some of this mod's files were removed outside the manager; reinstall it");
+                    } else if m.disabled {
+                        ui.label(format!("{} (disabled)", m.name))
+                            .on_hover_text("Disabled in the imported r2modman profile; its DLLs end in .old so BepInEx skips them");
+                    } else {
+                        ui.label(&m.name);
+                    }
+                    ui.label(m.version.clone().unwrap_or_default());
+                    ui_mod_update(app, ui, &id, updatable);
+                    if ui.button("Uninstall").clicked() {
+                        let settings = configs::ConfigLinks::load(&app.game_dir)
+                            .files_of(&app.game_dir, &id);
+                        app.pending_uninstall = Some(PendingUninstall {
+                            id,
+                            settings,
+                            delete_settings: false,
+                        });
+                    }
+                    ui.end_row();
+                }
+                for rel in &app.plugin_scan.unmanaged {
+                    let name = rel.rsplit('/').next().unwrap_or(rel);
+                    ui.label(name).on_hover_text(rel);
+                    ui.label("");
+                    ui.label("");
+                    ui.label(egui::RichText::new("Not managed").weak())
+                        .on_hover_text("Added outside the manager; it is not tracked or uninstalled");
+                    ui.end_row();
+                }
+            });
+        });
+    ui.add_space(8.0);
+    ui_modpack(app, ui);
+}
+
+/// Update check controls above the installed mods.
+fn ui_updates_bar(app: &mut AppState, ui: &mut egui::Ui) {
+    let checking = app.update_task.is_some();
+    let offered = app.offered_updates();
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(!checking, egui::Button::new("Check for updates"))
+            .on_hover_text(
+                "Look for newer versions of mods installed from Thunderstore, GitHub or a URL",
+            )
+            .clicked()
+        {
+            app.start_check_updates();
+        }
+        if checking {
+            ui.spinner();
+        } else if let Some(checked) = app.updates_checked {
+            ui.weak(format!("Checked {}", format_age(checked)));
+        }
+        if ui
+            .add_enabled(
+                !offered.is_empty() && !app.is_busy,
+                egui::Button::new(format!("Update all ({})", offered.len())),
+            )
+            .on_hover_text("Install every update except those of pinned mods and skipped versions")
+            .clicked()
+        {
+            app.start_updates(offered);
+        }
+        if ui
+            .checkbox(
+                &mut app.settings.check_updates_on_startup,
+                "Check on startup",
+            )
+            .changed()
+        {
+            app.save_settings();
+        }
+    });
+}
+
+/// "Update available" badge of the installed mod `id`, with the changelog on
+/// hover, and its pin switch.
+fn ui_mod_update(app: &mut AppState, ui: &mut egui::Ui, id: &str, updatable: bool) {
+    let update = app
+        .updates
+        .iter()
+        .find(|u| u.download.id == id)
+        .filter(|u| app.settings.skipped_updates.get(id).map(String::as_str) != Some(u.version()))
+        .cloned();
+    let pinned = app.settings.pinned_mods.contains(id);
+    ui.horizontal(|ui| {
+        if let Some(update) = update {
+            let version = update.version().to_string();
+            let notes = match update.changelog.trim() {
+                "" => "No release notes.",
+                notes => notes,
+            };
+            ui.colored_label(Color32::LIGHT_GREEN, format!("Update available: {version}"))
+                .on_hover_ui(|ui| {
+                    ui.set_max_width(400.0);
+                    ui.label(notes);
+                });
+            if !pinned {
+                if ui
+                    .add_enabled(!app.is_busy, egui::Button::new("Update"))
+                    .clicked()
+                {
+                    app.start_updates(vec![update.clone()]);
+                }
+                if ui
+                    .button("Skip")
+                    .on_hover_text(format!("Don't offer {version} again"))
+                    .clicked()
+                {
+                    app.settings
+                        .skipped_updates
+                        .insert(id.to_string(), version.clone());
+                    app.save_settings();
+                }
+            }
+        }
+        let mut pin = pinned;
+        if updatable
+            && ui
+                .checkbox(&mut pin, "Pin")
+                .on_hover_text("Keep this version; updates are shown but not installed")
+                .changed()
+        {
+            if pin {
+                app.settings.pinned_mods.insert(id.to_string());
+            } else {
+                app.settings.pinned_mods.remove(id);
+            }
+            app.save_settings();
+        }
+    });
+}
+
+/// A square package icon, or an empty tile until it has loaded.
+fn ui_icon(ui: &mut egui::Ui, tex: Option<&TextureHandle>, side: f32) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(side, side), egui::Sense::hover());
+    if !ui.is_rect_visible(rect) {
+        return;
+    }
+    if let Some(tex) = tex {
+        ui.painter().image(
+            tex.id(),
+            rect,
+            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            Color32::WHITE,
+        );
+    } else {
+        ui.painter()
+            .rect_filled(rect, 4.0, ui.visuals().faint_bg_color);
+    }
+}
+
+fn ui_browse(app: &mut AppState, ui: &mut egui::Ui) {
+    let fetching = app.browse.fetch_task.is_some();
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut app.browse.query)
+                .hint_text("Search mods")
+                .desired_width(200.0),
+        );
+        egui::ComboBox::from_id_salt("browse_category")
+            .selected_text(app.browse.category.as_deref().unwrap_or("All categories"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut app.browse.category, None, "All categories");
+                for c in app.browse.index.categories() {
+                    ui.selectable_value(&mut app.browse.category, Some(c.to_string()), c);
+                }
+            });
+        egui::ComboBox::from_id_salt("browse_sort")
+            .selected_text(app.browse.sort.label())
+            .show_ui(ui, |ui| {
+                for sort in BrowseSort::ALL {
+                    ui.selectable_value(&mut app.browse.sort, sort, sort.label());
+                }
+            });
+        ui.checkbox(&mut app.browse.show_deprecated, "Show deprecated");
+        if ui
+            .add_enabled(
+                !fetching && !app.settings.offline,
+                egui::Button::new("Refresh"),
+            )
+            .on_hover_text("Check Thunderstore for changes to the package list")
+            .clicked()
+        {
+            app.start_fetch_packages();
+        }
+        if fetching {
+            ui.spinner();
+        } else if let Some(checked) = app.browse.checked {
+            ui.weak(format!("Checked {}", format_age(checked)));
+        }
+    });
+    if app.browse.packages.is_empty() {
+        ui.label(if fetching {
+            "Fetching packages from Thunderstore..."
+        } else if app.settings.offline {
+            "No package list is saved yet. Turn off Offline mode to fetch it from Thunderstore."
+        } else {
+            "No packages listed. Click Refresh to try again."
+        });
+        return;
+    }
+
+    let browse = &app.browse;
+    let mut shown = browse
+        .index
+        .search(&browse.query, browse.category.as_deref());
+    shown.retain(|&i| browse.show_deprecated || !browse.packages[i].is_deprecated);
+    let packages = &browse.packages;
+    match browse.sort {
+        BrowseSort::Downloads => shown.sort_by_key(|&i| std::cmp::Reverse(packages[i].downloads())),
+        BrowseSort::Updated => {
+            shown.sort_by(|&a, &b| packages[b].date_updated.cmp(&packages[a].date_updated))
+        }
+        BrowseSort::Name => {
+            shown.sort_by_key(|&i| packages[i].name.to_lowercase());
+        }
+    }
+    ui.label(format!(
+        "{} of {} packages",
+        shown.len(),
+        app.browse.packages.len()
+    ));
+
+    let height = app.config.constants.mods_max_height * 2.0;
+    ui.columns(2, |cols| {
+        egui::ScrollArea::vertical()
+            .id_salt("browse_list")
+            .max_height(height)
+            .show_rows(&mut cols[0], 40.0, shown.len(), |ui, rows| {
+                for row in rows {
+                    let package = app.browse.packages[shown[row]].clone();
+                    app.request_icon(&package);
+                    ui.horizontal(|ui| {
+                        ui_icon(ui, app.browse.icons.get(&package.full_name), 32.0);
+                        ui.vertical(|ui| {
+                            let selected =
+                                app.browse.selected.as_deref() == Some(&package.full_name);
+                            let name = egui::RichText::new(&package.name).strong();
+                            if ui.selectable_label(selected, name).clicked() {
+                                app.browse.selected = Some(package.full_name.clone());
+                                app.browse.version = package
+                                    .latest()
+                                    .map(|v| v.version_number.clone())
+                                    .unwrap_or_default();
+                            }
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "by {} · {} downloads",
+                                    package.owner,
+                                    package.downloads()
+                                ))
+                                .small()
+                                .weak(),
+                            );
+                        });
+                    });
+                }
+            });
+        ui_package_details(app, &mut cols[1]);
+    });
+}
+
+fn ui_package_details(app: &mut AppState, ui: &mut egui::Ui) {
+    let Some(package) = app
+        .browse
+        .selected
+        .as_ref()
+        .and_then(|name| app.browse.packages.iter().find(|p| &p.full_name == name))
+        .cloned()
+    else {
+        ui.label("Select a package to see its details.");
+        return;
+    };
+    ui.horizontal(|ui| {
+        ui_icon(ui, app.browse.icons.get(&package.full_name), 64.0);
+        ui.vertical(|ui| {
+            ui.heading(&package.name);
+            ui.label(format!("by {}", package.owner));
+            ui.label(format!("{} downloads", package.downloads()));
+        });
+    });
+    if package.is_deprecated {
+        ui.colored_label(Color32::YELLOW, "This package is deprecated.");
+    }
+    let Some(version) = package
+        .version(&app.browse.version)
+        .or_else(|| package.latest())
+        .cloned()
+    else {
+        return;
+    };
+    ui.label(&version.description);
+    if !package.categories.is_empty() {
+        ui.label(format!("Categories: {}", package.categories.join(", ")));
+    }
+    if let Some(date) = package.date_updated.get(..10) {
+        ui.label(format!("Updated {date}"));
+    }
+    ui.horizontal(|ui| {
+        ui.label("Version:");
+        egui::ComboBox::from_id_salt("browse_version")
+            .selected_text(&version.version_number)
+            .show_ui(ui, |ui| {
+                for v in &package.versions {
+                    ui.selectable_value(
+                        &mut app.browse.version,
+                        v.version_number.clone(),
+                        &v.version_number,
+                    );
+                }
+            });
+    });
+    if !version.dependencies.is_empty() {
+        ui.label("Depends on:");
+        for dep in &version.dependencies {
+            let provided = thunderstore::PackageRef::parse_versioned(dep)
+                .is_some_and(|(p, _)| p.is_bepinex_pack());
+            if provided {
+                ui.label(format!("  {dep} (installed with BepInEx)"));
+            } else {
+                ui.label(format!("  {dep}"));
+            }
+        }
+    }
+    let installed = app
+        .mods
+        .mods
+        .iter()
+        .find(|m| m.id == package.full_name)
+        .and_then(|m| m.version.clone());
+    if let Some(v) = &installed {
+        ui.label(format!("Installed: {v}"));
+    }
+    let action = match &installed {
+        Some(v) if *v == version.version_number => "Reinstall",
+        Some(_) => "Install this version",
+        None => "Install",
+    };
+    let ready = app.bep_state.is_installed();
+    let install = ui
+        .add_enabled(!app.is_busy && ready, egui::Button::new(action))
+        .on_disabled_hover_text(if ready {
+            "Another operation is running"
+        } else {
+            "Install BepInEx first"
+        });
+    if install.clicked() {
+        app.start_install_package(package.package_ref(), version.version_number.clone());
+    }
+}
+
+fn ui_modpack(app: &mut AppState, ui: &mut egui::Ui) {
+    ui.heading("Modpack");
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut app.pack_include_archives, "Bundle mod archives");
+        ui.checkbox(&mut app.pack_include_configs, "Bundle BepInEx/config");
+    });
+    ui.horizontal(|ui| {
+        let export_btn = ui.add_enabled(
+            !app.is_busy && !app.mods.mods.is_empty(),
+            egui::Button::new("Export modpack..."),
+        );
+        if export_btn.clicked()
+            && let Some(dest) = rfd::FileDialog::new()
+                .add_filter("Modpack", &[modpack::EXTENSION])
+                .set_file_name(format!("restaurats.{}", modpack::EXTENSION))
+                .save_file()
+        {
+            app.start_export_modpack(dest);
+        }
+        let import_btn = ui
+            .add_enabled(!app.is_busy, egui::Button::new("Import modpack..."))
+            .on_hover_text(
+                "Replaces the installed mods with the modpack's and verifies their hashes",
+            );
+        if import_btn.clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Modpack", &[modpack::EXTENSION])
+                .pick_file()
+        {
+            app.pending_import = Some(PendingImport::Modpack(path));
+        }
+    });
+    ui.add_space(8.0);
+    ui.heading("r2modman profile");
+    ui.horizontal(|ui| {
+        let import_btn = ui
+            .add_enabled(!app.is_busy, egui::Button::new("Import .r2z..."))
+            .on_hover_text("Replaces the installed mods with the profile's enabled packages from Thunderstore (cached locally) and its configs");
+        if import_btn.clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("r2modman profile", &[r2modman::EXTENSION])
+                .pick_file()
+        {
+            app.pending_import = Some(PendingImport::R2z(path));
+        }
+        let export_btn = ui
+            .add_enabled(
+                !app.is_busy && !app.mods.mods.is_empty(),
+                egui::Button::new("Export .r2z..."),
+            )
+            .on_hover_text("Only mods installed from Thunderstore packages can be listed");
+        if export_btn.clicked()
+            && let Some(dest) = rfd::FileDialog::new()
+                .add_filter("r2modman profile", &[r2modman::EXTENSION])
+                .set_file_name(format!("Restaurats.{}", r2modman::EXTENSION))
+                .save_file()
+        {
+            app.start_export_r2z(dest);
+        }
+        if ui
+            .add_enabled(!app.is_busy, egui::Button::new("Migrate from r2modman..."))
+            .on_hover_text("Copy the mods of an r2modman profile folder into this game")
+            .clicked()
+        {
+            app.migrate = Some(MigrateWizard {
+                include_configs: true,
+                ..Default::default()
+            });
+        }
+    });
+}
+
+fn ui_uninstall_confirm(app: &mut AppState, ctx: &egui::Context) {
+    let Some(mut pending) = app.pending_uninstall.take() else {
+        return;
+    };
+    let Some(idx) = app.mods.mods.iter().position(|m| m.id == pending.id) else {
+        return;
+    };
+    let name = app.mods.mods[idx].name.clone();
+    let mut keep_open = true;
+    egui::Window::new("Uninstall mod?")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.label(format!("Uninstall {name}?"));
+            if pending.settings.is_empty() {
+                ui.weak("No settings files are linked to this mod.");
+            } else {
+                ui.checkbox(
+                    &mut pending.delete_settings,
+                    format!("Also delete settings ({} files)", pending.settings.len()),
+                )
+                .on_hover_text(format!(
+                    "{}\nA backup copy is kept.",
+                    pending.settings.join("\n")
+                ));
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Uninstall").clicked() {
+                    keep_open = false;
+                    let settings = if pending.delete_settings {
+                        pending.settings.clone()
+                    } else {
+                        Vec::new()
+                    };
+                    app.uninstall_mod(idx, &settings);
+                }
+                if ui.button("Cancel").clicked() {
+                    keep_open = false;
+                }
+            });
+        });
+    if keep_open {
+        app.pending_uninstall = Some(pending);
+    }
+}
+
+fn ui_bep_uninstall_confirm(app: &mut AppState, ctx: &egui::Context) {
+    let Some(mut keep_user_files) = app.pending_bep_uninstall else {
+        return;
+    };
+    let mut close = false;
+    egui::Window::new("Uninstall BepInEx?")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.label("Removes the files the BepInEx install wrote, including winhttp.dll, doorstop_config.ini and dotnet/.");
+            ui.checkbox(&mut keep_user_files, "Keep plugins and configs");
+            if !keep_user_files {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    "All installed mods and their settings are removed too.",
+                );
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Uninstall").clicked() {
+                    close = true;
+                    app.start_uninstall_bepinex(keep_user_files);
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    app.pending_bep_uninstall = (!close).then_some(keep_user_files);
+}
+
+fn ui_import_confirm(app: &mut AppState, ctx: &egui::Context) {
+    let Some(pending) = app.pending_import.clone() else {
+        return;
+    };
+    let (title, path, kind) = match &pending {
+        PendingImport::Modpack(path) => ("Import modpack?", path, "modpack"),
+        PendingImport::R2z(path) => ("Import r2modman profile?", path, "profile"),
+    };
+    let mut close = false;
+    egui::Window::new(title)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "{} replaces the installed mods: mods not in the {kind} are removed and its config files overwrite yours.",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            if !app.mods.mods.is_empty() {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    format!("{} installed mods are affected.", app.mods.mods.len()),
+                );
+            }
+            ui.label("A snapshot is taken first if BepInEx is installed, and \"Undo last operation\" reverts the import.");
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!app.is_busy, egui::Button::new("Replace mods"))
+                    .clicked()
+                {
+                    close = true;
+                    match &pending {
+                        PendingImport::Modpack(path) => app.start_import_modpack(path.clone()),
+                        PendingImport::R2z(path) => app.start_import_r2z(path.clone()),
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        app.pending_import = None;
+    }
+}
+
+fn ui_reset_confirm(app: &mut AppState, ctx: &egui::Context) {
+    let Some(entries) = app.reset_preview.clone() else {
+        return;
+    };
+    let mut close = false;
+    egui::Window::new("Reset to vanilla?")
+        .collapsible(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            if entries.is_empty() {
+                ui.label("The game folder has nothing from BepInEx or the mod manager.");
+                if ui.button("Close").clicked() {
+                    close = true;
+                }
+                return;
+            }
+            ui.label("These will be deleted from the game folder:");
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for e in &entries {
+                        let name = if e.is_dir {
+                            format!("{}/", e.rel)
+                        } else {
+                            e.rel.clone()
+                        };
+                        ui.label(format!(
+                            "{name}  ({} files, {})",
+                            e.files,
+                            format_size(e.bytes)
+                        ));
+                    }
+                });
+            ui.label("Restaurats' own files are not touched. A snapshot is taken first so this can be restored.");
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!app.is_busy, egui::Button::new("Back up and reset"))
+                    .clicked()
+                {
+                    close = true;
+                    app.start_reset_to_vanilla(entries.clone());
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        app.reset_preview = None;
+    }
+}
+
+fn ui_merge_report(app: &mut AppState, ctx: &egui::Context) {
+    if app.merge_reports.is_empty() {
+        return;
+    }
+    let mut open = true;
+    egui::Window::new("Config changes after upgrade")
+        .open(&mut open)
+        .collapsible(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for r in &app.merge_reports {
+                        ui.strong(&r.file);
+                        for (title, names) in [
+                            ("Kept your value", &r.kept),
+                            ("New setting", &r.added),
+                            ("Removed", &r.removed),
+                        ] {
+                            for name in names {
+                                ui.label(format!("{title}: {name}"));
+                            }
+                        }
+                        if r.kept.is_empty() && r.added.is_empty() && r.removed.is_empty() {
+                            ui.label("Restored your file; the new version did not ship one.");
+                        }
+                        ui.add_space(4.0);
+                    }
+                });
+        });
+    if !open {
+        app.merge_reports.clear();
+    }
+}
+
+fn ui_migrate_wizard(app: &mut AppState, ctx: &egui::Context) {
+    let Some(mut wizard) = app.migrate.take() else {
+        return;
+    };
+    let mut open = true;
+    let mut deploy = false;
+    egui::Window::new("Migrate from r2modman")
+        .open(&mut open)
+        .collapsible(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.label("Pick an r2modman profile folder (the one containing mods.yml).");
+            if ui.button("Choose profile folder...").clicked() {
+                let mut dialog = rfd::FileDialog::new();
+                if let Some(dir) = r2modman::default_profiles_dir().filter(|d| d.is_dir()) {
+                    dialog = dialog.set_directory(dir);
+                }
+                if let Some(dir) = dialog.pick_folder() {
+                    match r2modman::scan_profile_dir(&dir) {
+                        Ok(scan) => {
+                            wizard.scan = Some(scan);
+                            wizard.error = None;
+                        }
+                        Err(e) => {
+                            wizard.scan = None;
+                            wizard.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+            if let Some(err) = &wizard.error {
+                ui.colored_label(Color32::RED, err);
+            }
+            let Some(scan) = &wizard.scan else {
+                return;
+            };
+            ui.separator();
+            ui.label(format!("Profile: {}", scan.root.display()));
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for m in &scan.mods {
+                        let version = m.entry.version.as_deref().unwrap_or("?");
+                        let line = format!(
+                            "{} {} ({} files)",
+                            m.entry.name,
+                            version,
+                            m.entry.installed_files.len()
+                        );
+                        if m.enabled {
+                            ui.label(line);
+                        } else {
+                            ui.weak(format!("{line} - disabled, skipped"));
+                        }
+                    }
+                    for s in &scan.skipped {
+                        ui.weak(format!("Skipped: {s}"));
+                    }
+                });
+            ui.checkbox(
+                &mut wizard.include_configs,
+                format!("Copy {} config files", scan.configs.len()),
+            );
+            ui.label("Installed mods will be replaced. A snapshot is taken first.");
+            deploy = ui
+                .add_enabled(
+                    !app.is_busy && scan.mods.iter().any(|m| m.enabled),
+                    egui::Button::new("Deploy"),
+                )
+                .clicked();
+        });
+    if deploy && let Some(scan) = wizard.scan.take() {
+        app.start_migrate_profile(scan, wizard.include_configs);
+        return;
+    }
+    if open {
+        app.migrate = Some(wizard);
+    }
+}
+
+fn ui_config(app: &mut AppState, ui: &mut egui::Ui) {
+    ui_fixups(app, ui);
+    ui.separator();
+    if app.config_files.is_empty() {
+        ui.label("No config files yet. Plugins write theirs to BepInEx/config the first time the game runs with them.");
+        return;
+    }
+    let mut open_rel = None;
+    ui.horizontal(|ui| {
+        ui.label("File:");
+        let selected = app.open_config.as_ref().map(|o| o.rel.clone());
+        let file_label = |f: &configs::ConfigFile| {
+            let name = f.rel.trim_start_matches("BepInEx/config/").to_string();
+            match &f.owner {
+                Some(owner) => format!("{name} ({owner})"),
+                None => name,
+            }
+        };
+        let current = app
+            .config_files
+            .iter()
+            .find(|f| Some(&f.rel) == selected.as_ref())
+            .map(file_label)
+            .unwrap_or_else(|| "Choose a file".into());
+        egui::ComboBox::from_id_salt("config_file")
+            .selected_text(current)
+            .width(400.0)
+            .show_ui(ui, |ui| {
+                for f in &app.config_files {
+                    let resp = ui
+                        .selectable_label(Some(&f.rel) == selected.as_ref(), file_label(f))
+                        .on_hover_text(match (&f.plugin_name, &f.plugin_guid) {
+                            (Some(name), Some(guid)) => format!("Written by {name} ({guid})"),
+                            _ => f.rel.clone(),
+                        });
+                    if resp.clicked() {
+                        open_rel = Some(f.rel.clone());
+                    }
+                }
+            });
+    });
+    if let Some(rel) = open_rel {
+        if app.open_config.as_ref().is_some_and(|o| o.dirty) {
+            app.log("Unsaved changes discarded.");
+        }
+        app.open_config_file(&rel);
+    }
+
+    let Some(open) = &mut app.open_config else {
+        return;
+    };
+    let mut edits: Vec<(String, String, String)> = Vec::new();
+    egui::ScrollArea::vertical()
+        .id_salt("config_scroll")
+        .max_height(app.config.constants.mods_max_height)
+        .show(ui, |ui| {
+            let mut section = None;
+            for (i, entry) in open.doc.entries().iter().enumerate() {
+                if section != Some(&entry.section) {
+                    section = Some(&entry.section);
+                    ui.add_space(4.0);
+                    ui.strong(format!("[{}]", entry.section));
+                }
+                ui.horizontal(|ui| {
+                    let label = ui.label(&entry.key);
+                    if !entry.description.is_empty() {
+                        label.on_hover_text(&entry.description);
+                    }
+                    let mut value = entry.value.clone();
+                    setting_widget(ui, i, &entry.kind(), &mut value);
+                    if let Some(default) = &entry.default
+                        && ui
+                            .add_enabled(!entry.is_default(), egui::Button::new("Reset"))
+                            .on_hover_text(format!("Default: {default}"))
+                            .clicked()
+                    {
+                        value = default.clone();
+                    }
+                    if value != entry.value {
+                        edits.push((entry.section.clone(), entry.key.clone(), value));
+                    }
+                });
+            }
+        });
+    for (section, key, value) in edits {
+        open.doc.set(&section, &key, &value);
+        open.dirty = true;
+    }
+    let dirty = open.dirty;
+    let rel = open.rel.clone();
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(dirty && !app.is_busy, egui::Button::new("Save"))
+            .clicked()
+        {
+            app.save_open_config();
+        }
+        if ui.add_enabled(dirty, egui::Button::new("Revert")).clicked() {
+            app.open_config_file(&rel);
+        }
+    });
+}
+
+fn ui_fixups(app: &mut AppState, ui: &mut egui::Ui) {
+    ui.heading("Presets & fixes");
+    let mut toggled = None;
+    ui.horizontal_wrapped(|ui| {
+        for (i, fixup) in app.config.fixups.iter().enumerate() {
+            let applies = fixup.when.matches(&app.fixup_env);
+            let mut on = fixup.is_applied(&app.bep_cfg);
+            let resp = ui
+                .push_id(&fixup.id, |ui| {
+                    ui.add_enabled(
+                        applies && !app.is_busy,
+                        egui::Checkbox::new(&mut on, &fixup.name),
+                    )
+                })
+                .inner;
+            let resp = if applies {
+                resp.on_hover_text(&fixup.description)
+            } else {
+                resp.on_disabled_hover_text("Not needed for this game or BepInEx version")
+            };
+            if resp.changed() {
+                toggled = Some((i, on));
+            }
+        }
+    });
+    if let Some((i, on)) = toggled {
+        app.set_fixup(i, on);
+    }
 }
 
-fn ui_getting_started(app: &mut AppState, ui: &mut egui::Ui) {
-    ui.heading("BepInEx");
-    ui.horizontal(|ui| {
-        if app.bep_status.is_empty() {
-            app.bep_status = bepinex::detect_bep_status(&app.game_dir);
+/// Editor for one setting value, picked from what its comments say it accepts.
+fn setting_widget(ui: &mut egui::Ui, id: usize, kind: &ValueKind, value: &mut String) {
+    match kind {
+        ValueKind::Bool => {
+            let mut b = value.eq_ignore_ascii_case("true");
+            if ui.checkbox(&mut b, "").changed() {
+                *value = b.to_string();
+            }
         }
-        ui.label(format!("Status: {}", app.bep_status));
-        let auto_btn = ui.add_enabled(
-            !app.is_busy,
-            egui::Button::new("Install Bleeding Edge (auto)"),
-        );
-        if auto_btn.clicked() {
-            app.start_install_bepinex_stable_v5_async();
+        ValueKind::Enum(options) => {
+            egui::ComboBox::from_id_salt(("config_enum", id))
+                .selected_text(value.as_str())
+                .show_ui(ui, |ui| {
+                    for opt in options {
+                        ui.selectable_value(value, opt.clone(), opt);
+                    }
+                });
         }
-    });
-    ui.horizontal(|ui| {
-        ui.text_edit_singleline(&mut app.custom_bep_url)
-            .on_hover_text("Custom BepInEx zip URL (e.g., BE IL2CPP build)");
-        let from_url_btn = ui.add_enabled(!app.is_busy, egui::Button::new("Install from URL"));
-        if from_url_btn.clicked() {
-            if !app.custom_bep_url.trim().is_empty() {
-                let url = app.custom_bep_url.trim().to_string();
-                app.start_install_bepinex_from_url_async(url);
+        ValueKind::Flags(options) => {
+            let mut set: Vec<String> = value
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect();
+            let mut changed = false;
+            for opt in options {
+                let mut on = set.contains(opt);
+                if ui.checkbox(&mut on, opt).changed() {
+                    changed = true;
+                    if on {
+                        set.push(opt.clone());
+                    } else {
+                        set.retain(|v| v != opt);
+                    }
+                }
+            }
+            if changed {
+                // Keep the order BepInEx lists the flags in.
+                let ordered: Vec<&str> = options
+                    .iter()
+                    .filter(|o| set.contains(o))
+                    .map(String::as_str)
+                    .collect();
+                *value = ordered.join(", ");
             }
         }
-        let from_zip_btn = ui.add_enabled(!app.is_busy, egui::Button::new("Install from ZIP..."));
-        if from_zip_btn.clicked() {
-            if let Some(zip) = rfd::FileDialog::new()
-                .add_filter("zip", &["zip"])
-                .pick_file()
-            {
-                app.is_busy = true;
-                let game_dir = app.game_dir.clone();
-                let task: Arc<Mutex<Option<Result<(), String>>>> = Arc::new(Mutex::new(None));
-                app.install_task = Some(task.clone());
-                std::thread::spawn(move || {
-                    let res = (|| -> Result<()> {
-                        let mut buf = Vec::new();
-                        File::open(&zip)?.read_to_end(&mut buf)?;
-                        bepinex::install_bepinex_from_zip_bytes(&game_dir, &buf)?;
-                        if let Err(e) = bepinex::validate_bepinex_installation(&game_dir) {
-                            return Err(e);
-                        }
-                        Ok(())
-                    })();
-                    *task.lock().unwrap() = Some(res.map_err(|e| e.to_string()));
-                });
+        ValueKind::Integer { min, max } => match value.parse::<i64>() {
+            Ok(mut n) => {
+                let drag = egui::DragValue::new(&mut n)
+                    .range(min.unwrap_or(i64::MIN)..=max.unwrap_or(i64::MAX));
+                if ui.add(drag).changed() {
+                    *value = n.to_string();
+                }
+            }
+            Err(_) => {
+                ui.text_edit_singleline(value);
             }
+        },
+        ValueKind::Float { min, max } => match value.parse::<f64>() {
+            Ok(mut n) => {
+                let drag = egui::DragValue::new(&mut n)
+                    .speed(0.01)
+                    .range(min.unwrap_or(f64::MIN)..=max.unwrap_or(f64::MAX));
+                if ui.add(drag).changed() {
+                    *value = n.to_string();
+                }
+            }
+            Err(_) => {
+                ui.text_edit_singleline(value);
+            }
+        },
+        ValueKind::Text => {
+            ui.text_edit_singleline(value);
         }
-    });
+    }
 }
 
-fn ui_mods(app: &mut AppState, ui: &mut egui::Ui, _ctx: &egui::Context) {
-    let _ = draw_drop_zone(ui, "Drag a mod zip or dll here");
+fn ui_snapshots(app: &mut AppState, ui: &mut egui::Ui) {
+    ui.heading("Snapshots");
+    ui.label(
+        "A snapshot archives BepInEx/ (plugins, configs and the mod index), winhttp.dll and doorstop_config.ini.",
+    );
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut app.snapshot_label)
+            .on_hover_text("Label for the new snapshot");
+        let create_btn = ui.add_enabled(!app.is_busy, egui::Button::new("Create snapshot"));
+        if create_btn.clicked() {
+            app.start_create_snapshot();
+        }
+    });
     ui.add_space(8.0);
-    ui.heading("Installed Mods");
-    if app.mods.mods.is_empty() {
-        ui.label("Drag a mod zip or dll into the box above to install.");
+    if app.snapshots.is_empty() {
+        ui.label("No snapshots yet.");
+        return;
     }
+    let mut delete: Option<String> = None;
     egui::ScrollArea::vertical()
-        .id_salt("mods_scroll")
+        .id_salt("snapshots_scroll")
         .max_height(app.config.constants.mods_max_height)
         .show(ui, |ui| {
-            egui::Grid::new("mods_grid").striped(true).show(ui, |ui| {
-                ui.label("Name");
-                ui.label("Version");
-                ui.label("");
-                ui.end_row();
-                for i in 0..app.mods.mods.len() {
-                    let m = &app.mods.mods[i];
-                    ui.label(&m.name);
-                    ui.label(m.version.clone().unwrap_or_default());
-                    if ui.button("Uninstall").clicked() {
-                        app.uninstall_mod(i);
-                    }
+            egui::Grid::new("snapshots_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Label");
+                    ui.label("Taken");
+                    ui.label("Size");
+                    ui.label("Files");
+                    ui.label("");
                     ui.end_row();
-                }
-            });
+                    for snap in &app.snapshots {
+                        ui.label(&snap.label);
+                        ui.label(format_age(snap.created));
+                        ui.label(format_size(snap.size));
+                        ui.label(snap.files.to_string());
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add_enabled(!app.is_busy, egui::Button::new("Restore"))
+                                .clicked()
+                            {
+                                app.pending_restore = Some(snap.clone());
+                            }
+                            if ui
+                                .add_enabled(!app.is_busy, egui::Button::new("Delete"))
+                                .clicked()
+                            {
+                                delete = Some(snap.id.clone());
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
         });
+    if let Some(id) = delete {
+        if let Err(e) = snapshot::delete(&app.snapshot_root, &id) {
+            app.log(&format!("Delete snapshot failed: {e}"));
+        }
+        app.snapshots = snapshot::list(&app.snapshot_root, &app.game_dir);
+    }
 }
 
-fn map_mod_zip_entry_to_game_rel(entry: &str) -> Option<PathBuf> {
-    let lower = entry.to_lowercase();
-    if lower.ends_with('/') {
-        return Some(PathBuf::from(entry));
-    }
-    if lower.contains("bepinex/plugins/") {
-        let rel = entry
-            .splitn(2, |c| c == ':' || c == '*')
-            .next()
-            .unwrap_or(entry);
-        return Some(PathBuf::from(rel));
-    }
-    if lower.starts_with("plugins/") {
-        return Some(PathBuf::from("BepInEx").join("plugins").join(&entry[8..]));
-    }
-    if lower.starts_with("bepinex/") {
-        return Some(PathBuf::from(entry));
-    }
-    None
+fn ui_cache(app: &mut AppState, ui: &mut egui::Ui) {
+    ui.heading("Download cache");
+    ui.label(
+        "Every BepInEx build and mod archive installed is kept here, so reinstalls, repairs and modpack imports work offline.",
+    );
+    let size: u64 = app.cache_entries.iter().map(|e| e.size).sum();
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "{} archives, {} of",
+            app.cache_entries.len(),
+            format_size(size)
+        ));
+        let limit = ui.add(
+            egui::DragValue::new(&mut app.settings.cache_limit_mb)
+                .speed(16)
+                .suffix(" MB"),
+        );
+        if limit.drag_stopped() || limit.lost_focus() {
+            app.save_settings();
+            if !app.is_busy {
+                app.prune_cache(app.settings.cache_limit_mb);
+            }
+        }
+        let clean = ui
+            .add_enabled(!app.is_busy, egui::Button::new("Clean cache"))
+            .on_hover_text("Remove every archive the current setup was not installed from");
+        if clean.clicked()
+            && let Some((count, bytes)) = app.prune_cache(0)
+        {
+            app.log(&format!(
+                "Removed {count} archives ({}) from the download cache.",
+                format_size(bytes)
+            ));
+        }
+    });
+}
+
+fn ui_restore_confirm(app: &mut AppState, ctx: &egui::Context) {
+    let Some(snap) = app.pending_restore.clone() else {
+        return;
+    };
+    egui::Window::new("Restore snapshot?")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "Replace BepInEx/, winhttp.dll and doorstop_config.ini with snapshot \"{}\"?",
+                snap.label
+            ));
+            ui.label("Anything added since the snapshot was taken will be removed.");
+            ui.horizontal(|ui| {
+                if ui.button("Restore").clicked() {
+                    app.pending_restore = None;
+                    app.start_restore_snapshot(snap.clone());
+                }
+                if ui.button("Cancel").clicked() {
+                    app.pending_restore = None;
+                }
+            });
+        });
 }
 
 impl eframe::App for AppState {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Check if background poller detected BepInEx readiness
-        if !self.bep_ready {
-            if let Some(flag) = &self.poller_flag {
-                if *flag.lock().unwrap() {
-                    self.bep_ready = true;
-                    self.bep_status = bepinex::detect_bep_status(&self.game_dir);
-                    self.log("BepInEx is now ready! Mods tab enabled.");
-                    self.poller_flag = None;
+        // Files changed outside the manager. While a job runs, its own
+        // writes show up here too; the reload after it covers them.
+        let changes = self
+            .watcher
+            .as_ref()
+            .map(|w| w.take_changes())
+            .unwrap_or_default();
+        if !changes.is_empty() && !self.is_busy {
+            self.apply_watch_changes(changes);
+        }
+
+        let catalog_res = self
+            .catalog_task
+            .as_ref()
+            .and_then(|t| t.lock().unwrap().take());
+        if let Some(res) = catalog_res {
+            self.catalog_task = None;
+            match res {
+                Ok(builds) => {
+                    self.catalog = builds;
+                    let target = bepinex::BepInExInfo::from_archive_name(&self.bepinex_url());
+                    if let Some(installed) = &self.bep_info
+                        && catalog::is_newer(&target, installed)
+                    {
+                        self.log(&format!("{target} is available."));
+                    }
                 }
+                Err(e) => self.log(&format!("Could not list BepInEx builds: {e}")),
             }
         }
 
+        self.poll_browse(ctx);
+        self.poll_downloads();
+        self.poll_update_check();
+
         // Check for completion of background install task
-        let task_opt = self.install_task.as_ref().map(|a| Arc::clone(a));
+        let task_opt = self.install_task.as_ref().map(Arc::clone);
         if let Some(task) = task_opt {
             let res_opt = { task.lock().unwrap().take() };
             if let Some(res) = res_opt {
                 self.is_busy = false;
+                self.reload_game_state();
+                self.prune_cache(self.settings.cache_limit_mb);
                 match res {
                     Ok(()) => {
-                        self.bep_status = bepinex::detect_bep_status(&self.game_dir);
-                        self.bep_ready = bepinex::is_bep_installed(&self.game_dir);
                         self.log("BepInEx installed and validated.");
-                        if !self.bep_ready {
-                            self.log("Checking for BepInEx readiness in background...");
-                            let poller = poller::BepInExPoller::new(self.game_dir.clone());
-                            self.poller_flag = Some(poller.start());
+                        if self.bep_state != bepinex::BepState::Ready {
+                            self.log(self.bep_state.next_step());
                         }
                     }
                     Err(msg) => {
@@ -371,9 +2137,32 @@ impl eframe::App for AppState {
             }
         }
 
+        let job_opt = self
+            .job
+            .as_ref()
+            .map(|j| (j.name.clone(), Arc::clone(&j.result)));
+        if let Some((name, slot)) = job_opt {
+            let res_opt = { slot.lock().unwrap().take() };
+            if let Some(res) = res_opt {
+                self.is_busy = false;
+                self.job = None;
+                match res {
+                    Ok(msg) => self.log(&msg),
+                    Err(e) => self.log(&format!("{name} failed: {e}")),
+                }
+                self.reload_game_state();
+            }
+        }
+
+        if !self.is_busy {
+            self.transfer = None;
+        } else if self.transfer.is_some() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(200));
+        }
+
         let dropped = ctx.input(|i| i.raw.dropped_files.clone());
         if !dropped.is_empty() && !self.is_busy {
-            if self.bep_ready {
+            if self.bep_state.is_installed() {
                 for f in dropped {
                     if let Some(path) = f.path {
                         let is_zip = path
@@ -387,40 +2176,12 @@ impl eframe::App for AppState {
                             .map(|e| e.eq_ignore_ascii_case("dll"))
                             .unwrap_or(false);
                         if is_zip {
-                            if let Err(e) = self.install_mod_from_zip_path(&path) {
+                            if let Err(e) = self.install_mod_from_zip_path(&path, None) {
                                 self.log(&format!("Install failed: {e}"));
                             }
                         } else if is_dll {
-                            let dest = bepinex::plugins_dir(&self.game_dir)
-                                .join(path.file_name().unwrap());
-                            if let Some(parent) = dest.parent() {
-                                let _ = std::fs::create_dir_all(parent);
-                            }
-                            match std::fs::copy(&path, &dest) {
-                                Ok(_) => {
-                                    self.mods.mods.push(ModEntry {
-                                        id: path
-                                            .file_stem()
-                                            .and_then(|s| s.to_str())
-                                            .unwrap_or("mod")
-                                            .to_string(),
-                                        name: path
-                                            .file_name()
-                                            .and_then(|s| s.to_str())
-                                            .unwrap_or("mod")
-                                            .to_string(),
-                                        version: None,
-                                        source_zip: Some(path.display().to_string()),
-                                        installed_files: vec![format!(
-                                            "BepInEx/plugins/{}",
-                                            dest.file_name().unwrap().to_string_lossy()
-                                        )],
-                                    });
-
-                                    let _ = bepinex::save_index(&self.game_dir, &self.mods);
-                                    self.log("Mod installed.");
-                                }
-                                Err(e) => self.log(&format!("Install failed: {e}")),
+                            if let Err(e) = self.install_mod_from_dll_path(&path) {
+                                self.log(&format!("Install failed: {e}"));
                             }
                         } else {
                             self.log("Only .zip or .dll files are supported.");
@@ -432,6 +2193,14 @@ impl eframe::App for AppState {
             }
         }
 
+        ui_restore_confirm(self, ctx);
+        ui_migrate_wizard(self, ctx);
+        ui_merge_report(self, ctx);
+        ui_uninstall_confirm(self, ctx);
+        ui_bep_uninstall_confirm(self, ctx);
+        ui_import_confirm(self, ctx);
+        ui_reset_confirm(self, ctx);
+
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
             ui.heading("Restaurats Mod Manager");
         });
@@ -463,27 +2232,53 @@ impl eframe::App for AppState {
                     let plugins_dir = bepinex::plugins_dir(&self.game_dir);
                     open_directory_in_explorer(&plugins_dir);
                 }
+                ui.separator();
+                let undo_btn = ui.add_enabled(
+                    !self.is_busy && self.last_operation.is_some(),
+                    egui::Button::new("Undo last operation"),
+                );
+                let undo_btn = match &self.last_operation {
+                    Some(label) => undo_btn.on_hover_text(format!("Undo: {label}")),
+                    None => undo_btn.on_hover_text("Nothing to undo"),
+                };
+                if undo_btn.clicked() {
+                    self.undo_last_operation();
+                }
+                ui.separator();
+                let offline = ui
+                    .checkbox(&mut self.settings.offline, "Offline")
+                    .on_hover_text("Never use the network; install from the download cache");
+                if offline.changed() {
+                    self.save_settings();
+                    if self.settings.offline {
+                        self.log("Offline mode: downloads come from the cache only.");
+                    } else {
+                        self.log("Back online.");
+                        self.start_fetch_catalog();
+                    }
+                }
             });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.label("Game directory:");
                 let mut path_str = self.game_dir.display().to_string();
-                if ui.text_edit_singleline(&mut path_str).lost_focus() {
-                    self.game_dir = PathBuf::from(path_str);
+                if ui.text_edit_singleline(&mut path_str).lost_focus()
+                    && Path::new(&path_str) != self.game_dir
+                {
+                    self.set_game_dir(PathBuf::from(path_str));
                 }
-                if ui.button("Browse...").clicked() {
-                    if let Some(dir) = rfd::FileDialog::new()
+                if ui.button("Browse...").clicked()
+                    && let Some(dir) = rfd::FileDialog::new()
                         .set_directory(&self.game_dir)
                         .pick_folder()
-                    {
-                        self.game_dir = dir;
-                        self.mods = bepinex::load_index(&self.game_dir);
-                        self.bep_status = bepinex::detect_bep_status(&self.game_dir);
-                        self.bep_ready = bepinex::is_bep_installed(&self.game_dir);
-                    }
+                {
+                    self.set_game_dir(dir);
                 }
             });
+            if let Some(transfer) = &self.transfer {
+                ui_transfer(transfer, ui);
+            }
 
             ui.separator();
             ui.horizontal(|ui| {
@@ -494,22 +2289,51 @@ impl eframe::App for AppState {
                 if getting.clicked() {
                     self.current_tab = Tab::GettingStarted;
                 }
+                let installed = self.bep_state.is_installed();
                 let mods_tab = ui.add_enabled(
-                    self.bep_ready,
+                    installed,
                     egui::Button::new("Mods").selected(matches!(self.current_tab, Tab::Mods)),
                 );
-                if mods_tab.clicked() && self.bep_ready {
+                if mods_tab.clicked() && installed {
                     self.current_tab = Tab::Mods;
                 }
-                if !self.bep_ready {
+                if !installed {
                     mods_tab.on_hover_text("Install BepInEx first");
                 }
+                let browse = ui.selectable_label(matches!(self.current_tab, Tab::Browse), "Browse");
+                if browse.clicked() {
+                    self.current_tab = Tab::Browse;
+                    if self.browse.packages.is_empty() {
+                        self.load_packages();
+                    }
+                }
+                let config_tab = ui.add_enabled(
+                    installed,
+                    egui::Button::new("Config").selected(matches!(self.current_tab, Tab::Config)),
+                );
+                if config_tab.clicked() && installed {
+                    self.current_tab = Tab::Config;
+                    self.config_files = configs::list(&self.game_dir, &self.mods);
+                }
+                let snapshots =
+                    ui.selectable_label(matches!(self.current_tab, Tab::Snapshots), "Snapshots");
+                if snapshots.clicked() {
+                    self.current_tab = Tab::Snapshots;
+                    self.snapshots = snapshot::list(&self.snapshot_root, &self.game_dir);
+                }
             });
 
             ui.separator();
             match self.current_tab {
                 Tab::GettingStarted => ui_getting_started(self, ui),
                 Tab::Mods => ui_mods(self, ui, ctx),
+                Tab::Browse => ui_browse(self, ui),
+                Tab::Config => ui_config(self, ui),
+                Tab::Snapshots => {
+                    ui_snapshots(self, ui);
+                    ui.separator();
+                    ui_cache(self, ui);
+                }
             }
 
             ui.separator();
@@ -527,85 +2351,548 @@ impl eframe::App for AppState {
 }
 
 impl AppState {
-    fn start_install_bepinex_stable_v5_async(&mut self) {
-        if self.is_busy {
+    /// The BepInEx build to install: the pinned one, else the newest of the
+    /// channel that fits the game, else the configured build in the flavor
+    /// this game needs.
+    fn bepinex_url(&self) -> String {
+        if let Some(url) = &self.settings.pinned_bepinex {
+            return url.clone();
+        }
+        let url = self.latest_bepinex_url();
+        if !self.settings.offline || self.is_cached(&url) {
+            return url;
+        }
+        // Offline, the most recently used cached build that fits will do.
+        self.cache_entries
+            .iter()
+            .rev()
+            .filter(|e| e.origin.kind == cache::Kind::BepInEx)
+            .filter_map(|e| e.origin.url.clone())
+            .find(|u| bepinex::BepInExInfo::from_archive_name(u).fits(&self.game_info))
+            .unwrap_or(url)
+    }
+
+    fn latest_bepinex_url(&self) -> String {
+        catalog::builds_for(
+            &self.catalog,
+            self.settings.bepinex_channel,
+            &self.game_info,
+        )
+        .first()
+        .map(|b| b.url.clone())
+        .unwrap_or_else(|| {
+            self.game_info
+                .bepinex_url(&self.config.constants.bepinex_url)
+        })
+    }
+
+    fn is_cached(&self, url: &str) -> bool {
+        self.cache_entries
+            .iter()
+            .any(|e| e.origin.url.as_deref() == Some(url))
+    }
+
+    /// Request settings for every download: `[http]` from `Config.toml` and
+    /// the Offline switch.
+    fn http(&self) -> net::HttpConfig {
+        net::HttpConfig {
+            offline: self.settings.offline,
+            ..self.config.http.clone()
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save(&self.settings_path) {
+            self.log(&format!("Could not save settings: {e}"));
+        }
+    }
+
+    /// List available BepInEx builds in the background.
+    fn start_fetch_catalog(&mut self) {
+        if self.catalog_task.is_some() || self.settings.offline {
+            return;
+        }
+        let http = self.http();
+        let task: CatalogTask = Arc::new(Mutex::new(None));
+        let slot = Arc::clone(&task);
+        self.catalog_task = Some(task);
+        std::thread::spawn(move || {
+            let res = catalog::fetch(catalog::BE_BUILDS_URL, catalog::GITHUB_RELEASES_URL, &http)
+                .map_err(|e| e.to_string());
+            *slot.lock().unwrap() = Some(res);
+        });
+    }
+
+    /// Show the package list saved by the last refresh, then check
+    /// Thunderstore for changes.
+    fn load_packages(&mut self) {
+        let path = packages::default_path(thunderstore::COMMUNITY);
+        if let Some(listing) = packages::load(&path) {
+            self.set_packages(listing);
+        }
+        self.start_fetch_packages();
+    }
+
+    fn set_packages(&mut self, listing: Listing) {
+        self.browse.index = SearchIndex::build(&listing.packages);
+        self.browse.packages = listing.packages;
+        self.browse.checked = Some(listing.checked);
+    }
+
+    /// Refresh the stored Thunderstore package list in the background.
+    fn start_fetch_packages(&mut self) {
+        if self.browse.fetch_task.is_some() || self.settings.offline {
+            return;
+        }
+        let http = self.http();
+        let ctx = self.egui_ctx.clone();
+        let task: PackagesTask = Arc::new(Mutex::new(None));
+        let slot = Arc::clone(&task);
+        self.browse.fetch_task = Some(task);
+        std::thread::spawn(move || {
+            let res = packages::refresh(
+                &packages::default_path(thunderstore::COMMUNITY),
+                thunderstore::BASE_URL,
+                thunderstore::COMMUNITY,
+                &http,
+            )
+            .map_err(|e| e.to_string());
+            *slot.lock().unwrap() = Some(res);
+            ctx.request_repaint();
+        });
+    }
+
+    /// `pkg` at `version` and the dependencies it is missing, dependencies
+    /// first.
+    fn package_downloads(&self, pkg: &PackageRef, version: &str) -> Result<Vec<ModDownload>> {
+        let wanted = thunderstore::resolve(&self.browse.packages, pkg, version)?;
+        // Dependencies installed at the wanted version or later stay.
+        Ok(wanted
+            .into_iter()
+            .filter(|(p, v)| {
+                p == pkg
+                    || !self.mods.mods.iter().any(|m| {
+                        m.id == p.full_name()
+                            && m.version
+                                .as_deref()
+                                .is_some_and(|have| thunderstore::satisfies(have, v))
+                    })
+            })
+            .map(|(p, v)| thunderstore::mod_download(thunderstore::BASE_URL, &p, &v))
+            .collect())
+    }
+
+    /// Download `pkg` at `version` and the dependencies it is missing, then
+    /// install them like dropped zips.
+    fn start_install_package(&mut self, pkg: PackageRef, version: String) {
+        match self.package_downloads(&pkg, &version) {
+            Ok(wanted) => self.start_download_mods(wanted),
+            Err(e) => self.log(&format!("Cannot install {}: {e}", pkg.full_name())),
+        }
+    }
+
+    /// Download `wanted` in the background; they are installed in order once
+    /// all are downloaded.
+    fn start_download_mods(&mut self, wanted: Vec<ModDownload>) {
+        if self.is_busy || wanted.is_empty() {
             return;
         }
         self.is_busy = true;
-        let url = self.config.constants.bepinex_url.clone();
-        let ua = self.config.constants.user_agent.clone();
-        let game_dir = self.game_dir.clone();
-        let task: Arc<Mutex<Option<Result<(), String>>>> = Arc::new(Mutex::new(None));
-        self.install_task = Some(task.clone());
+        let cache_root = self.cache_root.clone();
+        let http = self.http();
+        let transfer = self.new_transfer();
+        let task: ModDownloadTask = Arc::new(Mutex::new(None));
+        self.download_task = Some(Arc::clone(&task));
         std::thread::spawn(move || {
-            let res = (|| -> Result<()> {
-                let bytes = download_bytes_blocking(&url, &ua)?;
-                bepinex::install_bepinex_from_zip_bytes(&game_dir, &bytes)?;
-                if let Err(e) = bepinex::validate_bepinex_installation(&game_dir) {
-                    return Err(e);
+            let res = wanted
+                .into_iter()
+                .map(|d| {
+                    let origin =
+                        cache::Origin::new(cache::Kind::Mod, d.id.clone(), d.version.clone());
+                    let path =
+                        cache::fetch(&cache_root, &d.url, &http, &d.expected, &transfer, origin)
+                            .map_err(|e| format!("{}: {e}", d.id))?;
+                    Ok((d, path))
+                })
+                .collect();
+            *task.lock().unwrap() = Some(res);
+        });
+    }
+
+    /// Install the mods downloaded by [`Self::start_download_mods`].
+    fn poll_downloads(&mut self) {
+        let fetched = self
+            .download_task
+            .as_ref()
+            .and_then(|t| t.lock().unwrap().take());
+        let Some(res) = fetched else {
+            return;
+        };
+        self.download_task = None;
+        self.is_busy = false;
+        match res {
+            Ok(fetched) => {
+                for (download, path) in fetched {
+                    let name = match &download.version {
+                        Some(v) => format!("{} {v}", download.id),
+                        None => download.id.clone(),
+                    };
+                    if let Err(e) = self.install_mod_from_zip_path(&path, Some(&download)) {
+                        self.log(&format!("Install of {name} failed: {e}"));
+                        break;
+                    }
+                    self.log(&format!("Installed {name}."));
                 }
-                Ok(())
-            })();
-            *task.lock().unwrap() = Some(res.map_err(|e| e.to_string()));
+            }
+            Err(e) => self.log(&format!("Download failed: {e}")),
+        }
+        self.reload_game_state();
+        self.prune_cache(self.settings.cache_limit_mb);
+        // Updates that were installed are done.
+        let mods = &self.mods.mods;
+        self.updates.retain(|u| {
+            mods.iter()
+                .find(|m| m.id == u.download.id)
+                .is_some_and(|m| updates::is_newer(u.version(), m.version.as_deref()))
+        });
+    }
+
+    /// Look for newer versions of the installed mods in the background.
+    /// Offline, only the saved Thunderstore package list is consulted.
+    fn start_check_updates(&mut self) {
+        if self.update_task.is_some() {
+            return;
+        }
+        let mods = self.mods.mods.clone();
+        let cache_root = self.cache_root.clone();
+        let http = self.http();
+        let ctx = self.egui_ctx.clone();
+        let task: UpdateCheckTask = Arc::new(Mutex::new(None));
+        self.update_task = Some(Arc::clone(&task));
+        std::thread::spawn(move || {
+            let path = packages::default_path(thunderstore::COMMUNITY);
+            let mut errors = Vec::new();
+            let listing = if http.offline {
+                packages::load(&path)
+            } else {
+                let community = thunderstore::COMMUNITY;
+                match packages::refresh(&path, thunderstore::BASE_URL, community, &http) {
+                    Ok((listing, _)) => Some(listing),
+                    Err(e) => {
+                        errors.push(format!("the Thunderstore package list: {e}"));
+                        packages::load(&path)
+                    }
+                }
+            };
+            let packages = listing.map(|l| l.packages).unwrap_or_default();
+            let sources = updates::Sources {
+                packages: &packages,
+                thunderstore: thunderstore::BASE_URL,
+                github: updates::GITHUB_API_URL,
+                cache_root: &cache_root,
+            };
+            let (found, failed) = updates::check_all(&mods, &sources, &http);
+            errors.extend(failed);
+            *task.lock().unwrap() = Some((found, errors));
+            ctx.request_repaint();
         });
     }
 
+    fn poll_update_check(&mut self) {
+        let checked = self
+            .update_task
+            .as_ref()
+            .and_then(|t| t.lock().unwrap().take());
+        let Some((found, errors)) = checked else {
+            return;
+        };
+        self.update_task = None;
+        self.updates_checked = Some(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        );
+        for e in &errors {
+            self.log(&format!("Could not check for updates of {e}"));
+        }
+        self.updates = found;
+        let offered = self.offered_updates().len();
+        if offered > 0 {
+            self.log(&format!("Updates are available for {offered} mods."));
+        }
+    }
+
+    /// Updates found for mods that are not pinned, leaving out versions the
+    /// user chose to skip.
+    fn offered_updates(&self) -> Vec<updates::Update> {
+        self.updates
+            .iter()
+            .filter(|u| {
+                let id = &u.download.id;
+                !self.settings.pinned_mods.contains(id)
+                    && self.settings.skipped_updates.get(id).map(String::as_str)
+                        != Some(u.version())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Download and install `chosen`, with whatever Thunderstore packages
+    /// newly depend on.
+    fn start_updates(&mut self, chosen: Vec<updates::Update>) {
+        if self.browse.packages.is_empty()
+            && let Some(listing) = packages::load(&packages::default_path(thunderstore::COMMUNITY))
+        {
+            self.set_packages(listing);
+        }
+        let mut wanted: Vec<ModDownload> = Vec::new();
+        for update in chosen {
+            let downloads = match &update.download.source {
+                ModSource::Thunderstore { namespace, name } => {
+                    let pkg = PackageRef {
+                        namespace: namespace.clone(),
+                        name: name.clone(),
+                    };
+                    match self.package_downloads(&pkg, update.version()) {
+                        Ok(downloads) => downloads,
+                        Err(e) => {
+                            self.log(&format!("Cannot update {}: {e}", update.download.id));
+                            continue;
+                        }
+                    }
+                }
+                _ => vec![update.download],
+            };
+            for d in downloads {
+                if !wanted.iter().any(|w| w.id == d.id) {
+                    wanted.push(d);
+                }
+            }
+        }
+        self.start_download_mods(wanted);
+    }
+
+    /// Download a zip from `url` and install it, remembering the URL so the
+    /// mod can be updated from it.
+    fn start_install_from_url(&mut self, url: String) {
+        let id = url
+            .split(['?', '#'])
+            .next()
+            .and_then(|u| u.trim_end_matches('/').rsplit('/').next())
+            .map(|name| name.trim_end_matches(".zip").to_string())
+            .filter(|name| !name.is_empty() && !name.contains(':'));
+        let Some(id) = id else {
+            self.log(&format!("{url} does not name a zip to download."));
+            return;
+        };
+        self.start_download_mods(vec![ModDownload {
+            id,
+            source: ModSource::Url { url: url.clone() },
+            version: None,
+            url,
+            expected: verify::Expected::default(),
+        }]);
+    }
+
+    /// Pick up the package list and icons from the Browse tab's background
+    /// work.
+    fn poll_browse(&mut self, ctx: &egui::Context) {
+        let listed = self
+            .browse
+            .fetch_task
+            .as_ref()
+            .and_then(|t| t.lock().unwrap().take());
+        if let Some(res) = listed {
+            self.browse.fetch_task = None;
+            match res {
+                Ok((listing, true)) => self.set_packages(listing),
+                Ok((listing, false)) => self.browse.checked = Some(listing.checked),
+                Err(e) => self.log(&format!("Could not list Thunderstore packages: {e}")),
+            }
+        }
+
+        let loaded = std::mem::take(&mut *self.browse.icons_loaded.lock().unwrap());
+        for (name, bytes) in loaded {
+            if let Some(tex) = load_texture_from_png_bytes(ctx, &name, &bytes) {
+                self.browse.icons.insert(name, tex);
+            }
+        }
+    }
+
+    /// Queue the icon of `package` for download, once.
+    fn request_icon(&mut self, package: &Package) {
+        if !self
+            .browse
+            .icons_requested
+            .insert(package.full_name.clone())
+        {
+            return;
+        }
+        let http = self.http();
+        let loaded = Arc::clone(&self.browse.icons_loaded);
+        let ctx = self.egui_ctx.clone();
+        let queue = self.browse.icon_queue.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel::<(Package, net::HttpConfig)>();
+            let dir = thunderstore::default_icon_dir();
+            std::thread::spawn(move || {
+                for (package, http) in rx {
+                    if let Ok(bytes) = thunderstore::fetch_icon(&dir, &package, &http) {
+                        loaded.lock().unwrap().push((package.full_name, bytes));
+                        ctx.request_repaint();
+                    }
+                }
+            });
+            tx
+        });
+        let _ = queue.send((package.clone(), http));
+    }
+
+    /// What the BepInEx archive at `url` must match: the digest published
+    /// with its catalog build, else the checks in `Config.toml`.
+    fn expected_bepinex(&self, url: &str) -> verify::Expected {
+        self.catalog
+            .iter()
+            .find(|b| b.url == url)
+            .and_then(|b| b.sha256.as_deref())
+            .map(verify::Expected::sha256)
+            .unwrap_or_else(|| self.config.expected_for(url))
+    }
+
+    /// Progress handle for the download a job is about to start, shown with
+    /// a Cancel button while it runs.
+    fn new_transfer(&mut self) -> net::Transfer {
+        let transfer = net::Transfer::default();
+        self.transfer = Some(transfer.clone());
+        transfer
+    }
+
+    /// Job step reading the BepInEx archive at `url` from the download cache,
+    /// downloading it first unless a copy is already cached.
+    fn fetch_bepinex(&mut self, url: String) -> impl FnOnce() -> Result<Vec<u8>> + Send + 'static {
+        let expected = self.expected_bepinex(&url);
+        let cache_root = self.cache_root.clone();
+        let http = self.http();
+        let transfer = self.new_transfer();
+        move || {
+            let version = bepinex::BepInExInfo::from_archive_name(&url).version;
+            let origin = cache::Origin::new(cache::Kind::BepInEx, "BepInEx", version);
+            let path = cache::fetch(&cache_root, &url, &http, &expected, &transfer, origin)?;
+            Ok(std::fs::read(path)?)
+        }
+    }
+
+    fn start_install_bepinex_stable_v5_async(&mut self) {
+        let fetch = self.fetch_bepinex(self.bepinex_url());
+        self.spawn_bepinex_install(fetch, false);
+    }
+
     fn start_install_bepinex_from_url_async(&mut self, url: String) {
+        let fetch = self.fetch_bepinex(url);
+        self.spawn_bepinex_install(fetch, false);
+    }
+
+    fn start_upgrade_bepinex(&mut self) {
+        let fetch = self.fetch_bepinex(self.bepinex_url());
+        self.spawn_bepinex_install(fetch, true);
+    }
+
+    /// Fetch a BepInEx archive on a worker thread, extract it and validate the
+    /// result, recording the extraction in the journal. An `upgrade` replaces
+    /// the core files of the installed build and keeps user files.
+    fn spawn_bepinex_install<F>(&mut self, fetch: F, upgrade: bool)
+    where
+        F: FnOnce() -> Result<Vec<u8>> + Send + 'static,
+    {
         if self.is_busy {
             return;
         }
         self.is_busy = true;
-        let ua = "restaurats-mod-manager";
         let game_dir = self.game_dir.clone();
-        let task: Arc<Mutex<Option<Result<(), String>>>> = Arc::new(Mutex::new(None));
+        let journal_root = self.journal_root.clone();
+        let snapshot_root = self.snapshot_root.clone();
+        let fixups = self.config.fixups.clone();
+        let cache_root = self.cache_root.clone();
+        let task: InstallTask = Arc::new(Mutex::new(None));
         self.install_task = Some(task.clone());
         std::thread::spawn(move || {
             let res = (|| -> Result<()> {
-                let bytes = download_bytes_blocking(&url, ua)?;
-                bepinex::install_bepinex_from_zip_bytes(&game_dir, &bytes)?;
-                if let Err(e) = bepinex::validate_bepinex_installation(&game_dir) {
-                    return Err(e);
+                let bytes = fetch()?;
+                let (files, dirs) = bepinex::archive_paths(&bytes)?;
+                if bepinex::is_bep_installed(&game_dir) {
+                    snapshot::create(&snapshot_root, &game_dir, "Before BepInEx install")?;
+                }
+                let label = if upgrade {
+                    "Upgrade BepInEx"
+                } else {
+                    "Install BepInEx"
+                };
+                let mut rec =
+                    journal::Recorder::new(&journal_root, &game_dir, OpKind::InstallBepInEx, label);
+                let replaced = if upgrade {
+                    bepinex::upgrade_replaced_files(&game_dir)
+                } else {
+                    Vec::new()
+                };
+                for rel in files
+                    .iter()
+                    .chain(&replaced)
+                    .map(String::as_str)
+                    .chain([bepinex::CONFIG_FILE, bepinex::INDEX_FILE])
+                {
+                    rec.track_file(rel)?;
+                }
+                for dir in dirs.iter().map(String::as_str).chain(
+                    bepinex::UPGRADE_REPLACED_DIRS
+                        .into_iter()
+                        .filter(|_| upgrade),
+                ) {
+                    rec.track_dir(dir);
                 }
-                Ok(())
+                let extracted = if upgrade {
+                    bepinex::upgrade_bepinex_from_zip_bytes(&game_dir, &bytes)
+                } else {
+                    bepinex::install_bepinex_from_zip_bytes(&game_dir, &bytes)
+                };
+                let res = extracted
+                    .and_then(|_| bepinex::validate_bepinex_installation(&game_dir))
+                    .and_then(|_| bepinex::record_install(&game_dir, &bytes, &cache_root))
+                    .and_then(|_| fixups::apply_auto(&game_dir, &fixups).map(|_| ()));
+                rec.commit()?;
+                res
             })();
             *task.lock().unwrap() = Some(res.map_err(|e| e.to_string()));
         });
     }
 }
 
-fn download_bytes_blocking(url: &str, user_agent: &str) -> Result<Vec<u8>> {
-    let rt = tokio::runtime::Runtime::new()?;
-    let bytes = rt.block_on(async {
-        let client = reqwest::Client::builder()
-            .user_agent(user_agent)
-            .build()
-            .map_err(|e| anyhow!(e))?;
-        let resp = client.get(url).send().await.map_err(|e| anyhow!(e))?;
-        let resp = resp.error_for_status().map_err(|e| anyhow!(e))?;
-        let b = resp.bytes().await.map_err(|e| anyhow!(e))?;
-        Ok::<Vec<u8>, anyhow::Error>(b.to_vec())
-    })?;
-    Ok(bytes)
-}
-
 fn main() -> Result<()> {
     // Load configuration (embedded in binary, optional filesystem override)
     let config = Config::load_or_default(Path::new("Config.toml"));
 
-    let mut app = AppState::default();
-    app.config = config;
-    app.game_dir = PathBuf::from(&app.config.constants.default_game_dir);
-    app.bep_status = bepinex::detect_bep_status(&app.game_dir);
-    app.mods = bepinex::load_index(&app.game_dir);
-    app.bep_ready = bepinex::is_bep_installed(&app.game_dir);
+    let mut app = AppState {
+        config,
+        ..AppState::default()
+    };
 
     let app_title = app.config.constants.app_title.clone();
-    let mut native_options = NativeOptions::default();
-    native_options.renderer = Renderer::Glow;
+    let native_options = NativeOptions {
+        renderer: Renderer::Glow,
+        ..NativeOptions::default()
+    };
     eframe::run_native(
         &app_title,
         native_options,
-        Box::new(|_cc| Ok(Box::new(app))),
+        Box::new(|cc| {
+            app.egui_ctx = cc.egui_ctx.clone();
+            let game_dir = PathBuf::from(&app.config.constants.default_game_dir);
+            app.set_game_dir(game_dir);
+            app.start_fetch_catalog();
+            if app.settings.check_updates_on_startup {
+                app.start_check_updates();
+            }
+            Ok(Box::new(app))
+        }),
     )
     .map_err(|e| anyhow!("{e}"))
 }
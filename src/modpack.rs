@@ -0,0 +1,609 @@
+use crate::cache;
+use crate::hash;
+use crate::journal::{self, OpKind};
+use crate::mods;
+use crate::thunderstore::{self, PackageRef};
+use crate::types::{ModEntry, ModIndex, ModSource};
+use crate::verify::{self, Expected};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::read::ZipArchive;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+pub const MANIFEST_NAME: &str = "modpack.toml";
+pub const FORMAT_VERSION: u32 = 1;
+pub const EXTENSION: &str = "rmpack";
+
+/// Lockfile describing an exact mod install.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Modpack {
+    pub format: u32,
+    pub name: String,
+    #[serde(default)]
+    pub mods: Vec<LockedMod>,
+    /// Bundled files from `BepInEx/config`, stored in the pack under their own path.
+    #[serde(default)]
+    pub configs: Vec<LockedFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedMod {
+    pub id: String,
+    pub name: String,
+    pub version: Option<String>,
+    /// Where the mod was originally installed from.
+    pub source: Option<String>,
+    /// Where the mod can be downloaded again when the pack does not bundle it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<ModSource>,
+    /// The archive's download URL, when it was downloaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Path of the bundled archive inside the pack.
+    pub archive: Option<String>,
+    pub archive_sha256: Option<String>,
+    /// Detached minisign signature of the archive and the key it was made
+    /// with, for packs whose author signs their archives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    pub files: Vec<LockedFile>,
+}
+
+impl LockedMod {
+    pub fn expected(&self) -> Expected {
+        Expected {
+            sha256: self.archive_sha256.clone(),
+            signature: self.signature.clone(),
+            public_key: self.public_key.clone(),
+        }
+    }
+
+    /// Where to download the locked archive: the recorded URL, else the
+    /// locked version of a Thunderstore package or the mod's URL.
+    pub fn download_url(&self) -> Option<String> {
+        if let Some(url) = &self.url {
+            return Some(url.clone());
+        }
+        match (&self.remote, &self.version) {
+            (Some(ModSource::Thunderstore { namespace, name }), Some(version)) => {
+                let pkg = PackageRef {
+                    namespace: namespace.clone(),
+                    name: name.clone(),
+                };
+                Some(thunderstore::download_url(
+                    thunderstore::BASE_URL,
+                    &pkg,
+                    version,
+                ))
+            }
+            (Some(ModSource::Url { url }), _) => Some(url.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedFile {
+    pub path: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    pub include_archives: bool,
+    pub include_configs: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub installed: usize,
+    pub removed: usize,
+    pub configs: usize,
+}
+
+/// Hash the installed files of every mod in `index`.
+pub fn lock(game_dir: &Path, index: &ModIndex, name: &str) -> Result<Modpack> {
+    let mut mods = Vec::new();
+    for m in &index.mods {
+        let mut files = Vec::new();
+        for rel in &m.installed_files {
+            let sha256 = hash::sha256_file(&game_dir.join(rel))
+                .map_err(|e| anyhow!("{} ({}): {e}", m.name, rel))?;
+            files.push(LockedFile {
+                path: rel.clone(),
+                sha256,
+            });
+        }
+        mods.push(LockedMod {
+            id: m.id.clone(),
+            name: m.name.clone(),
+            version: m.version.clone(),
+            source: m.source_zip.clone(),
+            remote: m.source.clone(),
+            url: None,
+            archive: None,
+            archive_sha256: m.archive_sha256.clone(),
+            signature: None,
+            public_key: None,
+            files,
+        });
+    }
+    Ok(Modpack {
+        format: FORMAT_VERSION,
+        name: name.to_string(),
+        mods,
+        configs: Vec::new(),
+    })
+}
+
+fn archive_name(id: &str) -> String {
+    let safe: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("archives/{safe}.zip")
+}
+
+/// Zip the installed files of a mod with their game-relative paths, which the
+/// install pipeline maps straight back to the same locations.
+fn synthesize_archive(game_dir: &Path, entry: &ModEntry) -> Result<Vec<u8>> {
+    let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    for rel in &entry.installed_files {
+        zw.start_file(rel.as_str(), options)?;
+        zw.write_all(&fs::read(game_dir.join(rel))?)?;
+    }
+    Ok(zw.finish()?.into_inner())
+}
+
+fn config_files(game_dir: &Path) -> Vec<String> {
+    let dir = game_dir.join("BepInEx").join("config");
+    walkdir::WalkDir::new(&dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(game_dir)
+                .ok()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        })
+        .collect()
+}
+
+/// Write `index` as a modpack to `dest`. Archives whose original file is gone
+/// are taken from the cache at `cache_root`.
+pub fn export(
+    game_dir: &Path,
+    index: &ModIndex,
+    dest: &Path,
+    cache_root: &Path,
+    options: ExportOptions,
+) -> Result<Modpack> {
+    let name = dest
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "modpack".into());
+    let mut pack = lock(game_dir, index, &name)?;
+    let cached = cache::list(cache_root);
+    for locked in &mut pack.mods {
+        locked.url = cached
+            .iter()
+            .find(|e| locked.archive_sha256.as_deref() == Some(e.sha256.as_str()))
+            .and_then(|e| e.origin.url.clone());
+    }
+    let mut zw = ZipWriter::new(File::create(dest)?);
+    let stored = SimpleFileOptions::default();
+    let deflated = stored.compression_method(zip::CompressionMethod::Deflated);
+
+    if options.include_archives {
+        for (locked, entry) in pack.mods.iter_mut().zip(&index.mods) {
+            let original = entry
+                .source_zip
+                .as_deref()
+                .map(PathBuf::from)
+                .filter(|p| {
+                    p.is_file() && p.extension().is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+                })
+                .or_else(|| cache::get(cache_root, entry.archive_sha256.as_deref()?));
+            let bytes = match original {
+                Some(p) => fs::read(p)?,
+                None => synthesize_archive(game_dir, entry)?,
+            };
+            let name = archive_name(&entry.id);
+            zw.start_file(name.as_str(), stored)?;
+            zw.write_all(&bytes)?;
+            locked.archive_sha256 = Some(hash::sha256_bytes(&bytes));
+            locked.archive = Some(name);
+        }
+    }
+    if options.include_configs {
+        for rel in config_files(game_dir) {
+            let bytes = fs::read(game_dir.join(&rel))?;
+            zw.start_file(rel.as_str(), deflated)?;
+            zw.write_all(&bytes)?;
+            pack.configs.push(LockedFile {
+                sha256: hash::sha256_bytes(&bytes),
+                path: rel,
+            });
+        }
+    }
+
+    zw.start_file(MANIFEST_NAME, deflated)?;
+    zw.write_all(toml::to_string_pretty(&pack)?.as_bytes())?;
+    zw.finish()?;
+    Ok(pack)
+}
+
+fn read_entry(zip: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>> {
+    let mut f = zip
+        .by_name(name)
+        .map_err(|_| anyhow!("{name} is missing from the modpack"))?;
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn parse_manifest(zip: &mut ZipArchive<File>) -> Result<Modpack> {
+    let manifest = String::from_utf8(read_entry(zip, MANIFEST_NAME)?)?;
+    let pack: Modpack = toml::from_str(&manifest)?;
+    if pack.format > FORMAT_VERSION {
+        return Err(anyhow!(
+            "Modpack format {} is newer than this manager supports ({FORMAT_VERSION})",
+            pack.format
+        ));
+    }
+    Ok(pack)
+}
+
+/// Paths from the lockfile that are missing or differ on disk.
+pub fn verify(game_dir: &Path, pack: &Modpack) -> Vec<String> {
+    pack.mods
+        .iter()
+        .flat_map(|m| m.files.iter())
+        .chain(pack.configs.iter())
+        .filter(|f| {
+            hash::sha256_file(&game_dir.join(&f.path))
+                .map(|h| h != f.sha256)
+                .unwrap_or(true)
+        })
+        .map(|f| f.path.clone())
+        .collect()
+}
+
+/// A modpack whose archives and configs all match its lockfile, ready to
+/// replace the installed mods.
+pub struct PreparedImport {
+    pack: Modpack,
+    wanted: Vec<mods::WantedMod>,
+    configs: Vec<(String, Vec<u8>)>,
+}
+
+/// Read the modpack at `pack_path` and check every file it would install
+/// against its lockfile, without touching the game. Bundled archives are
+/// added to the cache at `cache_root`; mods the pack does not bundle are
+/// taken from there or their original file, else `fetch` downloads them
+/// from the URL they were installed from.
+pub fn prepare_import<F>(
+    pack_path: &Path,
+    cache_root: &Path,
+    mut fetch: F,
+) -> Result<PreparedImport>
+where
+    F: FnMut(&LockedMod, &str) -> Result<PathBuf>,
+{
+    let mut zip = ZipArchive::new(File::open(pack_path)?)?;
+    let pack = parse_manifest(&mut zip)?;
+
+    let mut wanted = Vec::new();
+    let mut missing = Vec::new();
+    for m in &pack.mods {
+        let origin = cache::Origin::new(cache::Kind::Mod, m.name.clone(), m.version.clone());
+        let mut archive_sha256 = m.archive_sha256.clone();
+        let payload = if let Some(archive) = &m.archive {
+            let bytes = read_entry(&mut zip, archive)?;
+            verify::check(&bytes, &m.expected())
+                .map_err(|e| anyhow!("Bundled archive for {} rejected: {e}", m.name))?;
+            let sha256 = cache::put(cache_root, &bytes, origin)?;
+            let path = cache::get(cache_root, &sha256)
+                .ok_or_else(|| anyhow!("Could not cache the archive for {}", m.name))?;
+            archive_sha256 = Some(sha256);
+            mods::Payload::Zip(path)
+        } else if let Some(path) = m
+            .archive_sha256
+            .as_deref()
+            .and_then(|sha256| cache::get(cache_root, sha256))
+        {
+            mods::Payload::Zip(path)
+        } else if let Some(src) = m
+            .source
+            .as_deref()
+            .map(PathBuf::from)
+            .filter(|p| p.is_file())
+        {
+            let payload = mods::Payload::from_local(src);
+            if let mods::Payload::Zip(path) = &payload {
+                verify::check(&fs::read(path)?, &m.expected())
+                    .map_err(|e| anyhow!("Archive for {} rejected: {e}", m.name))?;
+                archive_sha256 = Some(cache::put_file(cache_root, path, origin)?);
+            }
+            payload
+        } else if let Some(url) = m.download_url() {
+            let path = fetch(m, &url).map_err(|e| anyhow!("{}: {e}", m.name))?;
+            archive_sha256 = Some(hash::sha256_file(&path)?);
+            mods::Payload::Zip(path)
+        } else {
+            missing.push(m.name.clone());
+            continue;
+        };
+        wanted.push(mods::WantedMod {
+            entry: ModEntry {
+                id: m.id.clone(),
+                name: m.name.clone(),
+                version: m.version.clone(),
+                source_zip: m.source.clone(),
+                source: m.remote.clone(),
+                archive_sha256,
+                ..Default::default()
+            },
+            payload,
+        });
+    }
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "No bundled, cached or local archive, nor a download URL, for: {}",
+            missing.join(", ")
+        ));
+    }
+    let mut configs = Vec::new();
+    for cfg in &pack.configs {
+        configs.push((cfg.path.clone(), read_entry(&mut zip, &cfg.path)?));
+    }
+
+    let mut mismatches = Vec::new();
+    for (m, w) in pack.mods.iter().zip(&wanted) {
+        let files: HashMap<String, String> = mods::payload_files(&w.payload)?.into_iter().collect();
+        for f in &m.files {
+            if files.get(&f.path) != Some(&f.sha256) {
+                mismatches.push(f.path.clone());
+            }
+        }
+        for rel in files.keys() {
+            if !m.files.iter().any(|f| &f.path == rel) {
+                mismatches.push(rel.clone());
+            }
+        }
+    }
+    for (cfg, (_, bytes)) in pack.configs.iter().zip(&configs) {
+        if hash::sha256_bytes(bytes) != cfg.sha256 {
+            mismatches.push(cfg.path.clone());
+        }
+    }
+    if !mismatches.is_empty() {
+        return Err(mismatch_error(&mismatches));
+    }
+    Ok(PreparedImport {
+        pack,
+        wanted,
+        configs,
+    })
+}
+
+impl PreparedImport {
+    pub fn name(&self) -> &str {
+        &self.pack.name
+    }
+}
+
+/// Replace the installed mods with the prepared pack's, undoing the import
+/// if the result still differs from the lockfile.
+pub fn import(
+    game_dir: &Path,
+    journal_root: &Path,
+    prepared: &PreparedImport,
+) -> Result<ImportReport> {
+    let PreparedImport {
+        pack,
+        wanted,
+        configs,
+    } = prepared;
+    let deployed = mods::deploy(
+        game_dir,
+        journal_root,
+        OpKind::ImportModpack,
+        &format!("Import modpack {}", pack.name),
+        wanted,
+        configs,
+    )?;
+    let mismatches = verify(game_dir, pack);
+    if !mismatches.is_empty() {
+        journal::undo_last(journal_root, game_dir)?;
+        return Err(mismatch_error(&mismatches));
+    }
+    Ok(ImportReport {
+        installed: deployed.installed,
+        removed: deployed.removed,
+        configs: deployed.configs,
+    })
+}
+
+fn mismatch_error(paths: &[String]) -> anyhow::Error {
+    anyhow!(
+        "{} files differ from the modpack lockfile, so nothing was imported: {}",
+        paths.len(),
+        paths.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bepinex;
+    use crate::net::{HttpConfig, Transfer};
+
+    /// Import without network access.
+    fn import_pack(
+        game_dir: &Path,
+        pack_path: &Path,
+        journal_root: &Path,
+        cache_root: &Path,
+    ) -> Result<ImportReport> {
+        let prepared = prepare_import(pack_path, cache_root, |_, url| {
+            Err(anyhow!("offline: {url}"))
+        })?;
+        import(game_dir, journal_root, &prepared)
+    }
+
+    #[test]
+    fn test_export_import_reproduces_install() {
+        let src = tempfile::tempdir().unwrap();
+        let src_dir = src.path();
+        fs::create_dir_all(src_dir.join("BepInEx/plugins/Cool")).unwrap();
+        fs::create_dir_all(src_dir.join("BepInEx/config")).unwrap();
+        fs::write(src_dir.join("BepInEx/plugins/Cool/cool.dll"), b"cool").unwrap();
+        fs::write(src_dir.join("BepInEx/config/cool.cfg"), b"[General]\n").unwrap();
+        let index = ModIndex {
+            mods: vec![ModEntry {
+                id: "cool".into(),
+                name: "Cool".into(),
+                version: Some("1.2.3".into()),
+                source_zip: Some("C:/gone/cool.zip".into()),
+                installed_files: vec!["BepInEx/plugins/Cool/cool.dll".into()],
+                source: None,
+                archive_sha256: None,
+                disabled: false,
+            }],
+            bepinex: None,
+        };
+        bepinex::save_index(src_dir, &index).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let pack_path = out.path().join("friends.rmpack");
+        let options = ExportOptions {
+            include_archives: true,
+            include_configs: true,
+        };
+        let cache_root = out.path().join("cache");
+        export(src_dir, &index, &pack_path, &cache_root, options).unwrap();
+
+        let dst = tempfile::tempdir().unwrap();
+        let journal = tempfile::tempdir().unwrap();
+        let report = import_pack(dst.path(), &pack_path, journal.path(), &cache_root).unwrap();
+        assert_eq!(report.installed, 1);
+        assert_eq!(report.configs, 1);
+        assert_eq!(
+            fs::read(dst.path().join("BepInEx/plugins/Cool/cool.dll")).unwrap(),
+            b"cool"
+        );
+        let loaded = bepinex::load_index(dst.path());
+        assert_eq!(loaded.mods[0].id, "cool");
+        assert_eq!(loaded.mods[0].version.as_deref(), Some("1.2.3"));
+
+        // A pack without archives installs from the cache the first import filled.
+        let lean = out.path().join("lean.rmpack");
+        let options = ExportOptions {
+            include_archives: false,
+            include_configs: false,
+        };
+        export(dst.path(), &loaded, &lean, &cache_root, options).unwrap();
+        let again = tempfile::tempdir().unwrap();
+        let report = import_pack(again.path(), &lean, journal.path(), &cache_root).unwrap();
+        assert_eq!(report.installed, 1);
+        assert!(again.path().join("BepInEx/plugins/Cool/cool.dll").is_file());
+
+        // A pack whose lockfile disagrees with its archive is refused before
+        // anything is replaced.
+        let mut pack = lock(dst.path(), &loaded, "tampered").unwrap();
+        pack.mods[0].files[0].sha256 = hash::sha256_bytes(b"other");
+        let tampered = out.path().join("tampered.rmpack");
+        let mut zw = ZipWriter::new(File::create(&tampered).unwrap());
+        zw.start_file(MANIFEST_NAME, SimpleFileOptions::default())
+            .unwrap();
+        zw.write_all(toml::to_string(&pack).unwrap().as_bytes())
+            .unwrap();
+        zw.finish().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let err = import_pack(target.path(), &tampered, journal.path(), &cache_root).unwrap_err();
+        assert!(err.to_string().contains("cool.dll"), "{err}");
+        assert!(!target.path().join("BepInEx").exists());
+
+        // On another machine, a lean pack downloads each mod from where it
+        // came from, pinned to the locked archive.
+        let archive = cache::get(
+            &cache_root,
+            loaded.mods[0].archive_sha256.as_deref().unwrap(),
+        );
+        let mut server = mockito::Server::new();
+        let download = server
+            .mock("GET", "/cool.zip")
+            .with_body(fs::read(archive.unwrap()).unwrap())
+            .create();
+        let mut remote = loaded.clone();
+        remote.mods[0].source_zip = None;
+        remote.mods[0].source = Some(ModSource::Url {
+            url: format!("{}/cool.zip", server.url()),
+        });
+        let shared = out.path().join("shared.rmpack");
+        let options = ExportOptions {
+            include_archives: false,
+            include_configs: false,
+        };
+        let pack = export(dst.path(), &remote, &shared, &cache_root, options).unwrap();
+        assert_eq!(pack.mods[0].remote, remote.mods[0].source);
+        let friend = tempfile::tempdir().unwrap();
+        let friend_cache = friend.path().join("cache");
+        let prepared = prepare_import(&shared, &friend_cache, |m, url| {
+            let origin = cache::Origin::new(cache::Kind::Mod, m.name.clone(), m.version.clone());
+            cache::fetch(
+                &friend_cache,
+                url,
+                &HttpConfig::default(),
+                &m.expected(),
+                &Transfer::default(),
+                origin,
+            )
+        })
+        .unwrap();
+        let game = friend.path().join("game");
+        import(&game, journal.path(), &prepared).unwrap();
+        download.assert();
+        assert!(game.join("BepInEx/plugins/Cool/cool.dll").is_file());
+        assert_eq!(
+            bepinex::load_index(&game).mods[0].source,
+            remote.mods[0].source
+        );
+    }
+
+    #[test]
+    fn test_verify_reports_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("BepInEx/plugins")).unwrap();
+        fs::write(dir.path().join("BepInEx/plugins/a.dll"), b"a").unwrap();
+        let index = ModIndex {
+            mods: vec![ModEntry {
+                id: "a".into(),
+                name: "A".into(),
+                installed_files: vec!["BepInEx/plugins/a.dll".into()],
+                ..Default::default()
+            }],
+            bepinex: None,
+        };
+        let pack = lock(dir.path(), &index, "a").unwrap();
+        assert!(verify(dir.path(), &pack).is_empty());
+        fs::write(dir.path().join("BepInEx/plugins/a.dll"), b"b").unwrap();
+        assert_eq!(verify(dir.path(), &pack), vec!["BepInEx/plugins/a.dll"]);
+    }
+}
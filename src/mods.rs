@@ -0,0 +1,528 @@
+use crate::bepinex;
+use crate::hash;
+use crate::journal::{self, OpKind};
+use crate::thunderstore::PackageRef;
+use crate::types::{ModEntry, ModIndex, ModSource};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use zip::read::ZipArchive;
+
+/// What installing a mod zip will write, computed before touching the game dir.
+pub struct ZipInstallPlan {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    /// Project page from the manifest.
+    pub website_url: Option<String>,
+    /// Zip entry index and its destination relative to the game dir.
+    pub files: Vec<(usize, String)>,
+    pub dirs: Vec<String>,
+}
+
+pub fn map_zip_entry_to_game_rel(entry: &str) -> Option<PathBuf> {
+    let lower = entry.to_lowercase();
+    if lower.contains("bepinex/plugins/") {
+        let rel = entry.split([':', '*']).next().unwrap_or(entry);
+        return Some(PathBuf::from(rel));
+    }
+    if lower.starts_with("plugins/") {
+        return Some(PathBuf::from("BepInEx").join("plugins").join(&entry[8..]));
+    }
+    if lower.starts_with("bepinex/") {
+        return Some(PathBuf::from(entry));
+    }
+    None
+}
+
+fn is_safe_rel(rel: &Path) -> bool {
+    rel.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// [`is_safe_rel`] for a path read from a file. Windows also splits on `\`,
+/// so a `..` hidden behind one would escape the game dir there.
+fn is_safe_rel_str(rel: &str) -> bool {
+    !rel.contains('\\') && is_safe_rel(Path::new(rel))
+}
+
+/// Whether `rel` names a file inside `BepInEx/config`.
+pub fn is_config_path(rel: &str) -> bool {
+    rel.starts_with("BepInEx/config/") && is_safe_rel_str(rel)
+}
+
+fn rel_string(rel: &Path) -> String {
+    rel.to_string_lossy().replace('\\', "/")
+}
+
+pub fn plan_zip_install(zip_path: &Path) -> Result<ZipInstallPlan> {
+    let file = File::open(zip_path).with_context(|| format!("Open zip {}", zip_path.display()))?;
+    let mut zip = ZipArchive::new(file)?;
+
+    let mut plan = ZipInstallPlan {
+        name: None,
+        version: None,
+        website_url: None,
+        files: Vec::new(),
+        dirs: Vec::new(),
+    };
+
+    for i in 0..zip.len() {
+        let mut f = zip.by_index(i)?;
+        let name = f.name().to_string();
+        if name.to_lowercase().ends_with("manifest.json") {
+            let mut s = String::new();
+            f.read_to_string(&mut s)?;
+            #[derive(Deserialize)]
+            struct Manifest {
+                name: Option<String>,
+                version_number: Option<String>,
+                version: Option<String>,
+                website_url: Option<String>,
+            }
+            if let Ok(mani) = serde_json::from_str::<Manifest>(&s) {
+                plan.name = mani.name.or(plan.name);
+                plan.version = mani.version_number.or(mani.version).or(plan.version);
+                plan.website_url = mani.website_url.filter(|u| !u.is_empty());
+            }
+        }
+    }
+
+    for i in 0..zip.len() {
+        let f = zip.by_index(i)?;
+        let raw_name = f.name().to_string();
+        let Some(rel) = map_zip_entry_to_game_rel(&raw_name) else {
+            continue;
+        };
+        if !is_safe_rel(&rel) {
+            continue;
+        }
+        if raw_name.ends_with('/') {
+            plan.dirs.push(rel_string(&rel));
+        } else {
+            plan.files.push((i, rel_string(&rel)));
+        }
+    }
+
+    if plan.files.is_empty() {
+        for i in 0..zip.len() {
+            let f = zip.by_index(i)?;
+            let name = f.name().to_string();
+            if !name.ends_with('/')
+                && name.to_lowercase().ends_with(".dll")
+                && let Some(file_name) = Path::new(&name).file_name()
+            {
+                plan.files.push((
+                    i,
+                    format!("BepInEx/plugins/{}", file_name.to_string_lossy()),
+                ));
+            }
+        }
+    }
+
+    if plan.files.is_empty() {
+        return Err(anyhow!("No installable files found in zip"));
+    }
+    plan.dirs.push("BepInEx/plugins".to_string());
+    Ok(plan)
+}
+
+/// Index id and source of the mod in `zip_path`.
+pub fn zip_identity(zip_path: &Path, plan: &ZipInstallPlan) -> (String, Option<ModSource>) {
+    let stem = zip_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("mod")
+        .to_string();
+    // Thunderstore and r2modman name downloads `Namespace-Name-1.2.3.zip`.
+    let package = PackageRef::parse_versioned(&stem)
+        .filter(|(p, _)| plan.name.as_deref() == Some(p.name.as_str()));
+    match package {
+        Some((p, _)) => (
+            p.full_name(),
+            Some(ModSource::Thunderstore {
+                namespace: p.namespace,
+                name: p.name,
+            }),
+        ),
+        None => (stem, plan.website_url.as_deref().and_then(github_repo)),
+    }
+}
+
+/// GitHub source for a mod whose project page is a GitHub repo.
+fn github_repo(url: &str) -> Option<ModSource> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("http://github.com/"))?;
+    let mut parts = path.split('/').filter(|p| !p.is_empty());
+    let (owner, name) = (parts.next()?, parts.next()?);
+    Some(ModSource::GitHub {
+        repo: format!("{owner}/{}", name.trim_end_matches(".git")),
+    })
+}
+
+pub fn apply_zip_install(
+    game_dir: &Path,
+    zip_path: &Path,
+    plan: &ZipInstallPlan,
+) -> Result<ModEntry> {
+    bepinex::ensure_dirs(game_dir)?;
+    let mut zip = ZipArchive::new(File::open(zip_path)?)?;
+    for dir in &plan.dirs {
+        fs::create_dir_all(game_dir.join(dir))?;
+    }
+    let mut installed_files = Vec::new();
+    for (i, rel) in &plan.files {
+        let mut f = zip.by_index(*i)?;
+        let outpath = game_dir.join(rel);
+        if let Some(p) = outpath.parent() {
+            fs::create_dir_all(p)?;
+        }
+        let mut out = File::create(&outpath)?;
+        std::io::copy(&mut f, &mut out)?;
+        installed_files.push(rel.clone());
+    }
+
+    let (id, source) = zip_identity(zip_path, plan);
+    Ok(ModEntry {
+        id: id.clone(),
+        name: plan.name.clone().unwrap_or(id),
+        version: plan.version.clone(),
+        source_zip: Some(zip_path.display().to_string()),
+        installed_files,
+        source,
+        archive_sha256: None,
+        disabled: false,
+    })
+}
+
+pub fn dll_dest_rel(dll_path: &Path) -> Result<String> {
+    let file_name = dll_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid dll path {}", dll_path.display()))?;
+    Ok(format!("BepInEx/plugins/{}", file_name.to_string_lossy()))
+}
+
+pub fn install_dll(game_dir: &Path, dll_path: &Path) -> Result<ModEntry> {
+    let rel = dll_dest_rel(dll_path)?;
+    let dest = game_dir.join(&rel);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(dll_path, &dest)?;
+    Ok(ModEntry {
+        id: dll_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("mod")
+            .to_string(),
+        name: dll_path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("mod")
+            .to_string(),
+        version: None,
+        source_zip: Some(dll_path.display().to_string()),
+        installed_files: vec![rel],
+        source: None,
+        archive_sha256: None,
+        disabled: false,
+    })
+}
+
+/// Directories that may be left empty after removing `entry`'s files, deepest
+/// first. The shared BepInEx folders themselves are never included.
+pub fn prunable_dirs(entry: &ModEntry) -> Vec<String> {
+    let keep = [
+        "BepInEx",
+        "BepInEx/plugins",
+        "BepInEx/config",
+        "BepInEx/patchers",
+    ];
+    let mut dirs: Vec<String> = Vec::new();
+    for rel in &entry.installed_files {
+        let mut cur = rel.as_str();
+        while let Some((parent, _)) = cur.rsplit_once('/') {
+            if keep.iter().any(|k| k.eq_ignore_ascii_case(parent)) {
+                break;
+            }
+            if !dirs.iter().any(|d| d == parent) {
+                dirs.push(parent.to_string());
+            }
+            cur = parent;
+        }
+    }
+    dirs.sort_by_key(|d| std::cmp::Reverse(d.matches('/').count()));
+    dirs
+}
+
+/// Files the installed `prev` has that its new version's `plan` no longer
+/// ships, so an update can remove them.
+pub fn dropped_files(prev: &ModEntry, plan: &ZipInstallPlan) -> Vec<String> {
+    prev.installed_files
+        .iter()
+        .filter(|rel| !plan.files.iter().any(|(_, p)| p.eq_ignore_ascii_case(rel)))
+        .cloned()
+        .collect()
+}
+
+/// Remove the tracked files of `entry`. Returns whether anything was removed.
+pub fn uninstall_files(game_dir: &Path, entry: &ModEntry) -> bool {
+    let mut removed_any = false;
+    for rel in &entry.installed_files {
+        let p = game_dir.join(rel);
+        if p.exists() && fs::remove_file(&p).is_ok() {
+            removed_any = true;
+        }
+    }
+    for dir in prunable_dirs(entry) {
+        let _ = fs::remove_dir(game_dir.join(dir));
+    }
+    removed_any
+}
+
+/// How `BepInEx/plugins` on disk compares with the mod index.
+#[derive(Debug, Default, PartialEq)]
+pub struct PluginScan {
+    /// Plugin files no mod claims, e.g. copied in by hand.
+    pub unmanaged: Vec<String>,
+    /// IDs of mods with installed files that are gone.
+    pub missing: Vec<String>,
+}
+
+pub fn scan_plugins(game_dir: &Path, index: &ModIndex) -> PluginScan {
+    let claimed = |rel: &str| {
+        index.mods.iter().any(|m| {
+            m.installed_files
+                .iter()
+                .any(|f| f.eq_ignore_ascii_case(rel))
+        })
+    };
+    let unmanaged = walkdir::WalkDir::new(game_dir.join("BepInEx").join("plugins"))
+        .sort_by_file_name()
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let rel = e.path().strip_prefix(game_dir).ok()?;
+            Some(rel.to_string_lossy().replace('\\', "/"))
+        })
+        .filter(|rel| !claimed(rel))
+        .collect();
+    let missing = index
+        .mods
+        .iter()
+        .filter(|m| m.installed_files.iter().any(|f| !game_dir.join(f).exists()))
+        .map(|m| m.id.clone())
+        .collect();
+    PluginScan { unmanaged, missing }
+}
+
+/// Where a mod of a deployed set is installed from.
+pub enum Payload {
+    Zip(PathBuf),
+    Dll(PathBuf),
+    /// Files already laid out like the game dir under `root`, e.g. an
+    /// r2modman profile folder.
+    Files {
+        root: PathBuf,
+        files: Vec<String>,
+    },
+}
+
+impl Payload {
+    pub fn from_local(path: PathBuf) -> Self {
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("dll"))
+        {
+            Payload::Dll(path)
+        } else {
+            Payload::Zip(path)
+        }
+    }
+}
+
+/// Files `payload` installs and their SHA-256, by game-relative path, read
+/// without touching the game dir.
+pub fn payload_files(payload: &Payload) -> Result<Vec<(String, String)>> {
+    match payload {
+        Payload::Zip(path) => {
+            let plan = plan_zip_install(path)?;
+            let mut zip = ZipArchive::new(File::open(path)?)?;
+            let mut files = Vec::new();
+            for (i, rel) in plan.files {
+                let mut bytes = Vec::new();
+                zip.by_index(i)?.read_to_end(&mut bytes)?;
+                files.push((rel, hash::sha256_bytes(&bytes)));
+            }
+            Ok(files)
+        }
+        Payload::Dll(path) => Ok(vec![(dll_dest_rel(path)?, hash::sha256_file(path)?)]),
+        Payload::Files { root, files } => files
+            .iter()
+            .map(|rel| Ok((rel.clone(), hash::sha256_file(&root.join(rel))?)))
+            .collect(),
+    }
+}
+
+/// Where `rel` goes while its mod is disabled: DLLs get `.old` appended so
+/// BepInEx skips them, as r2modman does.
+pub fn disabled_path(rel: &str) -> String {
+    if rel.to_ascii_lowercase().ends_with(".dll") {
+        format!("{rel}.old")
+    } else {
+        rel.to_string()
+    }
+}
+
+/// Rename the DLLs among `files` to their disabled names.
+fn disable_files(game_dir: &Path, files: &[String]) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    for rel in files {
+        let to = disabled_path(rel);
+        if to != *rel {
+            fs::rename(game_dir.join(rel), game_dir.join(&to))?;
+        }
+        out.push(to);
+    }
+    Ok(out)
+}
+
+/// A mod to deploy. `entry` carries its identity; installed files are filled
+/// in by the install, disabled if `entry.disabled`.
+pub struct WantedMod {
+    pub entry: ModEntry,
+    pub payload: Payload,
+}
+
+#[derive(Debug, Default)]
+pub struct DeployReport {
+    pub installed: usize,
+    pub removed: usize,
+    pub configs: usize,
+}
+
+enum Planned<'a> {
+    Zip(&'a Path, ZipInstallPlan),
+    Dll(&'a Path),
+    Files(&'a Path, &'a [String]),
+}
+
+fn copy_files(game_dir: &Path, root: &Path, files: &[String]) -> Result<ModEntry> {
+    for rel in files {
+        let out = game_dir.join(rel);
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(root.join(rel), out)?;
+    }
+    Ok(ModEntry {
+        installed_files: files.to_vec(),
+        ..Default::default()
+    })
+}
+
+/// Replace every installed mod with `wanted` and write `configs` (paths under
+/// `BepInEx/config`), recorded as a single journal operation.
+pub fn deploy(
+    game_dir: &Path,
+    journal_root: &Path,
+    kind: OpKind,
+    label: &str,
+    wanted: &[WantedMod],
+    configs: &[(String, Vec<u8>)],
+) -> Result<DeployReport> {
+    if let Some((bad, _)) = configs.iter().find(|(path, _)| !is_config_path(path)) {
+        return Err(anyhow!(
+            "Refusing config path outside BepInEx/config: {bad}"
+        ));
+    }
+    let mut planned = Vec::new();
+    for w in wanted {
+        planned.push(match &w.payload {
+            Payload::Zip(path) => Planned::Zip(path, plan_zip_install(path)?),
+            Payload::Dll(path) => Planned::Dll(path),
+            Payload::Files { root, files } => {
+                if let Some(bad) = files.iter().find(|f| !is_safe_rel_str(f)) {
+                    return Err(anyhow!("Refusing unsafe path {bad}"));
+                }
+                Planned::Files(root, files)
+            }
+        });
+    }
+
+    let current = bepinex::load_index(game_dir);
+    let mut rec = journal::Recorder::new(journal_root, game_dir, kind, label);
+    rec.track_file(bepinex::INDEX_FILE)?;
+    for m in &current.mods {
+        for rel in &m.installed_files {
+            rec.track_file(rel)?;
+        }
+        for dir in prunable_dirs(m) {
+            rec.track_dir(&dir);
+        }
+    }
+    for (w, p) in wanted.iter().zip(&planned) {
+        let files = match p {
+            Planned::Zip(_, plan) => {
+                for dir in &plan.dirs {
+                    rec.track_dir(dir);
+                }
+                plan.files.iter().map(|(_, rel)| rel.clone()).collect()
+            }
+            Planned::Dll(path) => vec![dll_dest_rel(path)?],
+            Planned::Files(_, files) => files.to_vec(),
+        };
+        for rel in &files {
+            rec.track_file(rel)?;
+            if w.entry.disabled {
+                rec.track_file(&disabled_path(rel))?;
+            }
+        }
+    }
+    for (path, _) in configs {
+        rec.track_file(path)?;
+    }
+
+    let mut report = DeployReport::default();
+    let applied = (|| -> Result<()> {
+        for m in &current.mods {
+            uninstall_files(game_dir, m);
+            report.removed += 1;
+        }
+        let mut index = ModIndex {
+            bepinex: current.bepinex.clone(),
+            ..Default::default()
+        };
+        for (w, p) in wanted.iter().zip(&planned) {
+            let installed = match p {
+                Planned::Zip(path, plan) => apply_zip_install(game_dir, path, plan)?,
+                Planned::Dll(path) => install_dll(game_dir, path)?,
+                Planned::Files(root, files) => copy_files(game_dir, root, files)?,
+            };
+            let installed_files = if w.entry.disabled {
+                disable_files(game_dir, &installed.installed_files)?
+            } else {
+                installed.installed_files
+            };
+            index.mods.push(ModEntry {
+                installed_files,
+                ..w.entry.clone()
+            });
+            report.installed += 1;
+        }
+        for (path, bytes) in configs {
+            let out = game_dir.join(path);
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(out, bytes)?;
+            report.configs += 1;
+        }
+        bepinex::save_index(game_dir, &index)
+    })();
+    rec.commit()?;
+    applied?;
+    Ok(report)
+}
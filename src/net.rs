@@ -0,0 +1,489 @@
+use crate::verify::{self, Expected};
+use anyhow::{Result, anyhow};
+use reqwest::header::{
+    ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Certificate, Proxy, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::error::Error as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const ATTEMPTS: u32 = 4;
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// One runtime for every request, instead of one per download.
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("failed to start the HTTP runtime")
+    })
+}
+
+/// How every request is made: `[http]` in Config.toml plus the app's
+/// Offline switch.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    /// e.g. `http://proxy.example:3128`. Without one, the `HTTPS_PROXY` and
+    /// `HTTP_PROXY` environment variables apply.
+    pub proxy: Option<String>,
+    /// PEM files with extra root certificates, e.g. of a proxy that
+    /// inspects TLS traffic.
+    pub ca_certs: Vec<PathBuf>,
+    pub connect_timeout_secs: u64,
+    /// Longest wait for the next chunk before the connection counts as dropped.
+    pub read_timeout_secs: u64,
+    /// Never touch the network and work from the download cache.
+    #[serde(skip)]
+    pub offline: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: "restaurats-mod-manager".to_string(),
+            proxy: None,
+            ca_certs: Vec::new(),
+            connect_timeout_secs: 15,
+            read_timeout_secs: 30,
+            offline: false,
+        }
+    }
+}
+
+fn client(http: &HttpConfig, url: &str) -> Result<reqwest::Client> {
+    if http.offline {
+        return Err(anyhow!(
+            "Offline mode is on, so {url} was not downloaded. Turn it off to use the network"
+        ));
+    }
+    let mut builder = reqwest::Client::builder()
+        .user_agent(&http.user_agent)
+        .connect_timeout(Duration::from_secs(http.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(http.read_timeout_secs));
+    if let Some(proxy) = http.proxy.as_deref().filter(|p| !p.trim().is_empty()) {
+        builder = builder.proxy(
+            Proxy::all(proxy.trim()).map_err(|e| anyhow!("Invalid proxy URL {proxy}: {e}"))?,
+        );
+    }
+    for path in &http.ca_certs {
+        let pem = fs::read(path)
+            .map_err(|e| anyhow!("Could not read CA certificate {}: {e}", path.display()))?;
+        for cert in Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow!("Invalid CA certificate {}: {e}", path.display()))?
+        {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    builder.build().map_err(|e| anyhow!(e))
+}
+
+/// A failed request in words the user can act on, rather than reqwest's.
+fn describe(e: reqwest::Error, http: &HttpConfig) -> anyhow::Error {
+    let mut cause = String::new();
+    let mut source = e.source();
+    while let Some(s) = source {
+        cause = s.to_string();
+        source = s.source();
+    }
+    let check = if http.proxy.is_some() {
+        "check your connection and the proxy in Config.toml, or turn on Offline mode"
+    } else {
+        "check your connection, or turn on Offline mode"
+    };
+    if e.is_timeout() {
+        anyhow!("The server did not respond in time ({check})")
+    } else if e.is_connect() && cause.to_ascii_lowercase().contains("certificate") {
+        anyhow!(
+            "The server's certificate is not trusted ({cause}). Behind a TLS-inspecting proxy, add its CA under ca_certs in Config.toml"
+        )
+    } else if e.is_connect() {
+        anyhow!("Could not connect ({check}): {cause}")
+    } else {
+        anyhow!(e)
+    }
+}
+
+/// Snapshot of a running download.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub url: String,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    pub bytes_per_sec: f64,
+}
+
+/// Handle shared between a download and the UI: progress out, cancel in.
+#[derive(Debug, Clone, Default)]
+pub struct Transfer {
+    progress: Arc<Mutex<Progress>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Transfer {
+    pub fn progress(&self) -> Progress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Progress)) {
+        f(&mut self.progress.lock().unwrap());
+    }
+}
+
+/// Small responses such as API listings, read into memory.
+pub fn download_bytes_blocking(url: &str, http: &HttpConfig) -> Result<Vec<u8>> {
+    let client = client(http, url)?;
+    runtime()
+        .block_on(async {
+            let resp = client.get(url).send().await?.error_for_status()?;
+            Ok::<_, reqwest::Error>(resp.bytes().await?.to_vec())
+        })
+        .map_err(|e| anyhow!("{url}: {}", describe(e, http)))
+}
+
+/// Validators of an earlier response, for asking the server whether it
+/// changed since.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// What to send as `If-Range`: a strong ETag, else the date.
+    fn if_range(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|e| !e.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+/// `url` with the validators of the response, or `None` when the server
+/// says it is unchanged since the response `since` describes.
+pub fn download_if_changed(
+    url: &str,
+    http: &HttpConfig,
+    since: &Validators,
+) -> Result<Option<(Vec<u8>, Validators)>> {
+    let client = client(http, url)?;
+    runtime()
+        .block_on(async {
+            let mut req = client.get(url);
+            if let Some(etag) = &since.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = &since.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, date);
+            }
+            let resp = req.send().await?;
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+            let resp = resp.error_for_status()?;
+            let validators = Validators::from_headers(resp.headers());
+            Ok::<_, reqwest::Error>(Some((resp.bytes().await?.to_vec(), validators)))
+        })
+        .map_err(|e| anyhow!("{url}: {}", describe(e, http)))
+}
+
+enum Failure {
+    /// Worth another attempt, resuming from what is on disk.
+    Retry(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Validators of the response `part` was started from, kept beside it.
+fn validators_path(part: &Path) -> PathBuf {
+    let mut name = part.file_name().unwrap_or_default().to_os_string();
+    name.push(".json");
+    part.with_file_name(name)
+}
+
+fn discard_part(part: &Path) {
+    let _ = fs::remove_file(part);
+    let _ = fs::remove_file(validators_path(part));
+}
+
+/// One request, appending to `part` when the server honours the Range header
+/// and the file is still the one `part` was started from. A part without
+/// validators can't be checked, so it is thrown away.
+async fn attempt(
+    client: &reqwest::Client,
+    http: &HttpConfig,
+    url: &str,
+    part: &Path,
+    transfer: &Transfer,
+) -> Result<(), Failure> {
+    let retry = |e: reqwest::Error| Failure::Retry(describe(e, http));
+    let mut have = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let since: Validators = fs::read(validators_path(part))
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default();
+    let mut req = client.get(url);
+    match since.if_range() {
+        Some(validator) if have > 0 => {
+            req = req
+                .header(RANGE, format!("bytes={have}-"))
+                .header(IF_RANGE, validator);
+        }
+        _ if have > 0 => {
+            discard_part(part);
+            have = 0;
+        }
+        _ => {}
+    }
+    let mut resp = req.send().await.map_err(retry)?;
+    let status = resp.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is stale; start over.
+        discard_part(part);
+        return Err(Failure::Retry(anyhow!("HTTP {status}")));
+    }
+    if status.is_client_error() {
+        return Err(Failure::Fatal(anyhow!("HTTP {status}")));
+    }
+    if !status.is_success() {
+        return Err(Failure::Retry(anyhow!("HTTP {status}")));
+    }
+    let resumed = status == StatusCode::PARTIAL_CONTENT && have > 0;
+    let validators = Validators::from_headers(resp.headers());
+    let differs = |now: &Option<String>, then: &Option<String>| now.is_some() && now != then;
+    if resumed
+        && (differs(&validators.etag, &since.etag)
+            || differs(&validators.last_modified, &since.last_modified))
+    {
+        discard_part(part);
+        return Err(Failure::Retry(anyhow!("the file changed on the server")));
+    }
+    let offset = if resumed { have } else { 0 };
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(part)
+    } else {
+        // A full response replaces whatever part was there.
+        let json = serde_json::to_vec(&validators).map_err(|e| Failure::Fatal(e.into()))?;
+        fs::write(validators_path(part), json).map_err(|e| Failure::Fatal(e.into()))?;
+        File::create(part)
+    }
+    .map_err(|e| Failure::Fatal(e.into()))?;
+    let total = resp.content_length().map(|len| len + offset);
+    transfer.update(|p| {
+        p.downloaded = offset;
+        p.total = total;
+    });
+
+    let started = Instant::now();
+    let mut received = 0u64;
+    while let Some(chunk) = resp.chunk().await.map_err(retry)? {
+        if transfer.is_cancelled() {
+            return Err(Failure::Fatal(anyhow!("Download cancelled")));
+        }
+        file.write_all(&chunk)
+            .map_err(|e| Failure::Fatal(e.into()))?;
+        received += chunk.len() as u64;
+        let secs = started.elapsed().as_secs_f64().max(0.001);
+        transfer.update(|p| {
+            p.downloaded = offset + received;
+            p.bytes_per_sec = received as f64 / secs;
+        });
+    }
+    if total.is_some_and(|t| offset + received < t) {
+        return Err(Failure::Retry(anyhow!("connection closed early")));
+    }
+    Ok(())
+}
+
+/// Stream `url` into `dest`, resuming a partial download left by an earlier
+/// attempt and retrying with backoff. `dest` only appears once complete.
+pub fn download_to_file(
+    url: &str,
+    http: &HttpConfig,
+    dest: &Path,
+    transfer: &Transfer,
+) -> Result<()> {
+    let client = client(http, url)?;
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = part_path(dest);
+    transfer.update(|p| {
+        *p = Progress {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    });
+    let mut backoff = FIRST_BACKOFF;
+    for n in 1..=ATTEMPTS {
+        match runtime().block_on(attempt(&client, http, url, &part, transfer)) {
+            Ok(()) => {
+                let _ = fs::remove_file(validators_path(&part));
+                fs::rename(&part, dest)?;
+                return Ok(());
+            }
+            Err(Failure::Fatal(e)) => {
+                if !transfer.is_cancelled() {
+                    discard_part(&part);
+                }
+                return Err(anyhow!("{url}: {e}"));
+            }
+            Err(Failure::Retry(e)) if n == ATTEMPTS => {
+                return Err(anyhow!("{url}: {e} (gave up after {ATTEMPTS} attempts)"));
+            }
+            Err(Failure::Retry(_)) => {
+                let until = Instant::now() + backoff;
+                while Instant::now() < until {
+                    if transfer.is_cancelled() {
+                        return Err(anyhow!("Download cancelled"));
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                backoff *= 2;
+            }
+        }
+    }
+    unreachable!("the last attempt always returns")
+}
+
+/// Where archives are streamed to before they are checked.
+fn downloads_dir() -> PathBuf {
+    crate::config::data_dir().join("downloads")
+}
+
+/// Download an archive and reject it unless it passes `expected`.
+pub fn download_verified(
+    url: &str,
+    http: &HttpConfig,
+    expected: &Expected,
+    transfer: &Transfer,
+) -> Result<Vec<u8>> {
+    let dest = downloads_dir().join(format!(
+        "{}.zip",
+        &crate::hash::sha256_bytes(url.as_bytes())[..16]
+    ));
+    download_to_file(url, http, &dest, transfer)?;
+    let bytes = fs::read(&dest);
+    let _ = fs::remove_file(&dest);
+    let bytes = bytes?;
+    verify::check(&bytes, expected).map_err(|e| anyhow!("{url}: {e}"))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resumes_partial_download() {
+        let mut server = mockito::Server::new();
+        let rest = server
+            .mock("GET", "/mod.zip")
+            .match_header("range", "bytes=5-")
+            .match_header("if-range", "\"v1\"")
+            .with_status(206)
+            .with_header("etag", "\"v1\"")
+            .with_body("world")
+            .create();
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("mod.zip");
+        let part = part_path(&dest);
+        fs::write(&part, "hello").unwrap();
+        fs::write(validators_path(&part), r#"{"etag":"\"v1\""}"#).unwrap();
+
+        let transfer = Transfer::default();
+        let url = format!("{}/mod.zip", server.url());
+        let http = HttpConfig::default();
+        download_to_file(&url, &http, &dest, &transfer).unwrap();
+        rest.assert();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "helloworld");
+        assert!(!part.exists() && !validators_path(&part).exists());
+        let p = transfer.progress();
+        assert_eq!((p.downloaded, p.total), (10, Some(10)));
+
+        // The file changed since the part was started: the server sends all
+        // of it and the part is replaced.
+        let changed = server
+            .mock("GET", "/new.zip")
+            .match_header("if-range", "\"v1\"")
+            .with_header("etag", "\"v2\"")
+            .with_body("brand new")
+            .create();
+        let new = dir.path().join("new.zip");
+        let new_part = part_path(&new);
+        fs::write(&new_part, "stale").unwrap();
+        fs::write(validators_path(&new_part), r#"{"etag":"\"v1\""}"#).unwrap();
+        let new_url = format!("{}/new.zip", server.url());
+        download_to_file(&new_url, &http, &new, &transfer).unwrap();
+        changed.assert();
+        assert_eq!(fs::read_to_string(&new).unwrap(), "brand new");
+
+        // A part with nothing to check it against is not resumed.
+        let fresh = server
+            .mock("GET", "/new.zip")
+            .match_header("range", mockito::Matcher::Missing)
+            .with_body("brand new")
+            .create();
+        fs::write(&new_part, "stale").unwrap();
+        download_to_file(&new_url, &http, &new, &transfer).unwrap();
+        fresh.assert();
+        assert_eq!(fs::read_to_string(&new).unwrap(), "brand new");
+
+        let missing = server.mock("GET", "/gone.zip").with_status(404).create();
+        let gone = dir.path().join("gone.zip");
+        let err = download_to_file(
+            &format!("{}/gone.zip", server.url()),
+            &http,
+            &gone,
+            &transfer,
+        )
+        .unwrap_err();
+        missing.assert();
+        assert!(err.to_string().contains("404"), "{err}");
+
+        let offline = HttpConfig {
+            offline: true,
+            ..HttpConfig::default()
+        };
+        let err = download_to_file(&url, &offline, &gone, &transfer).unwrap_err();
+        assert!(err.to_string().contains("Offline mode"), "{err}");
+    }
+}
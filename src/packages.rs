@@ -0,0 +1,231 @@
+//! Local copy of a Thunderstore community's package list, refreshed with
+//! conditional requests, and the search index the Browse tab filters with.
+
+use crate::net::{self, HttpConfig, Validators};
+use crate::thunderstore::{self, Package};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Listing {
+    #[serde(default)]
+    pub validators: Validators,
+    /// When the server last confirmed the list, in seconds since the epoch.
+    #[serde(default)]
+    pub checked: u64,
+    pub packages: Vec<Package>,
+}
+
+pub fn default_path(community: &str) -> PathBuf {
+    crate::config::data_dir()
+        .join("thunderstore")
+        .join(format!("{community}.json"))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn load(path: &Path) -> Option<Listing> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+fn save(path: &Path, listing: &Listing) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = path.with_extension("json.part");
+    fs::write(&part, serde_json::to_vec(listing)?)?;
+    fs::rename(part, path)?;
+    Ok(())
+}
+
+/// Ask the package API whether the list stored at `path` is still current
+/// and store the new one if not. The result is the stored listing, with
+/// `changed` telling whether it differs from before.
+pub fn refresh(
+    path: &Path,
+    base: &str,
+    community: &str,
+    http: &HttpConfig,
+) -> Result<(Listing, bool)> {
+    let mut listing = load(path).unwrap_or_default();
+    let since = if listing.packages.is_empty() {
+        Validators::default()
+    } else {
+        listing.validators.clone()
+    };
+    let url = thunderstore::package_list_url(base, community);
+    let changed = match net::download_if_changed(&url, http, &since)? {
+        Some((json, validators)) => {
+            listing.packages = serde_json::from_slice(&json)?;
+            listing.validators = validators;
+            true
+        }
+        None => false,
+    };
+    listing.checked = now_secs();
+    save(path, &listing)?;
+    Ok((listing, changed))
+}
+
+/// Words of `text` for the index: lowercase runs of letters and digits, with
+/// CamelCase names also split into their parts (`FasterChefs` gives
+/// `fasterchefs`, `faster` and `chefs`).
+fn tokens(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        out.push(word.to_lowercase());
+        let mut part = String::new();
+        let mut prev_lower = false;
+        for c in word.chars() {
+            if c.is_uppercase() && prev_lower && !part.is_empty() {
+                out.push(std::mem::take(&mut part).to_lowercase());
+            }
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+            part.push(c);
+        }
+        if part.len() < word.len() {
+            out.push(part.to_lowercase());
+        }
+    }
+    out
+}
+
+/// Which packages each word and category appears in, by position in the
+/// listing.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    words: BTreeMap<String, BTreeSet<usize>>,
+    categories: HashMap<String, BTreeSet<usize>>,
+    len: usize,
+}
+
+impl SearchIndex {
+    /// Index names, authors, descriptions and categories of `packages`.
+    pub fn build(packages: &[Package]) -> Self {
+        let mut index = Self {
+            len: packages.len(),
+            ..Default::default()
+        };
+        for (i, p) in packages.iter().enumerate() {
+            let description = p.latest().map(|v| v.description.as_str()).unwrap_or("");
+            let text = [p.name.as_str(), &p.owner, description]
+                .into_iter()
+                .chain(p.categories.iter().map(String::as_str));
+            for word in text.flat_map(tokens) {
+                index.words.entry(word).or_default().insert(i);
+            }
+            for c in &p.categories {
+                index.categories.entry(c.clone()).or_default().insert(i);
+            }
+        }
+        index
+    }
+
+    /// Every category, sorted.
+    pub fn categories(&self) -> Vec<&str> {
+        let mut all: Vec<&str> = self.categories.keys().map(String::as_str).collect();
+        all.sort_unstable();
+        all
+    }
+
+    /// Positions of the packages in `category` (if given) where every word
+    /// of `query` starts a word of the package, in listing order.
+    pub fn search(&self, query: &str, category: Option<&str>) -> Vec<usize> {
+        let mut hits: BTreeSet<usize> = match category {
+            Some(c) => self.categories.get(c).cloned().unwrap_or_default(),
+            None => (0..self.len).collect(),
+        };
+        for word in query.split(|c: char| !c.is_alphanumeric()) {
+            if word.is_empty() || hits.is_empty() {
+                continue;
+            }
+            let word = word.to_lowercase();
+            let matching: BTreeSet<usize> = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(w, _)| w.starts_with(&word))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+            hits.retain(|i| matching.contains(i));
+        }
+        hits.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    const PACKAGES: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/thunderstore_packages.json"
+    ));
+
+    #[test]
+    fn test_conditional_refresh_and_search() {
+        let mut server = mockito::Server::new();
+        let full = server
+            .mock("GET", "/c/restaurats/api/v1/package/")
+            .match_header("if-none-match", Matcher::Missing)
+            .with_header("etag", "\"v1\"")
+            .with_header("content-type", "application/json")
+            .with_body(PACKAGES)
+            .create();
+        let unchanged = server
+            .mock("GET", "/c/restaurats/api/v1/package/")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .create();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("restaurats.json");
+        let http = HttpConfig::default();
+
+        let (listing, changed) = refresh(&path, &server.url(), "restaurats", &http).unwrap();
+        full.assert();
+        assert!(changed);
+        assert_eq!(listing.packages.len(), 4);
+        assert_eq!(listing.validators.etag.as_deref(), Some("\"v1\""));
+        let (again, changed) = refresh(&path, &server.url(), "restaurats", &http).unwrap();
+        unchanged.assert();
+        assert!(!changed);
+        assert_eq!(again.packages, listing.packages);
+        // Browsing offline works from what was stored.
+        assert_eq!(load(&path).unwrap().packages, listing.packages);
+
+        let index = SearchIndex::build(&listing.packages);
+        let names = |hits: Vec<usize>| -> Vec<String> {
+            hits.into_iter()
+                .map(|i| listing.packages[i].full_name.clone())
+                .collect()
+        };
+        assert_eq!(names(index.search("chefs", None)), ["Ratsmith-FasterChefs"]);
+        assert_eq!(
+            names(index.search("Rush hour", None)),
+            ["Ratsmith-FasterChefs"]
+        );
+        assert_eq!(
+            names(index.search("rats", None)),
+            ["Ratsmith-FasterChefs", "Ratsmith-RatLib"]
+        );
+        assert_eq!(
+            names(index.search("", Some("Libraries"))),
+            ["Ratsmith-RatLib"]
+        );
+        assert!(index.search("editor", Some("Libraries")).is_empty());
+        assert_eq!(index.search("", None).len(), 4);
+        assert_eq!(index.categories(), ["Libraries", "Mods", "Tools", "Tweaks"]);
+    }
+}
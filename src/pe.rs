@@ -0,0 +1,102 @@
+//! Just enough of the PE format to read a DLL's machine type and the version
+//! strings from its `VS_VERSIONINFO` resource.
+
+/// `IMAGE_FILE_MACHINE_*` values we care about.
+pub const MACHINE_I386: u16 = 0x014c;
+pub const MACHINE_AMD64: u16 = 0x8664;
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// `Machine` field of the COFF header, or `None` if this is not a PE image.
+pub fn machine(bytes: &[u8]) -> Option<u16> {
+    if bytes.get(..2)? != b"MZ" {
+        return None;
+    }
+    let pe = u32_at(bytes, 0x3c)? as usize;
+    if bytes.get(pe..pe + 4)? != b"PE\0\0" {
+        return None;
+    }
+    u16_at(bytes, pe + 4)
+}
+
+/// `dwFileVersionMS/LS` of the `VS_FIXEDFILEINFO` block, as four parts.
+pub fn file_version(bytes: &[u8]) -> Option<[u16; 4]> {
+    const SIGNATURE: [u8; 4] = 0xFEEF_04BDu32.to_le_bytes();
+    let at = bytes.windows(4).position(|w| w == SIGNATURE)?;
+    let ms = u32_at(bytes, at + 8)?;
+    let ls = u32_at(bytes, at + 12)?;
+    Some([(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16])
+}
+
+/// Value of a `StringFileInfo` entry such as `ProductVersion`.
+pub fn version_string(bytes: &[u8], key: &str) -> Option<String> {
+    let needle: Vec<u8> = key
+        .encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let mut at = bytes.windows(needle.len()).position(|w| w == needle)? + needle.len();
+    // The value starts on the next 32-bit boundary.
+    at = (at + 3) & !3;
+    let units: Vec<u16> = bytes
+        .get(at..)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&u| u != 0)
+        .collect();
+    let value = String::from_utf16(&units).ok()?;
+    (!value.is_empty()).then_some(value)
+}
+
+/// Smallest image `machine` accepts, for tests elsewhere.
+#[cfg(test)]
+pub fn test_image(machine: u16) -> Vec<u8> {
+    let mut dll = vec![0u8; 0x48];
+    dll[..2].copy_from_slice(b"MZ");
+    dll[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+    dll[0x40..0x44].copy_from_slice(b"PE\0\0");
+    dll[0x44..0x46].copy_from_slice(&machine.to_le_bytes());
+    dll
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16z(s: &str) -> Vec<u8> {
+        s.encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn test_reads_machine_and_versions() {
+        let mut dll = test_image(MACHINE_AMD64);
+        dll.resize(0x80, 0);
+
+        dll.extend(0xFEEF_04BDu32.to_le_bytes());
+        dll.extend(0x0001_0000u32.to_le_bytes());
+        dll.extend(0x0006_0000u32.to_le_bytes());
+        dll.extend(0x0000_02f0u32.to_le_bytes());
+        dll.extend(utf16z("ProductVersion"));
+        while !dll.len().is_multiple_of(4) {
+            dll.push(0);
+        }
+        dll.extend(utf16z("6.0.0-be.752+dd0655f"));
+
+        assert_eq!(machine(&dll), Some(MACHINE_AMD64));
+        assert_eq!(file_version(&dll), Some([6, 0, 0, 752]));
+        assert_eq!(
+            version_string(&dll, "ProductVersion").as_deref(),
+            Some("6.0.0-be.752+dd0655f")
+        );
+        assert_eq!(machine(b"not a dll"), None);
+    }
+}
@@ -0,0 +1,524 @@
+use crate::hash;
+use crate::journal::OpKind;
+use crate::mods::{self, Payload, WantedMod};
+use crate::thunderstore::PackageRef;
+use crate::types::{ModEntry, ModIndex, ModSource};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::read::ZipArchive;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// Profile listing inside an r2modman `.r2z` export.
+pub const R2X_NAME: &str = "export.r2x";
+pub const EXTENSION: &str = "r2z";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct R2xProfile {
+    pub profile_name: String,
+    #[serde(default)]
+    pub mods: Vec<R2xMod>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct R2xMod {
+    /// Thunderstore `Namespace-Name`.
+    pub name: String,
+    pub version: R2xVersion,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct R2xVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl R2xVersion {
+    pub fn parse(v: &str) -> Option<Self> {
+        let mut parts = v.trim().split('.').map(|p| p.parse::<u32>().ok());
+        let version = Self {
+            major: parts.next()??,
+            minor: parts.next().unwrap_or(Some(0))?,
+            patch: parts.next().unwrap_or(Some(0))?,
+        };
+        Some(version)
+    }
+}
+
+impl std::fmt::Display for R2xVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// An `.r2z` read into memory: the profile listing plus its config files.
+pub struct R2zContents {
+    pub profile: R2xProfile,
+    pub configs: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportReport {
+    pub exported: usize,
+    pub configs: usize,
+    /// Mods without a Thunderstore identity, which r2modman cannot install.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub installed: usize,
+    pub removed: usize,
+    pub configs: usize,
+    /// Mods the profile has disabled, installed disabled.
+    pub disabled: usize,
+    pub skipped: Vec<String>,
+}
+
+pub fn read_r2z(path: &Path) -> Result<R2zContents> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+    let mut profile = None;
+    let mut configs = Vec::new();
+    for i in 0..zip.len() {
+        let mut f = zip.by_index(i)?;
+        if f.is_dir() {
+            continue;
+        }
+        let name = f.name().replace('\\', "/");
+        if name == R2X_NAME {
+            let mut s = String::new();
+            f.read_to_string(&mut s)?;
+            profile = Some(serde_yaml::from_str::<R2xProfile>(&s)?);
+        } else if name.starts_with("BepInEx/config/") && f.enclosed_name().is_some() {
+            let mut buf = Vec::new();
+            f.read_to_end(&mut buf)?;
+            configs.push((name, buf));
+        }
+    }
+    let profile = profile.ok_or_else(|| anyhow!("{R2X_NAME} not found; not an r2modman export"))?;
+    Ok(R2zContents { profile, configs })
+}
+
+pub fn export_r2z(
+    game_dir: &Path,
+    index: &ModIndex,
+    profile_name: &str,
+    dest: &Path,
+) -> Result<ExportReport> {
+    let mut report = ExportReport::default();
+    let mut profile = R2xProfile {
+        profile_name: profile_name.to_string(),
+        mods: Vec::new(),
+    };
+    for m in &index.mods {
+        let version = m.version.as_deref().and_then(R2xVersion::parse);
+        match (&m.source, version) {
+            (Some(ModSource::Thunderstore { namespace, name }), Some(version)) => {
+                profile.mods.push(R2xMod {
+                    name: format!("{namespace}-{name}"),
+                    version,
+                    enabled: !m.disabled,
+                });
+                report.exported += 1;
+            }
+            _ => report.skipped.push(m.name.clone()),
+        }
+    }
+
+    let mut zw = ZipWriter::new(File::create(dest)?);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zw.start_file(R2X_NAME, options)?;
+    zw.write_all(serde_yaml::to_string(&profile)?.as_bytes())?;
+    let config_dir = game_dir.join("BepInEx").join("config");
+    for entry in walkdir::WalkDir::new(&config_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let rel = entry
+            .path()
+            .strip_prefix(game_dir)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        zw.start_file(rel, options)?;
+        zw.write_all(&fs::read(entry.path())?)?;
+        report.configs += 1;
+    }
+    zw.finish()?;
+    Ok(report)
+}
+
+/// Replace the installed mods with the profile's; mods it doesn't list are
+/// removed, as there are no profiles to import into. Mods the profile has
+/// disabled are installed disabled. `fetch` resolves a package version to a
+/// local zip, from a cache or by downloading it.
+pub fn import_r2z<F>(
+    game_dir: &Path,
+    contents: &R2zContents,
+    journal_root: &Path,
+    mut fetch: F,
+) -> Result<ImportReport>
+where
+    F: FnMut(&PackageRef, &str) -> Result<PathBuf>,
+{
+    let mut report = ImportReport::default();
+    let mut wanted = Vec::new();
+    for m in &contents.profile.mods {
+        let Some(pkg) = PackageRef::parse(&m.name) else {
+            report
+                .skipped
+                .push(format!("{} (unrecognised name)", m.name));
+            continue;
+        };
+        if pkg.is_bepinex_pack() {
+            report
+                .skipped
+                .push(format!("{} (BepInEx is installed separately)", m.name));
+            continue;
+        }
+        let version = m.version.to_string();
+        let archive = match fetch(&pkg, &version) {
+            Ok(archive) => archive,
+            // A mod that is off anyway doesn't hold up the import.
+            Err(e) if !m.enabled => {
+                report.skipped.push(format!("{} (disabled; {e})", m.name));
+                continue;
+            }
+            Err(e) => return Err(anyhow!("{}: {e}", m.name)),
+        };
+        report.disabled += usize::from(!m.enabled);
+        wanted.push(WantedMod {
+            entry: ModEntry {
+                id: pkg.full_name(),
+                name: pkg.name.clone(),
+                version: Some(version),
+                source_zip: Some(archive.display().to_string()),
+                archive_sha256: hash::sha256_file(&archive).ok(),
+                source: Some(ModSource::Thunderstore {
+                    namespace: pkg.namespace.clone(),
+                    name: pkg.name.clone(),
+                }),
+                disabled: !m.enabled,
+                ..Default::default()
+            },
+            payload: Payload::Zip(archive),
+        });
+    }
+
+    let deployed = mods::deploy(
+        game_dir,
+        journal_root,
+        OpKind::ImportProfile,
+        &format!("Import r2modman profile {}", contents.profile.profile_name),
+        &wanted,
+        &contents.configs,
+    )?;
+    report.installed = deployed.installed;
+    report.removed = deployed.removed;
+    report.configs = deployed.configs;
+    Ok(report)
+}
+
+/// Per-profile mod list r2modman keeps next to the profile's `BepInEx/` tree.
+pub const MODS_YML: &str = "mods.yml";
+
+/// The subset of an r2modman `mods.yml` entry the migration needs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileMod {
+    /// Thunderstore `Namespace-Name`.
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    pub version_number: R2xVersion,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// A mod found in a profile folder, with the files r2modman installed for it.
+#[derive(Debug, Clone)]
+pub struct ScannedMod {
+    pub entry: ModEntry,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileScan {
+    pub root: PathBuf,
+    pub mods: Vec<ScannedMod>,
+    pub configs: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// r2modman installs each package into a folder named after it under these.
+const PACKAGE_DIRS: [&str; 3] = ["BepInEx/plugins", "BepInEx/patchers", "BepInEx/monomod"];
+
+/// Default location of r2modman's Restaurats profiles.
+pub fn default_profiles_dir() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|b| {
+        b.config_dir()
+            .join("r2modmanPlus-local")
+            .join("Restaurats")
+            .join("profiles")
+    })
+}
+
+fn files_under(root: &Path, rel_dir: &str) -> Vec<String> {
+    walkdir::WalkDir::new(root.join(rel_dir))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(root)
+                .ok()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        })
+        .collect()
+}
+
+/// Read `mods.yml` of an r2modman profile folder and work out which files
+/// belong to each mod.
+pub fn scan_profile_dir(root: &Path) -> Result<ProfileScan> {
+    let yml = fs::read_to_string(root.join(MODS_YML))
+        .map_err(|e| anyhow!("{MODS_YML} not found in {}: {e}", root.display()))?;
+    let listed: Vec<ProfileMod> = serde_yaml::from_str(&yml)?;
+
+    let mut scan = ProfileScan {
+        root: root.to_path_buf(),
+        mods: Vec::new(),
+        configs: files_under(root, "BepInEx/config"),
+        skipped: Vec::new(),
+    };
+    for m in listed {
+        let Some(pkg) = PackageRef::parse(&m.name) else {
+            scan.skipped.push(format!("{} (unrecognised name)", m.name));
+            continue;
+        };
+        if pkg.is_bepinex_pack() {
+            scan.skipped
+                .push(format!("{} (BepInEx is installed separately)", m.name));
+            continue;
+        }
+        let installed_files: Vec<String> = PACKAGE_DIRS
+            .iter()
+            .flat_map(|dir| files_under(root, &format!("{dir}/{}", m.name)))
+            .collect();
+        if installed_files.is_empty() {
+            scan.skipped.push(format!("{} (no files found)", m.name));
+            continue;
+        }
+        scan.mods.push(ScannedMod {
+            entry: ModEntry {
+                id: pkg.full_name(),
+                name: m.display_name.clone().unwrap_or_else(|| pkg.name.clone()),
+                version: Some(m.version_number.to_string()),
+                source_zip: None,
+                installed_files,
+                source: Some(ModSource::Thunderstore {
+                    namespace: pkg.namespace,
+                    name: pkg.name,
+                }),
+                archive_sha256: None,
+                disabled: false,
+            },
+            enabled: m.enabled,
+        });
+    }
+    Ok(scan)
+}
+
+/// Copy the enabled mods (and optionally the configs) of a scanned profile
+/// into the game dir, replacing the installed mods.
+pub fn deploy_profile_dir(
+    game_dir: &Path,
+    scan: &ProfileScan,
+    journal_root: &Path,
+    include_configs: bool,
+) -> Result<ImportReport> {
+    let mut report = ImportReport {
+        skipped: scan.skipped.clone(),
+        ..Default::default()
+    };
+    let mut wanted = Vec::new();
+    for m in &scan.mods {
+        if !m.enabled {
+            report.skipped.push(format!("{} (disabled)", m.entry.name));
+            continue;
+        }
+        wanted.push(WantedMod {
+            entry: m.entry.clone(),
+            payload: Payload::Files {
+                root: scan.root.clone(),
+                files: m.entry.installed_files.clone(),
+            },
+        });
+    }
+    let mut configs = Vec::new();
+    if include_configs {
+        for rel in &scan.configs {
+            configs.push((rel.clone(), fs::read(scan.root.join(rel))?));
+        }
+    }
+    let profile_name = scan
+        .root
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let deployed = mods::deploy(
+        game_dir,
+        journal_root,
+        OpKind::ImportProfile,
+        &format!("Migrate r2modman profile {profile_name}"),
+        &wanted,
+        &configs,
+    )?;
+    report.installed = deployed.installed;
+    report.removed = deployed.removed;
+    report.configs = deployed.configs;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SAMPLE_R2X: &str = "profileName: Co-op\nmods:\n  - name: BepInEx-BepInExPack_IL2CPP\n    version:\n      major: 6\n      minor: 0\n      patch: 733\n    enabled: true\n  - name: Chef-FasterKnives\n    version:\n      major: 1\n      minor: 2\n      patch: 0\n    enabled: true\n  - name: Chef-Broken\n    version:\n      major: 0\n      minor: 1\n      patch: 0\n    enabled: false\n";
+
+    fn package_zip(name: &str, version: &str) -> Vec<u8> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        zw.start_file("manifest.json", options).unwrap();
+        write!(zw, r#"{{"name":"{name}","version_number":"{version}"}}"#).unwrap();
+        zw.start_file(format!("plugins/{name}.dll"), options)
+            .unwrap();
+        zw.write_all(b"dll").unwrap();
+        zw.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_import_r2z_installs_enabled_packages() {
+        let tmp = tempfile::tempdir().unwrap();
+        let r2z = tmp.path().join("coop.r2z");
+        let mut zw = ZipWriter::new(File::create(&r2z).unwrap());
+        let options = SimpleFileOptions::default();
+        zw.start_file(R2X_NAME, options).unwrap();
+        zw.write_all(SAMPLE_R2X.as_bytes()).unwrap();
+        zw.start_file("BepInEx/config/chef.knives.cfg", options)
+            .unwrap();
+        zw.write_all(b"[General]\nSpeed = 2\n").unwrap();
+        zw.finish().unwrap();
+
+        let contents = read_r2z(&r2z).unwrap();
+        assert_eq!(contents.profile.profile_name, "Co-op");
+        assert_eq!(contents.profile.mods.len(), 3);
+
+        let game = tempfile::tempdir().unwrap();
+        let journal = tempfile::tempdir().unwrap();
+        let cache = tmp.path().join("cache");
+        fs::create_dir_all(&cache).unwrap();
+        let report = import_r2z(game.path(), &contents, journal.path(), |pkg, version| {
+            let path = cache.join(format!("{}-{version}.zip", pkg.full_name()));
+            fs::write(&path, package_zip(&pkg.name, version))?;
+            Ok(path)
+        })
+        .unwrap();
+
+        // The disabled mod is installed, with its DLL renamed so BepInEx
+        // skips it.
+        assert_eq!(report.installed, 2);
+        assert_eq!(report.disabled, 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(
+            game.path()
+                .join("BepInEx/plugins/FasterKnives.dll")
+                .exists()
+        );
+        assert!(game.path().join("BepInEx/plugins/Broken.dll.old").exists());
+        assert!(!game.path().join("BepInEx/plugins/Broken.dll").exists());
+        assert!(game.path().join("BepInEx/config/chef.knives.cfg").exists());
+        let index = crate::bepinex::load_index(game.path());
+        assert_eq!(index.mods[0].id, "Chef-FasterKnives");
+        assert_eq!(index.mods[0].version.as_deref(), Some("1.2.0"));
+        assert!(index.mods[1].disabled);
+        assert_eq!(
+            index.mods[1].installed_files,
+            ["BepInEx/plugins/Broken.dll.old"]
+        );
+
+        let exported = tmp.path().join("out.r2z");
+        let report = export_r2z(game.path(), &index, "Back", &exported).unwrap();
+        assert_eq!(report.exported, 2);
+        let back = read_r2z(&exported).unwrap();
+        assert_eq!(back.profile.mods[0].name, "Chef-FasterKnives");
+        assert!(!back.profile.mods[1].enabled);
+        assert_eq!(back.configs.len(), 1);
+
+        // Undoing the import takes the disabled files away too.
+        crate::journal::undo_last(journal.path(), game.path()).unwrap();
+        assert!(!game.path().join("BepInEx/plugins/Broken.dll.old").exists());
+    }
+
+    #[test]
+    fn test_scan_and_deploy_profile_dir() {
+        let profile = tempfile::tempdir().unwrap();
+        let root = profile.path();
+        fs::write(
+            root.join(MODS_YML),
+            "- manifestVersion: 2\n  name: Chef-FasterKnives\n  displayName: Faster Knives\n  versionNumber:\n    major: 1\n    minor: 0\n    patch: 4\n  enabled: true\n- manifestVersion: 2\n  name: Chef-Off\n  versionNumber:\n    major: 0\n    minor: 1\n    patch: 0\n  enabled: false\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("BepInEx/plugins/Chef-FasterKnives/lib")).unwrap();
+        fs::write(
+            root.join("BepInEx/plugins/Chef-FasterKnives/FasterKnives.dll"),
+            b"a",
+        )
+        .unwrap();
+        fs::write(
+            root.join("BepInEx/plugins/Chef-FasterKnives/lib/Helper.dll"),
+            b"b",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("BepInEx/plugins/Chef-Off")).unwrap();
+        fs::write(root.join("BepInEx/plugins/Chef-Off/Off.dll.old"), b"c").unwrap();
+        fs::create_dir_all(root.join("BepInEx/config")).unwrap();
+        fs::write(root.join("BepInEx/config/chef.knives.cfg"), b"[General]\n").unwrap();
+
+        let scan = scan_profile_dir(root).unwrap();
+        assert_eq!(scan.mods.len(), 2);
+        assert_eq!(scan.mods[0].entry.name, "Faster Knives");
+        assert_eq!(
+            scan.mods[0].entry.installed_files,
+            vec![
+                "BepInEx/plugins/Chef-FasterKnives/FasterKnives.dll",
+                "BepInEx/plugins/Chef-FasterKnives/lib/Helper.dll"
+            ]
+        );
+
+        let game = tempfile::tempdir().unwrap();
+        let journal = tempfile::tempdir().unwrap();
+        let report = deploy_profile_dir(game.path(), &scan, journal.path(), true).unwrap();
+        assert_eq!(report.installed, 1);
+        assert_eq!(report.configs, 1);
+        assert!(
+            game.path()
+                .join("BepInEx/plugins/Chef-FasterKnives/lib/Helper.dll")
+                .exists()
+        );
+        assert!(!game.path().join("BepInEx/plugins/Chef-Off").exists());
+        let index = crate::bepinex::load_index(game.path());
+        assert_eq!(index.mods[0].installed_files.len(), 2);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::bepinex;
+use anyhow::{Result, anyhow};
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Top-level entries BepInEx and the manager create in the game folder.
+/// `BepInEx/` holds plugins, configs, the mod index and generated interop
+/// and cache folders.
+pub const MANAGED_ENTRIES: [&str; 6] = [
+    "BepInEx",
+    "dotnet",
+    "winhttp.dll",
+    "doorstop_config.ini",
+    ".doorstop_version",
+    ".mod-manager-restore",
+];
+
+/// One top-level entry a reset deletes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResetEntry {
+    pub rel: String,
+    pub is_dir: bool,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Whether `top` (a top-level name in the game folder) belongs to the game
+/// itself. Nothing matching this is ever deleted.
+fn is_game_entry(top: &str) -> bool {
+    let lower = top.to_ascii_lowercase();
+    lower.ends_with("_data")
+        || lower.ends_with(".exe")
+        || matches!(
+            lower.as_str(),
+            "unityplayer.dll"
+                | "gameassembly.dll"
+                | "baselib.dll"
+                | "unitycrashhandler64.exe"
+                | "monobleedingedge"
+                | "d3d12"
+        )
+}
+
+/// Everything a reset to vanilla would delete: the known BepInEx entries plus
+/// the top-level entries of every tracked BepInEx and mod file.
+pub fn plan(game_dir: &Path) -> Vec<ResetEntry> {
+    let index = bepinex::load_index(game_dir);
+    let tracked = index
+        .bepinex
+        .iter()
+        .flat_map(|b| b.files.iter().map(|f| f.path.clone()))
+        .chain(index.mods.iter().flat_map(|m| m.installed_files.clone()));
+    let mut tops: Vec<String> = MANAGED_ENTRIES.iter().map(|e| e.to_string()).collect();
+    for rel in tracked {
+        let top = rel.split('/').next().unwrap_or_default().to_string();
+        if !top.is_empty() && top != ".." && !tops.contains(&top) {
+            tops.push(top);
+        }
+    }
+    tops.into_iter()
+        .filter(|top| !is_game_entry(top))
+        .filter_map(|top| {
+            let path = game_dir.join(&top);
+            let meta = fs::symlink_metadata(&path).ok()?;
+            let (files, bytes) = if meta.is_dir() {
+                WalkDir::new(&path)
+                    .into_iter()
+                    .flatten()
+                    .filter(|e| e.file_type().is_file())
+                    .fold((0, 0), |(n, b), e| {
+                        (n + 1, b + e.metadata().map(|m| m.len()).unwrap_or(0))
+                    })
+            } else {
+                (1, meta.len())
+            };
+            Some(ResetEntry {
+                rel: top,
+                is_dir: meta.is_dir(),
+                files,
+                bytes,
+            })
+        })
+        .collect()
+}
+
+/// Delete the entries of a [`plan`].
+pub fn apply(game_dir: &Path, entries: &[ResetEntry]) -> Result<()> {
+    for e in entries {
+        if is_game_entry(&e.rel) || e.rel.contains(['/', '\\']) || e.rel == ".." {
+            return Err(anyhow!("Refusing to delete {}", e.rel));
+        }
+        let path = game_dir.join(&e.rel);
+        if e.is_dir {
+            fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BepInExInstall, ModIndex, TrackedFile};
+
+    #[test]
+    fn test_plan_keeps_game_files() {
+        let game = tempfile::tempdir().unwrap();
+        let g = game.path();
+        fs::create_dir_all(g.join("BepInEx/interop")).unwrap();
+        fs::create_dir_all(g.join("dotnet")).unwrap();
+        fs::create_dir_all(g.join("Restaurats_Data")).unwrap();
+        fs::write(g.join("BepInEx/interop/Assembly-CSharp.dll"), b"12345").unwrap();
+        fs::write(g.join("winhttp.dll"), b"w").unwrap();
+        fs::write(g.join("changelog.txt"), b"v6").unwrap();
+        fs::write(g.join("Restaurats.exe"), b"game").unwrap();
+        fs::write(g.join("UnityPlayer.dll"), b"unity").unwrap();
+        let index = ModIndex {
+            bepinex: Some(BepInExInstall {
+                files: ["changelog.txt", "UnityPlayer.dll", "Restaurats_Data/x"]
+                    .iter()
+                    .map(|p| TrackedFile {
+                        path: p.to_string(),
+                        sha256: String::new(),
+                        size: None,
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        bepinex::save_index(g, &index).unwrap();
+
+        let entries = plan(g);
+        let names: Vec<&str> = entries.iter().map(|e| e.rel.as_str()).collect();
+        assert_eq!(names, ["BepInEx", "dotnet", "winhttp.dll", "changelog.txt"]);
+        assert_eq!(entries[0].files, 2);
+
+        apply(g, &entries).unwrap();
+        assert!(!g.join("BepInEx").exists());
+        assert!(!g.join("changelog.txt").exists());
+        assert!(g.join("Restaurats.exe").exists());
+        assert!(g.join("UnityPlayer.dll").exists());
+        assert!(g.join("Restaurats_Data").is_dir());
+    }
+}
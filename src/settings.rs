@@ -0,0 +1,78 @@
+//! Choices the user makes in the app, kept in the user data dir. Unlike
+//! `Config.toml` these are written back whenever they change.
+
+use crate::cache;
+use crate::catalog::Channel;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Channel the newest BepInEx build is taken from.
+    #[serde(default)]
+    pub bepinex_channel: Channel,
+    /// URL of a BepInEx build to use instead of the newest in the channel.
+    #[serde(default)]
+    pub pinned_bepinex: Option<String>,
+    /// Size the download cache is pruned to, in MB.
+    #[serde(default = "default_cache_limit")]
+    pub cache_limit_mb: u64,
+    /// Never use the network; installs come from the download cache.
+    #[serde(default)]
+    pub offline: bool,
+    /// Look for mod updates when the app starts.
+    #[serde(default = "default_true")]
+    pub check_updates_on_startup: bool,
+    /// Mods "Update all" leaves alone, by id.
+    #[serde(default)]
+    pub pinned_mods: BTreeSet<String>,
+    /// Update the user chose to skip, by mod id; newer versions are offered.
+    #[serde(default)]
+    pub skipped_updates: BTreeMap<String, String>,
+}
+
+fn default_cache_limit() -> u64 {
+    cache::DEFAULT_LIMIT_MB
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            bepinex_channel: Channel::default(),
+            pinned_bepinex: None,
+            cache_limit_mb: default_cache_limit(),
+            offline: false,
+            check_updates_on_startup: true,
+            pinned_mods: BTreeSet::new(),
+            skipped_updates: BTreeMap::new(),
+        }
+    }
+}
+
+pub fn default_path() -> PathBuf {
+    crate::config::data_dir().join("settings.json")
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
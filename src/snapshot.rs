@@ -0,0 +1,265 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+use zip::read::ZipArchive;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// Top-level entries of the game folder that belong to the BepInEx setup.
+pub const SNAPSHOT_ENTRIES: [&str; 3] = ["BepInEx", "winhttp.dll", "doorstop_config.ini"];
+
+const STAGING_DIR: &str = ".mod-manager-restore";
+/// Under the staging folder, where the current entries wait until the
+/// restore has succeeded.
+const ASIDE_DIR: &str = ".current";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotMeta {
+    pub id: String,
+    pub label: String,
+    pub game_dir: String,
+    pub created: u64,
+    /// Compressed archive size in bytes.
+    pub size: u64,
+    pub files: usize,
+    /// Top-level entries the snapshot covers; older snapshots cover
+    /// [`SNAPSHOT_ENTRIES`].
+    #[serde(default)]
+    pub entries: Vec<String>,
+}
+
+impl SnapshotMeta {
+    fn covered(&self) -> Vec<String> {
+        if self.entries.is_empty() {
+            SNAPSHOT_ENTRIES.iter().map(|e| e.to_string()).collect()
+        } else {
+            self.entries.clone()
+        }
+    }
+}
+
+pub fn default_root() -> PathBuf {
+    crate::config::data_dir().join("snapshots")
+}
+
+fn archive_path(root: &Path, id: &str) -> PathBuf {
+    root.join(format!("{id}.zip"))
+}
+
+fn meta_path(root: &Path, id: &str) -> PathBuf {
+    root.join(format!("{id}.json"))
+}
+
+/// Compress the BepInEx setup of `game_dir` into the snapshot store.
+pub fn create(root: &Path, game_dir: &Path, label: &str) -> Result<SnapshotMeta> {
+    create_with(root, game_dir, label, &SNAPSHOT_ENTRIES)
+}
+
+/// Like [`create`], for an explicit list of top-level entries.
+pub fn create_with(
+    root: &Path,
+    game_dir: &Path,
+    label: &str,
+    entries: &[&str],
+) -> Result<SnapshotMeta> {
+    fs::create_dir_all(root)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let mut id = now.as_millis().to_string();
+    while archive_path(root, &id).exists() {
+        id.push('0');
+    }
+
+    let part = root.join(format!("{id}.zip.part"));
+    let mut zw = ZipWriter::new(File::create(&part)?);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut files = 0;
+    for top in entries {
+        let top_path = game_dir.join(top);
+        if !top_path.exists() {
+            continue;
+        }
+        for entry in WalkDir::new(&top_path).sort_by_file_name() {
+            let entry = entry?;
+            let rel = entry
+                .path()
+                .strip_prefix(game_dir)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            if entry.file_type().is_dir() {
+                zw.add_directory(format!("{rel}/"), options)?;
+            } else {
+                zw.start_file(rel, options)?;
+                io::copy(&mut File::open(entry.path())?, &mut zw)?;
+                files += 1;
+            }
+        }
+    }
+    zw.finish()?;
+    fs::rename(&part, archive_path(root, &id))?;
+
+    let meta = SnapshotMeta {
+        id: id.clone(),
+        label: label.to_string(),
+        game_dir: game_dir.display().to_string(),
+        created: now.as_secs(),
+        size: fs::metadata(archive_path(root, &id))?.len(),
+        files,
+        entries: entries.iter().map(|e| e.to_string()).collect(),
+    };
+    fs::write(meta_path(root, &id), serde_json::to_string_pretty(&meta)?)?;
+    Ok(meta)
+}
+
+/// Snapshots taken of `game_dir`, newest first.
+pub fn list(root: &Path, game_dir: &Path) -> Vec<SnapshotMeta> {
+    let key = game_dir.display().to_string();
+    let mut out: Vec<SnapshotMeta> = fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+        .filter_map(|e| fs::read_to_string(e.path()).ok())
+        .filter_map(|s| serde_json::from_str::<SnapshotMeta>(&s).ok())
+        .filter(|m| m.game_dir == key && archive_path(root, &m.id).exists())
+        .collect();
+    out.sort_by(|a, b| b.created.cmp(&a.created).then(b.id.cmp(&a.id)));
+    out
+}
+
+pub fn delete(root: &Path, id: &str) -> Result<()> {
+    let _ = fs::remove_file(meta_path(root, id));
+    fs::remove_file(archive_path(root, id))?;
+    Ok(())
+}
+
+/// Put the game folder's BepInEx setup back exactly as it was when the
+/// snapshot was taken. The archive is staged first and the swap is rolled
+/// back on failure, so the game is never left half-restored.
+pub fn restore(root: &Path, game_dir: &Path, id: &str) -> Result<()> {
+    let meta: SnapshotMeta = serde_json::from_str(&fs::read_to_string(meta_path(root, id))?)?;
+    let archive = archive_path(root, id);
+    let mut zip = ZipArchive::new(File::open(&archive)?)?;
+
+    let staging = game_dir.join(STAGING_DIR);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    let staged = (|| -> Result<()> {
+        for i in 0..zip.len() {
+            let mut f = zip.by_index(i)?;
+            let rel = f
+                .enclosed_name()
+                .ok_or_else(|| anyhow!("Unsafe path in snapshot: {}", f.name()))?;
+            let out = staging.join(rel);
+            if f.is_dir() {
+                fs::create_dir_all(&out)?;
+            } else {
+                if let Some(parent) = out.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut f, &mut File::create(&out)?)?;
+            }
+        }
+        Ok(())
+    })();
+    if let Err(e) = staged {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    // Move the current entries aside before putting the staged ones in, so
+    // a failure (e.g. a DLL locked by the running game) can be rolled back
+    // instead of leaving a mix of both setups.
+    let aside = staging.join(ASIDE_DIR);
+    fs::create_dir_all(&aside)?;
+    let mut moved = Vec::new();
+    let mut placed = Vec::new();
+    let swapped = (|| -> Result<()> {
+        for top in meta.covered() {
+            let current = game_dir.join(&top);
+            if current.exists() {
+                fs::rename(&current, aside.join(&top))?;
+                moved.push(top.clone());
+            }
+        }
+        for top in meta.covered() {
+            let from = staging.join(&top);
+            if from.exists() {
+                fs::rename(&from, game_dir.join(&top))?;
+                placed.push(top.clone());
+            }
+        }
+        Ok(())
+    })();
+    if let Err(e) = swapped {
+        for top in &placed {
+            let _ = fs::rename(game_dir.join(top), staging.join(top));
+        }
+        for top in &moved {
+            let _ = fs::rename(aside.join(top), game_dir.join(top));
+        }
+        let _ = fs::remove_dir_all(&staging);
+        return Err(anyhow!("Restore failed, the current setup was kept: {e}"));
+    }
+    fs::remove_dir_all(&staging)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_restore_roundtrip() {
+        let root = tempfile::tempdir().unwrap();
+        let game = tempfile::tempdir().unwrap();
+        let game_dir = game.path();
+        fs::create_dir_all(game_dir.join("BepInEx/plugins/Empty")).unwrap();
+        fs::create_dir_all(game_dir.join("BepInEx/config")).unwrap();
+        fs::write(game_dir.join("BepInEx/config/BepInEx.cfg"), b"[Logging]\n").unwrap();
+        fs::write(game_dir.join("winhttp.dll"), b"proxy").unwrap();
+        fs::write(game_dir.join("Restaurats.exe"), b"game").unwrap();
+
+        let meta = create(root.path(), game_dir, "before test").unwrap();
+        assert_eq!(meta.files, 2);
+        assert_eq!(list(root.path(), game_dir).len(), 1);
+
+        fs::write(game_dir.join("BepInEx/config/BepInEx.cfg"), b"changed").unwrap();
+        fs::write(game_dir.join("BepInEx/plugins/new.dll"), b"x").unwrap();
+        fs::write(game_dir.join("doorstop_config.ini"), b"x").unwrap();
+
+        restore(root.path(), game_dir, &meta.id).unwrap();
+        assert_eq!(
+            fs::read(game_dir.join("BepInEx/config/BepInEx.cfg")).unwrap(),
+            b"[Logging]\n"
+        );
+        assert!(!game_dir.join("BepInEx/plugins/new.dll").exists());
+        assert!(!game_dir.join("doorstop_config.ini").exists());
+        assert!(game_dir.join("BepInEx/plugins/Empty").is_dir());
+        assert!(game_dir.join("Restaurats.exe").exists());
+        assert!(!game_dir.join(STAGING_DIR).exists());
+
+        // When an entry can't be swapped in, the ones already moved go back.
+        // Here winhttp.dll can't be moved aside onto a folder the archive
+        // planted, after BepInEx/ already was.
+        let mut zw = ZipWriter::new(File::create(archive_path(root.path(), &meta.id)).unwrap());
+        zw.start_file(
+            format!("{ASIDE_DIR}/winhttp.dll/x"),
+            SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zw.finish().unwrap();
+        let err = restore(root.path(), game_dir, &meta.id).unwrap_err();
+        assert!(err.to_string().contains("current setup was kept"), "{err}");
+        assert_eq!(
+            fs::read(game_dir.join("BepInEx/config/BepInEx.cfg")).unwrap(),
+            b"[Logging]\n"
+        );
+        assert_eq!(fs::read(game_dir.join("winhttp.dll")).unwrap(), b"proxy");
+        assert!(!game_dir.join(STAGING_DIR).exists());
+    }
+}
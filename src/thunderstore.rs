@@ -0,0 +1,335 @@
+use crate::cache::{self, Kind, Origin};
+use crate::net::{self, HttpConfig, Transfer};
+use crate::types::{ModDownload, ModSource};
+use crate::verify::Expected;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const BASE_URL: &str = "https://thunderstore.io";
+/// Community the game's packages are listed under.
+pub const COMMUNITY: &str = "restaurats";
+
+/// A Thunderstore package identity, `Namespace-Name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageRef {
+    pub namespace: String,
+    pub name: String,
+}
+
+impl PackageRef {
+    pub fn parse(full_name: &str) -> Option<Self> {
+        let (namespace, name) = full_name.split_once('-')?;
+        if namespace.is_empty() || name.is_empty() || name.contains('-') {
+            return None;
+        }
+        Some(Self {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    /// Parse `Namespace-Name-1.2.3`, the form used by downloads and
+    /// dependency strings.
+    pub fn parse_versioned(s: &str) -> Option<(Self, String)> {
+        let (rest, version) = s.rsplit_once('-')?;
+        let is_version = version.split('.').count() == 3
+            && version
+                .split('.')
+                .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+        if !is_version {
+            return None;
+        }
+        Some((Self::parse(rest)?, version.to_string()))
+    }
+
+    pub fn full_name(&self) -> String {
+        format!("{}-{}", self.namespace, self.name)
+    }
+
+    /// BepInEx itself is installed by the manager, not as a package.
+    pub fn is_bepinex_pack(&self) -> bool {
+        self.name.starts_with("BepInExPack")
+    }
+}
+
+pub fn download_url(base: &str, pkg: &PackageRef, version: &str) -> String {
+    format!(
+        "{base}/package/download/{}/{}/{version}/",
+        pkg.namespace, pkg.name
+    )
+}
+
+/// What to download for installing `pkg` at `version`.
+pub fn mod_download(base: &str, pkg: &PackageRef, version: &str) -> ModDownload {
+    ModDownload {
+        id: pkg.full_name(),
+        source: ModSource::Thunderstore {
+            namespace: pkg.namespace.clone(),
+            name: pkg.name.clone(),
+        },
+        version: Some(version.to_string()),
+        url: download_url(base, pkg, version),
+        expected: Expected::default(),
+    }
+}
+
+/// Local zip of a package version, downloaded into the cache at
+/// `cache_root` on first use.
+pub fn fetch_package(
+    cache_root: &Path,
+    base: &str,
+    pkg: &PackageRef,
+    version: &str,
+    http: &HttpConfig,
+    transfer: &Transfer,
+) -> Result<PathBuf> {
+    let url = download_url(base, pkg, version);
+    // A published package version never changes, so any cached copy will do.
+    if let Some(path) = cache::find_url(cache_root, &url) {
+        return Ok(path);
+    }
+    cache::fetch(
+        cache_root,
+        &url,
+        http,
+        &Expected::default(),
+        transfer,
+        Origin::new(Kind::Mod, pkg.full_name(), Some(version.to_string())),
+    )
+}
+
+/// A package as listed by the v1 package API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub full_name: String,
+    pub owner: String,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub is_deprecated: bool,
+    /// ISO 8601, so it sorts as text.
+    #[serde(default)]
+    pub date_updated: String,
+    /// Newest first.
+    pub versions: Vec<PackageVersion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageVersion {
+    pub version_number: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub icon: String,
+    /// `Namespace-Name-1.2.3` strings.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub downloads: u64,
+}
+
+impl Package {
+    pub fn package_ref(&self) -> PackageRef {
+        PackageRef {
+            namespace: self.owner.clone(),
+            name: self.name.clone(),
+        }
+    }
+
+    pub fn latest(&self) -> Option<&PackageVersion> {
+        self.versions.first()
+    }
+
+    pub fn version(&self, version: &str) -> Option<&PackageVersion> {
+        self.versions.iter().find(|v| v.version_number == version)
+    }
+
+    /// Downloads of every version together.
+    pub fn downloads(&self) -> u64 {
+        self.versions.iter().map(|v| v.downloads).sum()
+    }
+}
+
+pub fn package_list_url(base: &str, community: &str) -> String {
+    format!("{base}/c/{community}/api/v1/package/")
+}
+
+fn version_key(version: &str) -> Vec<u64> {
+    version.split('.').filter_map(|p| p.parse().ok()).collect()
+}
+
+/// Whether `have` is `want` or a later version.
+pub fn satisfies(have: &str, want: &str) -> bool {
+    version_key(have) >= version_key(want)
+}
+
+/// `pkg` at `version` and everything it depends on, dependencies first.
+/// BepInExPack is left out, since the manager installs BepInEx itself. When
+/// dependency strings ask for different versions of a package, the newest
+/// wins, and it is the newest version's dependencies that are installed.
+pub fn resolve(
+    packages: &[Package],
+    pkg: &PackageRef,
+    version: &str,
+) -> Result<Vec<(PackageRef, String)>> {
+    type Chosen<'a> = HashMap<String, (&'a Package, String)>;
+
+    /// Pick the newest version asked for of every package reachable from
+    /// `pkg`, walking again from a package whenever its version goes up.
+    fn choose<'a>(
+        by_name: &HashMap<&str, &'a Package>,
+        pkg: &PackageRef,
+        version: &str,
+        chosen: &mut Chosen<'a>,
+    ) -> Result<()> {
+        if pkg.is_bepinex_pack() {
+            return Ok(());
+        }
+        let full_name = pkg.full_name();
+        if let Some((_, have)) = chosen.get(&full_name)
+            && version_key(version) <= version_key(have)
+        {
+            return Ok(());
+        }
+        let package = *by_name
+            .get(full_name.as_str())
+            .ok_or_else(|| anyhow!("{full_name} is not listed on Thunderstore"))?;
+        chosen.insert(full_name.clone(), (package, version.to_string()));
+        for dep in dependencies(package, version) {
+            let (dep_pkg, dep_version) = PackageRef::parse_versioned(&dep)
+                .ok_or_else(|| anyhow!("{full_name} has an invalid dependency {dep}"))?;
+            choose(by_name, &dep_pkg, &dep_version, chosen)?;
+        }
+        Ok(())
+    }
+
+    /// Append `pkg` after the chosen versions of its dependencies.
+    fn order(
+        chosen: &Chosen<'_>,
+        pkg: &PackageRef,
+        seen: &mut HashSet<String>,
+        out: &mut Vec<(PackageRef, String)>,
+    ) {
+        let full_name = pkg.full_name();
+        let Some((package, version)) = chosen.get(&full_name) else {
+            return;
+        };
+        // Marked before recursing so dependency cycles end here.
+        if !seen.insert(full_name) {
+            return;
+        }
+        for dep in dependencies(package, version) {
+            if let Some((dep_pkg, _)) = PackageRef::parse_versioned(&dep) {
+                order(chosen, &dep_pkg, seen, out);
+            }
+        }
+        out.push((pkg.clone(), version.clone()));
+    }
+
+    fn dependencies(package: &Package, version: &str) -> Vec<String> {
+        package
+            .version(version)
+            .or_else(|| package.latest())
+            .map(|v| v.dependencies.clone())
+            .unwrap_or_default()
+    }
+
+    let by_name: HashMap<&str, &Package> =
+        packages.iter().map(|p| (p.full_name.as_str(), p)).collect();
+    let mut chosen = HashMap::new();
+    choose(&by_name, pkg, version, &mut chosen)?;
+    let mut out = Vec::new();
+    order(&chosen, pkg, &mut HashSet::new(), &mut out);
+    Ok(out)
+}
+
+/// Icon of a package version, kept in `dir` after the first download.
+pub fn fetch_icon(dir: &Path, package: &Package, http: &HttpConfig) -> Result<Vec<u8>> {
+    let version = package
+        .latest()
+        .ok_or_else(|| anyhow!("{} has no versions", package.full_name))?;
+    let path = dir.join(format!(
+        "{}-{}.png",
+        package.full_name, version.version_number
+    ));
+    if let Ok(bytes) = fs::read(&path) {
+        return Ok(bytes);
+    }
+    let bytes = net::download_bytes_blocking(&version.icon, http)?;
+    fs::create_dir_all(dir)?;
+    fs::write(&path, &bytes)?;
+    Ok(bytes)
+}
+
+pub fn default_icon_dir() -> PathBuf {
+    crate::config::data_dir().join("icons")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKAGES: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/thunderstore_packages.json"
+    ));
+
+    #[test]
+    fn test_resolve_dependencies() {
+        let packages: Vec<Package> = serde_json::from_str(PACKAGES).unwrap();
+        let chefs = packages.iter().find(|p| p.name == "FasterChefs").unwrap();
+        assert_eq!(chefs.downloads(), 1500 + 250);
+
+        // FasterChefs 1.1.0 needs RatLib 2.0.0, which needs the config helper
+        // and BepInEx. The older RatLib asked for by the helper is replaced.
+        let order = resolve(&packages, &chefs.package_ref(), "1.1.0").unwrap();
+        let order: Vec<_> = order
+            .iter()
+            .map(|(p, v)| format!("{}-{v}", p.full_name()))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "Cheese-ConfigHelper-0.3.0",
+                "Ratsmith-RatLib-2.0.0",
+                "Ratsmith-FasterChefs-1.1.0"
+            ]
+        );
+
+        // RatLib 1.0.0 is visited first; FasterChefs then asks for 2.0.0,
+        // whose own dependencies must still come along.
+        let mut bundle = chefs.clone();
+        bundle.name = "ChefPack".to_string();
+        bundle.full_name = "Ratsmith-ChefPack".to_string();
+        bundle.versions.truncate(1);
+        bundle.versions[0].version_number = "1.0.0".to_string();
+        bundle.versions[0].dependencies = vec![
+            "Ratsmith-RatLib-1.0.0".to_string(),
+            "Ratsmith-FasterChefs-1.1.0".to_string(),
+        ];
+        let bundle_ref = bundle.package_ref();
+        let mut with_bundle = packages.clone();
+        with_bundle.push(bundle);
+        let order = resolve(&with_bundle, &bundle_ref, "1.0.0").unwrap();
+        let order: Vec<_> = order
+            .iter()
+            .map(|(p, v)| format!("{}-{v}", p.full_name()))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "Cheese-ConfigHelper-0.3.0",
+                "Ratsmith-RatLib-2.0.0",
+                "Ratsmith-FasterChefs-1.1.0",
+                "Ratsmith-ChefPack-1.0.0"
+            ]
+        );
+
+        let missing = PackageRef::parse("Nobody-Gone").unwrap();
+        assert!(resolve(&packages, &missing, "1.0.0").is_err());
+    }
+}
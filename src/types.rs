@@ -1,8 +1,60 @@
+use crate::bepinex::cfg::CfgDocument;
+use crate::bepinex::{BepInExInfo, BepState};
+use crate::cache;
+use crate::catalog::Build;
 use crate::config::Config;
+use crate::configs::{ConfigFile, MergeReport};
+use crate::fixups::Environment;
+use crate::game::GameInfo;
+use crate::mods::PluginScan;
+use crate::net::{HttpConfig, Transfer};
+use crate::packages::{Listing, SearchIndex};
+use crate::r2modman::ProfileScan;
+use crate::reset::ResetEntry;
+use crate::settings::Settings;
+use crate::snapshot::SnapshotMeta;
+use crate::thunderstore::Package;
+use crate::updates::Update;
+use crate::verify::Expected;
+use crate::watcher::GameWatcher;
+use eframe::egui;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 
+/// Where a mod can be fetched from again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ModSource {
+    Thunderstore {
+        namespace: String,
+        name: String,
+    },
+    /// Latest release of an `owner/name` GitHub repo.
+    #[serde(rename = "github")]
+    GitHub {
+        repo: String,
+    },
+    /// A zip downloaded straight from a URL.
+    Url {
+        url: String,
+    },
+}
+
+/// A mod archive to download and install under a known identity, rather than
+/// the one its file name suggests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModDownload {
+    pub id: String,
+    pub source: ModSource,
+    /// Taken from the archive's manifest when not known up front.
+    pub version: Option<String>,
+    pub url: String,
+    pub expected: Expected,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModEntry {
     pub id: String,
@@ -10,32 +62,218 @@ pub struct ModEntry {
     pub version: Option<String>,
     pub source_zip: Option<String>,
     pub installed_files: Vec<String>,
+    #[serde(default)]
+    pub source: Option<ModSource>,
+    /// Archive the mod was installed from, kept in the download cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_sha256: Option<String>,
+    /// Installed with its DLLs renamed to `.dll.old`, the way r2modman
+    /// disables a mod, so BepInEx does not load it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+}
+
+/// A file written by the manager, with its hash at the time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackedFile {
+    pub path: String,
+    pub sha256: String,
+    /// Size in bytes; missing in indexes written before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+/// What the BepInEx install wrote to the game dir.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BepInExInstall {
+    pub version: Option<String>,
+    /// Archive the files came from, kept in the download cache for repairs.
+    pub archive_sha256: String,
+    pub files: Vec<TrackedFile>,
+    pub dirs: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModIndex {
     pub mods: Vec<ModEntry>,
+    #[serde(default)]
+    pub bepinex: Option<BepInExInstall>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Tab {
     GettingStarted,
     Mods,
+    Browse,
+    Config,
+    Snapshots,
+}
+
+/// Result slot filled by a background refresh of the stored Thunderstore
+/// package list, with whether the list changed.
+pub type PackagesTask = Arc<Mutex<Option<Result<(Listing, bool), String>>>>;
+
+/// A mod downloaded for installing, with its archive.
+pub type FetchedMod = (ModDownload, PathBuf);
+
+/// Result slot filled once a batch of mods is downloaded.
+pub type ModDownloadTask = Arc<Mutex<Option<Result<Vec<FetchedMod>, String>>>>;
+
+/// Result slot filled by a background update check: updates found and the
+/// checks that failed.
+pub type UpdateCheckTask = Arc<Mutex<Option<(Vec<Update>, Vec<String>)>>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BrowseSort {
+    #[default]
+    Downloads,
+    Updated,
+    Name,
+}
+
+impl BrowseSort {
+    pub const ALL: [BrowseSort; 3] = [BrowseSort::Downloads, BrowseSort::Updated, BrowseSort::Name];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BrowseSort::Downloads => "Most downloaded",
+            BrowseSort::Updated => "Recently updated",
+            BrowseSort::Name => "Name",
+        }
+    }
+}
+
+/// Icons downloaded but not yet turned into textures, by package full name.
+pub type LoadedIcons = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+/// State of the Browse tab.
+#[derive(Default)]
+pub struct Browse {
+    /// Thunderstore packages for the game, empty until loaded or fetched.
+    pub packages: Vec<Package>,
+    pub index: SearchIndex,
+    /// When Thunderstore last confirmed the list.
+    pub checked: Option<u64>,
+    pub fetch_task: Option<PackagesTask>,
+    pub query: String,
+    pub category: Option<String>,
+    pub sort: BrowseSort,
+    pub show_deprecated: bool,
+    /// Full name of the package shown in the details pane.
+    pub selected: Option<String>,
+    /// Version picked for installing the selected package.
+    pub version: String,
+    pub icons: HashMap<String, egui::TextureHandle>,
+    /// Packages whose icon was asked for, to ask only once.
+    pub icons_requested: HashSet<String>,
+    /// Sends packages to the icon downloader thread.
+    pub icon_queue: Option<Sender<(Package, HttpConfig)>>,
+    pub icons_loaded: LoadedIcons,
+}
+
+/// Result slot filled by a background BepInEx install.
+pub type InstallTask = Arc<Mutex<Option<Result<(), String>>>>;
+
+/// Result slot filled by a background fetch of the BepInEx build catalog.
+pub type CatalogTask = Arc<Mutex<Option<Result<Vec<Build>, String>>>>;
+
+/// A generic background job: its name for the log and a slot for the message
+/// reported when it finishes.
+pub struct Job {
+    pub name: String,
+    pub result: Arc<Mutex<Option<Result<String, String>>>>,
+}
+
+/// A file whose import would replace the installed mods.
+#[derive(Debug, Clone)]
+pub enum PendingImport {
+    Modpack(PathBuf),
+    R2z(PathBuf),
+}
+
+/// Mod awaiting confirmation in the uninstall dialog.
+pub struct PendingUninstall {
+    pub id: String,
+    /// Generated config files linked to the mod.
+    pub settings: Vec<String>,
+    pub delete_settings: bool,
+}
+
+/// A config file loaded into the Config tab for editing.
+pub struct OpenConfig {
+    pub rel: String,
+    pub doc: CfgDocument,
+    pub dirty: bool,
+}
+
+/// State of the "Migrate from r2modman" window while it is open.
+#[derive(Default)]
+pub struct MigrateWizard {
+    pub scan: Option<ProfileScan>,
+    pub error: Option<String>,
+    pub include_configs: bool,
 }
 
 pub struct AppState {
     pub game_dir: PathBuf,
-    pub bep_status: String,
+    pub bep_state: BepState,
     pub mods: ModIndex,
+    pub plugin_scan: PluginScan,
     pub status_log: Vec<String>,
     pub custom_bep_url: String,
     pub is_busy: bool,
     pub logo_texture: Option<Box<dyn std::any::Any>>,
     pub current_tab: Tab,
-    pub bep_ready: bool,
-    pub poller_flag: Option<Arc<Mutex<bool>>>,
-    pub install_task: Option<Arc<Mutex<Option<Result<(), String>>>>>,
+    /// Stops when replaced, e.g. when the game folder changes.
+    pub watcher: Option<GameWatcher>,
+    pub egui_ctx: egui::Context,
+    pub install_task: Option<InstallTask>,
     pub config: Config,
+    pub journal_root: PathBuf,
+    pub last_operation: Option<String>,
+    pub job: Option<Job>,
+    pub snapshot_root: PathBuf,
+    pub snapshots: Vec<SnapshotMeta>,
+    pub snapshot_label: String,
+    /// Download cache of BepInEx builds and mod archives.
+    pub cache_root: PathBuf,
+    pub cache_entries: Vec<cache::Entry>,
+    pub pending_restore: Option<SnapshotMeta>,
+    pub pack_include_archives: bool,
+    pub pack_include_configs: bool,
+    pub migrate: Option<MigrateWizard>,
+    pub config_files: Vec<ConfigFile>,
+    pub open_config: Option<OpenConfig>,
+    pub fixup_env: Environment,
+    /// Current `BepInEx.cfg`, for showing which fixups are on.
+    pub bep_cfg: CfgDocument,
+    /// Shown after an upgrade merged config files, until dismissed.
+    pub merge_reports: Vec<MergeReport>,
+    pub pending_uninstall: Option<PendingUninstall>,
+    pub bep_info: Option<BepInExInfo>,
+    pub game_info: GameInfo,
+    pub settings: Settings,
+    pub settings_path: PathBuf,
+    /// Available BepInEx builds, empty until fetched.
+    pub catalog: Vec<Build>,
+    pub catalog_task: Option<CatalogTask>,
+    /// Download of the running job, if it has one.
+    pub transfer: Option<Transfer>,
+    /// Open uninstall dialog for BepInEx, with its "keep plugins and configs" choice.
+    pub pending_bep_uninstall: Option<bool>,
+    /// File picked for import, while its confirmation is open.
+    pub pending_import: Option<PendingImport>,
+    pub browse: Browse,
+    /// What "Reset to vanilla" would delete, while its dialog is open.
+    pub reset_preview: Option<Vec<ResetEntry>>,
+    /// Mods being downloaded from Thunderstore, GitHub or a URL.
+    pub download_task: Option<ModDownloadTask>,
+    /// Newer versions of installed mods found by the last check.
+    pub updates: Vec<Update>,
+    pub update_task: Option<UpdateCheckTask>,
+    pub updates_checked: Option<u64>,
+    /// URL typed into "Install from URL".
+    pub mod_url: String,
 }
 
 impl Default for AppState {
@@ -44,17 +282,52 @@ impl Default for AppState {
             PathBuf::from(r"C:\Program Files (x86)\Steam\steamapps\common\Restaurats");
         Self {
             game_dir: default_path,
-            bep_status: String::new(),
+            bep_state: BepState::NotInstalled,
             mods: ModIndex::default(),
+            plugin_scan: PluginScan::default(),
             status_log: Vec::new(),
             custom_bep_url: String::new(),
             is_busy: false,
             logo_texture: None,
             current_tab: Tab::GettingStarted,
-            bep_ready: false,
-            poller_flag: None,
+            watcher: None,
+            egui_ctx: egui::Context::default(),
             install_task: None,
             config: Config::default(),
+            journal_root: crate::journal::default_root(),
+            last_operation: None,
+            job: None,
+            snapshot_root: crate::snapshot::default_root(),
+            snapshots: Vec::new(),
+            snapshot_label: String::new(),
+            cache_root: crate::cache::default_root(),
+            cache_entries: Vec::new(),
+            pending_restore: None,
+            pack_include_archives: true,
+            pack_include_configs: true,
+            migrate: None,
+            config_files: Vec::new(),
+            open_config: None,
+            fixup_env: Environment::default(),
+            bep_cfg: CfgDocument::default(),
+            merge_reports: Vec::new(),
+            pending_uninstall: None,
+            bep_info: None,
+            game_info: GameInfo::default(),
+            settings: Settings::load(&crate::settings::default_path()),
+            settings_path: crate::settings::default_path(),
+            catalog: Vec::new(),
+            catalog_task: None,
+            transfer: None,
+            pending_bep_uninstall: None,
+            pending_import: None,
+            browse: Browse::default(),
+            reset_preview: None,
+            download_task: None,
+            updates: Vec::new(),
+            update_task: None,
+            updates_checked: None,
+            mod_url: String::new(),
         }
     }
 }
@@ -0,0 +1,458 @@
+//! Newer versions of installed mods, looked up where each mod came from:
+//! the Thunderstore package list, a GitHub repo's latest release, or the URL
+//! it was downloaded from.
+
+use crate::cache::{self, Kind, Origin};
+use crate::mods;
+use crate::net::{self, HttpConfig};
+use crate::thunderstore::{self, Package, PackageRef};
+use crate::types::{ModDownload, ModEntry, ModSource};
+use crate::verify::{self, Expected};
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::path::Path;
+
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// A newer version of an installed mod.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub download: ModDownload,
+    /// Release notes of the new version, empty if there are none.
+    pub changelog: String,
+}
+
+impl Update {
+    pub fn version(&self) -> &str {
+        self.download.version.as_deref().unwrap_or("")
+    }
+}
+
+/// Where to look for updates.
+pub struct Sources<'a> {
+    /// Package list of the game's Thunderstore community.
+    pub packages: &'a [Package],
+    pub thunderstore: &'a str,
+    pub github: &'a str,
+    /// Downloads from plain URLs are kept here, ready for installing.
+    pub cache_root: &'a Path,
+}
+
+fn semver(version: &str) -> Option<semver::Version> {
+    let version = version.trim().trim_start_matches(['v', 'V']);
+    let split = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, rest) = version.split_at(split);
+    // `1.2` is taken as `1.2.0`.
+    let padded = match core.split('.').count() {
+        1 => format!("{core}.0.0{rest}"),
+        2 => format!("{core}.0{rest}"),
+        _ => version.to_string(),
+    };
+    semver::Version::parse(&padded).ok()
+}
+
+fn numbers(version: &str) -> Vec<u64> {
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|n| n.parse().ok())
+        .collect()
+}
+
+/// Order of two versions. Semver when both parse as semver (a leading `v`
+/// and missing minor or patch numbers are allowed), else by the numbers in
+/// them, as for `5.4.23.2`. `None` when there are no numbers to go by.
+pub fn compare(a: &str, b: &str) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (semver(a), semver(b)) {
+        return Some((a.major, a.minor, a.patch, &a.pre).cmp(&(b.major, b.minor, b.patch, &b.pre)));
+    }
+    let (a_numbers, b_numbers) = (numbers(a), numbers(b));
+    if a_numbers.is_empty() || b_numbers.is_empty() {
+        return (a.trim() == b.trim()).then_some(Ordering::Equal);
+    }
+    Some(a_numbers.cmp(&b_numbers))
+}
+
+/// Whether `latest` should replace `current`. Versions that cannot be
+/// ordered count as newer when they differ, and so does anything when the
+/// installed version is unknown.
+pub fn is_newer(latest: &str, current: Option<&str>) -> bool {
+    match current {
+        Some(current) => compare(latest, current).is_none_or(|o| o == Ordering::Greater),
+        None => true,
+    }
+}
+
+#[derive(Deserialize)]
+struct Changelog {
+    markdown: Option<String>,
+}
+
+fn thunderstore_changelog(
+    base: &str,
+    pkg: &PackageRef,
+    version: &str,
+    http: &HttpConfig,
+) -> Result<String> {
+    let url = format!(
+        "{base}/api/experimental/package/{}/{}/{version}/changelog/",
+        pkg.namespace, pkg.name
+    );
+    let json = net::download_bytes_blocking(&url, http)?;
+    let changelog: Changelog = serde_json::from_slice(&json)?;
+    changelog.markdown.ok_or_else(|| anyhow!("no changelog"))
+}
+
+fn check_thunderstore(
+    entry: &ModEntry,
+    pkg: &PackageRef,
+    sources: &Sources,
+    http: &HttpConfig,
+) -> Result<Option<Update>> {
+    let full_name = pkg.full_name();
+    let package = sources
+        .packages
+        .iter()
+        .find(|p| p.full_name == full_name)
+        .ok_or_else(|| anyhow!("{full_name} is not listed on Thunderstore"))?;
+    let Some(latest) = package.latest() else {
+        return Ok(None);
+    };
+    let version = &latest.version_number;
+    if !is_newer(version, entry.version.as_deref()) {
+        return Ok(None);
+    }
+    // Packages without a changelog only have their description to show.
+    let changelog = match http.offline {
+        true => latest.description.clone(),
+        false => thunderstore_changelog(sources.thunderstore, pkg, version, http)
+            .unwrap_or_else(|_| latest.description.clone()),
+    };
+    Ok(Some(Update {
+        download: thunderstore::mod_download(sources.thunderstore, pkg, version),
+        changelog,
+    }))
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    body: Option<String>,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+fn check_github(
+    entry: &ModEntry,
+    repo: &str,
+    sources: &Sources,
+    http: &HttpConfig,
+) -> Result<Option<Update>> {
+    let url = format!("{}/repos/{repo}/releases/latest", sources.github);
+    let release: Release = serde_json::from_slice(&net::download_bytes_blocking(&url, http)?)?;
+    let version = release.tag_name.trim_start_matches(['v', 'V']).to_string();
+    if !is_newer(&version, entry.version.as_deref()) {
+        return Ok(None);
+    }
+    let asset = release
+        .assets
+        .into_iter()
+        .find(|a| a.name.to_ascii_lowercase().ends_with(".zip"))
+        .ok_or_else(|| anyhow!("the {} release of {repo} has no zip", release.tag_name))?;
+    Ok(Some(Update {
+        download: ModDownload {
+            id: entry.id.clone(),
+            source: ModSource::GitHub {
+                repo: repo.to_string(),
+            },
+            version: Some(version),
+            url: asset.browser_download_url,
+            expected: Expected {
+                sha256: asset.digest.filter(|d| d.starts_with("sha256:")),
+                ..Default::default()
+            },
+        },
+        changelog: release.body.unwrap_or_default(),
+    }))
+}
+
+/// A plain URL has no version to ask for, so the archive is downloaded and
+/// compared with the installed one. A changed archive is kept in the cache.
+fn check_url(
+    entry: &ModEntry,
+    url: &str,
+    sources: &Sources,
+    http: &HttpConfig,
+) -> Result<Option<Update>> {
+    let bytes = net::download_bytes_blocking(url, http)?;
+    verify::check(&bytes, &Expected::default())?;
+    let origin = Origin {
+        url: Some(url.to_string()),
+        ..Origin::new(Kind::Mod, entry.id.clone(), None)
+    };
+    let sha256 = cache::put(sources.cache_root, &bytes, origin)?;
+    if entry.archive_sha256.as_deref() == Some(sha256.as_str()) {
+        return Ok(None);
+    }
+    let path = cache::get(sources.cache_root, &sha256)
+        .ok_or_else(|| anyhow!("{url} was not kept in the download cache"))?;
+    let shipped = mods::plan_zip_install(&path)?.version;
+    // A rebuilt archive of the same version is not an update.
+    if let (Some(new), Some(old)) = (&shipped, &entry.version)
+        && compare(new, old).is_some_and(|o| o != Ordering::Greater)
+    {
+        return Ok(None);
+    }
+    let version = shipped.unwrap_or_else(|| format!("build {}", &sha256[..8]));
+    Ok(Some(Update {
+        download: ModDownload {
+            id: entry.id.clone(),
+            source: ModSource::Url {
+                url: url.to_string(),
+            },
+            version: Some(version),
+            url: url.to_string(),
+            expected: Expected::sha256(&sha256),
+        },
+        changelog: String::new(),
+    }))
+}
+
+/// Updates for every mod in `mods` with a known source, and the errors of
+/// the checks that failed. Offline, only the saved Thunderstore package list
+/// is consulted.
+pub fn check_all(
+    mods: &[ModEntry],
+    sources: &Sources,
+    http: &HttpConfig,
+) -> (Vec<Update>, Vec<String>) {
+    let mut updates = Vec::new();
+    let mut errors = Vec::new();
+    for entry in mods {
+        let res = match &entry.source {
+            Some(ModSource::Thunderstore { namespace, name }) => {
+                let pkg = PackageRef {
+                    namespace: namespace.clone(),
+                    name: name.clone(),
+                };
+                check_thunderstore(entry, &pkg, sources, http)
+            }
+            Some(ModSource::GitHub { repo }) if !http.offline => {
+                check_github(entry, repo, sources, http)
+            }
+            Some(ModSource::Url { url }) if !http.offline => check_url(entry, url, sources, http),
+            _ => Ok(None),
+        };
+        match res {
+            Ok(Some(update)) => updates.push(update),
+            Ok(None) => {}
+            Err(e) => errors.push(format!("{}: {e}", entry.name)),
+        }
+    }
+    (updates, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    const PACKAGES: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/thunderstore_packages.json"
+    ));
+
+    fn mod_zip(version: &str) -> Vec<u8> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        zw.start_file("manifest.json", SimpleFileOptions::default())
+            .unwrap();
+        write!(
+            zw,
+            r#"{{"name":"Chairs","version_number":"{version}","website_url":"https://github.com/cheese/chairs"}}"#
+        )
+        .unwrap();
+        zw.start_file("plugins/Chairs.dll", SimpleFileOptions::default())
+            .unwrap();
+        zw.write_all(b"MZ").unwrap();
+        zw.finish().unwrap().into_inner()
+    }
+
+    fn entry(id: &str, version: &str, source: ModSource) -> ModEntry {
+        ModEntry {
+            id: id.into(),
+            name: id.into(),
+            version: Some(version.into()),
+            source: Some(source),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_finds_updates_from_every_source() {
+        assert_eq!(compare("v1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(compare("1.10.0", "1.9.3"), Some(Ordering::Greater));
+        assert_eq!(compare("1.0.0-beta.2", "1.0.0"), Some(Ordering::Less));
+        assert_eq!(compare("5.4.23.2", "5.4.9"), Some(Ordering::Greater));
+        assert_eq!(compare("nightly", "stable"), None);
+        assert!(is_newer("r12", Some("r9")));
+        assert!(is_newer("stable", Some("nightly")));
+        assert!(!is_newer("nightly", Some("nightly")));
+        assert!(!is_newer("1.0.0", Some("1.0.0+build.7")));
+
+        let mut server = mockito::Server::new();
+        let changelog = server
+            .mock(
+                "GET",
+                "/api/experimental/package/Ratsmith/FasterChefs/1.1.0/changelog/",
+            )
+            .with_body(r#"{"markdown":"Chefs no longer drop plates."}"#)
+            .create();
+        let release = server
+            .mock("GET", "/repos/cheese/tables/releases/latest")
+            .with_body(format!(
+                r#"{{"tag_name":"v1.3.0","body":"Round tables.","assets":[
+                    {{"name":"Tables.zip","browser_download_url":"{}/Tables.zip","digest":"sha256:ab"}}]}}"#,
+                server.url()
+            ))
+            .create();
+        let v2 = mod_zip("2.0.0");
+        let direct = server.mock("GET", "/Chairs.zip").with_body(&v2).create();
+        let cache = tempfile::tempdir().unwrap();
+        let packages: Vec<Package> = serde_json::from_str(PACKAGES).unwrap();
+        let sources = Sources {
+            packages: &packages,
+            thunderstore: &server.url(),
+            github: &server.url(),
+            cache_root: cache.path(),
+        };
+        let thunderstore = |name: &str| ModSource::Thunderstore {
+            namespace: "Ratsmith".into(),
+            name: name.into(),
+        };
+        let chairs_url = format!("{}/Chairs.zip", server.url());
+        let mods = [
+            entry("Ratsmith-FasterChefs", "1.0.0", thunderstore("FasterChefs")),
+            entry("Ratsmith-RatLib", "2.0.0", thunderstore("RatLib")),
+            entry(
+                "Tables",
+                "1.2",
+                ModSource::GitHub {
+                    repo: "cheese/tables".into(),
+                },
+            ),
+            entry(
+                "Chairs",
+                "1.0.0",
+                ModSource::Url {
+                    url: chairs_url.clone(),
+                },
+            ),
+            entry("Ratsmith-Gone", "1.0.0", thunderstore("Gone")),
+        ];
+        let (updates, errors) = check_all(&mods, &sources, &HttpConfig::default());
+        changelog.assert();
+        release.assert();
+        direct.assert();
+        let found: Vec<_> = updates
+            .iter()
+            .map(|u| (u.download.id.as_str(), u.version(), u.changelog.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Ratsmith-FasterChefs",
+                    "1.1.0",
+                    "Chefs no longer drop plates."
+                ),
+                ("Tables", "1.3.0", "Round tables."),
+                ("Chairs", "2.0.0", ""),
+            ]
+        );
+        assert_eq!(
+            updates[1].download.expected.sha256.as_deref(),
+            Some("sha256:ab")
+        );
+        // The changed archive is ready in the cache.
+        let chairs = &updates[2].download;
+        let cached = cache::find_url(cache.path(), &chairs_url).unwrap();
+        assert_eq!(std::fs::read(&cached).unwrap(), v2);
+        // Zips that name a GitHub project page can be updated from it.
+        let plan = mods::plan_zip_install(&cached).unwrap();
+        let (_, source) = mods::zip_identity(&cached, &plan);
+        assert_eq!(
+            source,
+            Some(ModSource::GitHub {
+                repo: "cheese/chairs".into()
+            })
+        );
+        assert_eq!(
+            errors,
+            ["Ratsmith-Gone: Ratsmith-Gone is not listed on Thunderstore"]
+        );
+
+        // Installed from that archive, Chairs is up to date.
+        let mut chairs_entry = mods[3].clone();
+        chairs_entry.archive_sha256 = chairs.expected.sha256.clone();
+        let (updates, _) = check_all(&[chairs_entry], &sources, &HttpConfig::default());
+        assert!(updates.is_empty());
+
+        // Offline, only the saved package list is used.
+        let offline = HttpConfig {
+            offline: true,
+            ..HttpConfig::default()
+        };
+        let (updates, errors) = check_all(&mods[..4], &sources, &offline);
+        assert!(errors.is_empty());
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            updates[0].changelog,
+            "Chefs walk and cook quicker during rush hour."
+        );
+
+        // Updating over a version that shipped an extra file removes it.
+        let game = tempfile::tempdir().unwrap();
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in ["plugins/Chairs.dll", "plugins/ChairsLegacy.dll"] {
+            zw.start_file(name, SimpleFileOptions::default()).unwrap();
+            zw.write_all(b"MZ").unwrap();
+        }
+        let v1_path = game.path().join("Chairs-1.0.0.zip");
+        std::fs::write(&v1_path, zw.finish().unwrap().into_inner()).unwrap();
+        let v1_plan = mods::plan_zip_install(&v1_path).unwrap();
+        let installed = mods::apply_zip_install(game.path(), &v1_path, &v1_plan).unwrap();
+        let legacy = installed
+            .installed_files
+            .iter()
+            .find(|f| f.ends_with("ChairsLegacy.dll"))
+            .unwrap()
+            .clone();
+        let dropped = mods::dropped_files(&installed, &plan);
+        assert_eq!(dropped, std::slice::from_ref(&legacy));
+        mods::apply_zip_install(game.path(), &cached, &plan).unwrap();
+        mods::uninstall_files(
+            game.path(),
+            &ModEntry {
+                installed_files: dropped,
+                ..Default::default()
+            },
+        );
+        assert!(!game.path().join(&legacy).exists());
+        assert!(
+            installed
+                .installed_files
+                .iter()
+                .filter(|f| **f != legacy)
+                .all(|f| game.path().join(f).exists())
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Checks a downloaded archive must pass before it is extracted: that it is a
+//! zip at all, and matches a known SHA-256 digest or minisign signature.
+
+use crate::hash;
+use anyhow::{Result, anyhow};
+use minisign_verify::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+
+/// What an archive is expected to be. All fields are optional; an empty
+/// `Expected` only checks that the bytes are a zip.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Expected {
+    /// Hex digest, optionally prefixed with `sha256:` as GitHub reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Detached minisign signature, the contents of the `.minisig` file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Base64 minisign public key (`RW...`) the signature must be made with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+impl Expected {
+    pub fn sha256(digest: &str) -> Self {
+        Self {
+            sha256: Some(digest.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Reject `bytes` unless it is a zip matching everything in `expected`.
+pub fn check(bytes: &[u8], expected: &Expected) -> Result<()> {
+    if !bytes.starts_with(b"PK\x03\x04") && !bytes.starts_with(b"PK\x05\x06") {
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]).to_ascii_lowercase();
+        if head.contains("<html") || head.contains("<!doctype") {
+            return Err(anyhow!(
+                "The server sent a web page instead of a zip (a captive portal, login or error page?)"
+            ));
+        }
+        return Err(anyhow!("The download is not a zip archive"));
+    }
+    if let Some(want) = &expected.sha256 {
+        let want = want
+            .trim()
+            .trim_start_matches("sha256:")
+            .to_ascii_lowercase();
+        let got = hash::sha256_bytes(bytes);
+        if got != want {
+            return Err(anyhow!(
+                "SHA-256 mismatch: expected {want}, got {got}. The mirror or network returned a different file"
+            ));
+        }
+    }
+    match (&expected.signature, &expected.public_key) {
+        (Some(sig), Some(key)) => {
+            let key = PublicKey::from_base64(key.trim())
+                .map_err(|e| anyhow!("Invalid minisign public key: {e}"))?;
+            let sig =
+                Signature::decode(sig).map_err(|e| anyhow!("Invalid minisign signature: {e}"))?;
+            key.verify(bytes, &sig, false)
+                .map_err(|e| anyhow!("Signature check failed: {e}"))?;
+        }
+        (Some(_), None) => return Err(anyhow!("A signature is given but no public key")),
+        (None, Some(_)) => return Err(anyhow!("A public key is given but no signature")),
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_KEY: &str = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCCYIGHz54RaZerHPJSfrDFIs5JT/AKg5Mm7RfB+35r9v7hnjTXSqs5hDp+wG5qPmfaLRk/cv7urzd8UR36jiGgo=
+trusted comment: timestamp:1700000000\tfile:mod.zip
+ND2K0Mkgk7fG8Xz1jK8wrOgZhEWJU0qtB1pG4p00jH+8IBPSTy9RpBsTymJ9NzdHwE/JoG8gDzXhjnwCftnRCA==
+";
+
+    #[test]
+    fn test_rejects_tampered_downloads() {
+        let mut zip = b"PK\x03\x04".to_vec();
+        zip.extend(b"rest of the archive");
+
+        check(&zip, &Expected::default()).unwrap();
+        let digest = format!("sha256:{}", hash::sha256_bytes(&zip).to_uppercase());
+        check(&zip, &Expected::sha256(&digest)).unwrap();
+        let mut tampered = zip.clone();
+        tampered.push(0);
+        assert!(check(&tampered, &Expected::sha256(&digest)).is_err());
+
+        let portal = b"<!DOCTYPE html><html><body>Sign in to Wi-Fi</body></html>";
+        let err = check(portal, &Expected::default()).unwrap_err();
+        assert!(err.to_string().contains("web page"), "{err}");
+
+        let signed = Expected {
+            signature: Some(SIGNATURE.into()),
+            public_key: Some(PUBLIC_KEY.into()),
+            ..Default::default()
+        };
+        check(&zip, &signed).unwrap();
+        assert!(check(&tampered, &signed).is_err());
+        let unsigned = Expected {
+            signature: None,
+            ..signed
+        };
+        assert!(check(&zip, &unsigned).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Watches the game folder so the UI notices BepInEx finishing its first
+//! launch and plugins added or removed outside the manager.
+
+use crate::bepinex;
+use anyhow::Result;
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Changes are batched for this long so a burst of writes is one refresh.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// What kind of files changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// `BepInEx/plugins`.
+    Plugins,
+    /// Files written by the first launch: interop, unity-libs and the log.
+    /// No longer reported once the log shows the chainloader started, since
+    /// the game keeps writing the log while it runs.
+    FirstLaunch,
+    /// Anything else BepInEx installs (core, dotnet, winhttp.dll, ...).
+    Setup,
+}
+
+fn classify(rel: &Path) -> Option<Change> {
+    let rel = rel
+        .to_string_lossy()
+        .replace('\\', "/")
+        .to_ascii_lowercase();
+    let first_launch = [
+        "bepinex/interop",
+        "bepinex/unity-libs",
+        "bepinex/logoutput.log",
+    ];
+    if rel.starts_with("bepinex/plugins") {
+        Some(Change::Plugins)
+    } else if first_launch.iter().any(|p| rel.starts_with(p)) {
+        Some(Change::FirstLaunch)
+    } else if rel == "bepinex"
+        || rel.starts_with("bepinex/core")
+        || rel.starts_with("dotnet")
+        || rel.starts_with("winhttp.dll")
+        || rel.starts_with("doorstop_config.ini")
+    {
+        Some(Change::Setup)
+    } else {
+        None
+    }
+}
+
+fn chainloader_started(game_dir: &Path) -> bool {
+    fs::read(game_dir.join(bepinex::LOG_FILE))
+        .is_ok_and(|log| String::from_utf8_lossy(&log).contains("Chainloader started"))
+}
+
+enum Msg {
+    Fs(notify::Result<Event>),
+    Stop,
+}
+
+/// Background watcher of one game folder. Stops when cancelled or dropped.
+pub struct GameWatcher {
+    stop: Sender<Msg>,
+    changes: Receiver<Change>,
+}
+
+impl GameWatcher {
+    /// Start watching `game_dir`. `wake` is called from the watcher thread
+    /// whenever new changes are ready, e.g. to request a repaint.
+    pub fn start(game_dir: &Path, wake: impl Fn() + Send + 'static) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let fs_tx = tx.clone();
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _ = fs_tx.send(Msg::Fs(res));
+        })?;
+        // The game folder itself is watched shallowly; `_Data` folders are
+        // large and never interesting. `BepInEx/` is watched recursively
+        // once it exists.
+        watcher.watch(game_dir, RecursiveMode::NonRecursive)?;
+        let bep_dir = game_dir.join("BepInEx");
+        let mut bep_watched = watcher.watch(&bep_dir, RecursiveMode::Recursive).is_ok();
+
+        let (changes_tx, changes) = mpsc::channel();
+        let root = game_dir.to_path_buf();
+        thread::spawn(move || {
+            let mut pending = BTreeSet::new();
+            let mut deadline: Option<Instant> = None;
+            let mut launched = chainloader_started(&root);
+            loop {
+                let msg = match deadline {
+                    Some(at) => rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+                    None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match msg {
+                    Ok(Msg::Fs(Ok(event))) => {
+                        for path in &event.paths {
+                            let Some(change) = path.strip_prefix(&root).ok().and_then(classify)
+                            else {
+                                continue;
+                            };
+                            if change == Change::FirstLaunch && launched {
+                                continue;
+                            }
+                            pending.insert(change);
+                            deadline.get_or_insert_with(|| Instant::now() + SETTLE);
+                        }
+                        if !bep_watched && bep_dir.is_dir() {
+                            bep_watched = watcher.watch(&bep_dir, RecursiveMode::Recursive).is_ok();
+                        } else if bep_watched && !bep_dir.exists() {
+                            bep_watched = false;
+                        }
+                    }
+                    Ok(Msg::Fs(Err(_))) => {}
+                    Err(RecvTimeoutError::Timeout) => {
+                        deadline = None;
+                        if pending.contains(&Change::Setup) {
+                            launched = false;
+                        }
+                        if pending.contains(&Change::FirstLaunch) {
+                            launched = chainloader_started(&root);
+                        }
+                        for change in std::mem::take(&mut pending) {
+                            if changes_tx.send(change).is_err() {
+                                return;
+                            }
+                        }
+                        wake();
+                    }
+                    Ok(Msg::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+
+        Ok(Self { stop: tx, changes })
+    }
+
+    /// Changes seen since the last call, each kind at most once.
+    pub fn take_changes(&self) -> BTreeSet<Change> {
+        self.changes.try_iter().collect()
+    }
+
+    pub fn cancel(&self) {
+        let _ = self.stop.send(Msg::Stop);
+    }
+}
+
+impl Drop for GameWatcher {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait_for(watcher: &GameWatcher, want: Change) -> bool {
+        let until = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < until {
+            if watcher.take_changes().contains(&want) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn test_reports_plugin_and_setup_changes() {
+        let game = tempfile::tempdir().unwrap();
+        let g = game.path();
+        let watcher = GameWatcher::start(g, || {}).unwrap();
+
+        // BepInEx/ appears after the watcher started.
+        fs::create_dir_all(g.join("BepInEx/core")).unwrap();
+        assert!(wait_for(&watcher, Change::Setup));
+        fs::create_dir_all(g.join("BepInEx/plugins")).unwrap();
+        thread::sleep(SETTLE);
+        fs::write(g.join("BepInEx/plugins/Hand.dll"), b"x").unwrap();
+        assert!(wait_for(&watcher, Change::Plugins));
+
+        let log = g.join(bepinex::LOG_FILE);
+        fs::write(&log, "[Message:   BepInEx] BepInEx 6.0.0\n").unwrap();
+        assert!(wait_for(&watcher, Change::FirstLaunch));
+        fs::write(&log, "[Message:   BepInEx] Chainloader started\n").unwrap();
+        assert!(wait_for(&watcher, Change::FirstLaunch));
+        // The running game keeps logging; that is not news.
+        fs::write(
+            &log,
+            "[Message:   BepInEx] Chainloader started\n[Info] tick\n",
+        )
+        .unwrap();
+        thread::sleep(SETTLE * 2);
+        assert!(!watcher.take_changes().contains(&Change::FirstLaunch));
+
+        watcher.cancel();
+        fs::write(g.join("BepInEx/plugins/Later.dll"), b"x").unwrap();
+        thread::sleep(SETTLE * 2);
+        assert!(watcher.take_changes().is_empty());
+    }
+}